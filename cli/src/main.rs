@@ -1,17 +1,25 @@
 use clap::{Parser, Subcommand};
 use scorg_lib::{
-    database::{Dao, SqliteDao},
+    config::{self, open_db},
+    database::{Dao, Order, QueryOptions, SqliteDao},
+    doctor::Doctor,
+    errors::Error,
     importer::Importer,
-    models::{SafmedScore, Student},
-    services::{SafmedScoreService, StudentService},
+    models::{Card, Deck, Group, Role, SafmedScore, Student},
+    services::{DeckService, GroupService, SafmedScoreService, StudentService, UserService},
     useful::*,
 };
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
+use std::collections::HashMap;
+use std::io::stdin;
 use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// Database file to use, overrides SCORG_DB and the config file
+    #[arg(long, global = true)]
+    db: Option<String>,
     #[command(subcommand)]
     action: Actions,
 }
@@ -21,19 +29,283 @@ enum Actions {
     AddStudent {
         name: String,
         date_of_birth: String,
+        /// The school's unique pupil number
+        #[arg(long)]
+        upn: Option<String>,
+        /// Year group or class
+        #[arg(long)]
+        class: Option<String>,
+        #[arg(long)]
+        notes: Option<String>,
+        #[arg(long)]
+        start_date: Option<String>,
+        #[arg(long)]
+        leave_date: Option<String>,
+        /// Add the student as not currently taking part
+        #[arg(long)]
+        inactive: bool,
     },
     AddSafmed {
         name: String,
         correct: i32,
         incorrect: i32,
         date: Option<String>,
+        /// The name of the deck, the general deck if not given
+        #[arg(long)]
+        deck: Option<String>,
+        /// How many seconds the timing lasted
+        #[arg(long, default_value_t = 60)]
+        duration: i32,
+        /// Fronts of the cards the student got wrong, separated by commas
+        #[arg(long, value_delimiter = ',')]
+        missed: Vec<String>,
+        /// Fronts of the cards the student got right, separated by commas
+        #[arg(long, value_delimiter = ',')]
+        got: Vec<String>,
+        /// Replace this timing of the day instead of adding another
+        #[arg(long)]
+        seq: Option<i32>,
     },
-    Delete {
+    /// List every timing a student has had, not just one a day
+    Timings {
+        name: String,
+        #[arg(long)]
+        date: Option<String>,
+        /// The name of the deck, all decks if not given
+        #[arg(long)]
+        deck: Option<String>,
+    },
+    /// Change one of the day's timings, see timings for their numbers
+    EditTiming {
+        name: String,
+        date: String,
+        seq: i32,
+        /// The name of the deck, the general deck if not given
+        #[arg(long)]
+        deck: Option<String>,
+        #[arg(long)]
+        correct: Option<i32>,
+        #[arg(long)]
+        incorrect: Option<i32>,
+        #[arg(long)]
+        duration: Option<i32>,
+    },
+    DeleteTiming {
+        name: String,
+        date: String,
+        seq: i32,
+        /// The name of the deck, the general deck if not given
+        #[arg(long)]
+        deck: Option<String>,
+    },
+    /// List the cards a student gets wrong most often
+    Missed {
+        name: String,
+        /// From 30 days ago unless given
+        #[arg(long)]
+        from: Option<String>,
+        /// Up to today unless given
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Hide a student without losing anything, use purge to delete them
+    #[command(alias = "delete")]
+    Archive {
+        name: String,
+    },
+    Unarchive {
         name: String,
     },
-    All,
+    /// List the archived students
+    Archived,
+    /// Delete an archived student and all of their scores for good
+    Purge {
+        name: String,
+    },
+    All {
+        #[arg(long)]
+        sort: Option<String>,
+        #[arg(long)]
+        desc: bool,
+        #[arg(long)]
+        limit: Option<usize>,
+        #[arg(long)]
+        offset: Option<usize>,
+    },
+    /// Find students by name, date of birth, upn, class or notes, best match first
+    Search {
+        text: String,
+    },
     Import {
         filepath: String,
+        /// Show what would be added without saving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show every change made to a student and their scores, and who made it
+    History {
+        name: String,
+    },
+    /// Save a snapshot of the database
+    Backup,
+    /// Restore a snapshot, or list the snapshots if none is given
+    Restore {
+        snapshot: Option<String>,
+    },
+    /// Look for orphaned scores, bad dates and duplicate students
+    Doctor {
+        /// Fix what can be fixed, after taking a snapshot
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Manage classes and intervention groups
+    Group {
+        #[command(subcommand)]
+        action: GroupActions,
+    },
+    /// Manage the SAFMEDS decks students are timed on
+    Deck {
+        #[command(subcommand)]
+        action: DeckActions,
+    },
+    /// Manage who can log in to the app and the students they look after
+    User {
+        #[command(subcommand)]
+        action: UserActions,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum GroupActions {
+    Add {
+        name: String,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    List,
+    Edit {
+        group: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Delete a group and its history, its students are kept
+    Delete {
+        group: String,
+    },
+    /// Put a student in a group, from today unless a date is given
+    Join {
+        group: String,
+        student: String,
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Take a student out of a group, from today unless a date is given
+    Leave {
+        group: String,
+        student: String,
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// List the students in a group now
+    Members {
+        group: String,
+    },
+    /// Show everyone who has joined or left a group
+    History {
+        group: String,
+    },
+    /// Show each member's latest score
+    Scores {
+        group: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DeckActions {
+    Add {
+        name: String,
+        #[arg(long, default_value = "")]
+        subject: String,
+        /// How many cards are in the deck
+        #[arg(long, default_value_t = 0)]
+        cards: i32,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    List,
+    Edit {
+        deck: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        subject: Option<String>,
+        #[arg(long)]
+        cards: Option<i32>,
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Delete a deck, as long as nobody has scores on it
+    Delete {
+        deck: String,
+    },
+    /// List the cards in a deck
+    Cards {
+        deck: String,
+    },
+    AddCard {
+        deck: String,
+        front: String,
+        #[arg(default_value = "")]
+        back: String,
+        /// Labels for the card, separated by commas
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+    },
+    /// Delete a card and everyone's results on it
+    DeleteCard {
+        deck: String,
+        front: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum UserActions {
+    /// Add a user, reading their password from stdin
+    Add {
+        username: String,
+        /// admin, teacher or viewer
+        #[arg(long, default_value = "teacher")]
+        role: String,
+    },
+    List,
+    Delete {
+        username: String,
+    },
+    /// Change a user's password, reading it from stdin
+    Passwd {
+        username: String,
+    },
+    /// Change what a user can do, to admin, teacher or viewer
+    Role {
+        username: String,
+        role: String,
+    },
+    /// Let a teacher or viewer see a student
+    Assign {
+        username: String,
+        student: String,
+    },
+    Unassign {
+        username: String,
+        student: String,
+    },
+    /// List the students assigned to a user
+    Students {
+        username: String,
     },
 }
 
@@ -48,31 +320,74 @@ fn main() -> CliResult {
     )
     .expect("failed to init logger");
 
-    let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new());
+    let args = Args::parse();
+    let sqlite = match open_db(args.db.as_deref()) {
+        Ok(sqlite) => Arc::new(sqlite),
+        Err(e) => return Err(e.to_string()),
+    };
+    // restoring comes before init so a database that won't migrate can still
+    // be rolled back
+    if let Actions::Restore { snapshot } = &args.action {
+        return restore(&sqlite, snapshot.as_deref());
+    }
+    let dao: Arc<dyn Dao> = sqlite;
+    if let Err(e) = dao.init() {
+        return Err(e.to_string());
+    }
+    let policy = match config::Config::load() {
+        Ok(config) => config.day_policy.unwrap_or_default(),
+        Err(e) => return Err(e.to_string()),
+    };
     let students = Arc::new(StudentService::new(Arc::clone(&dao)));
-    let scores = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
-    students.init().unwrap();
-    scores.init().unwrap();
+    let scores = Arc::new(SafmedScoreService::new(Arc::clone(&dao)).with_policy(policy));
 
-    let args = Args::parse();
     match args.action {
-        Actions::All => students
-            .all()
+        Actions::All {
+            sort,
+            desc,
+            limit,
+            offset,
+        } => students
+            .list(&list_options(sort, desc, limit, offset))
             .and_then(|st| {
                 println!("{st:#?}");
                 Ok(())
             })
             .or_else(|e| Err(e.to_string())),
+        Actions::Search { text } => match students.search(&text) {
+            Ok(found) => {
+                println!("{found:#?}");
+                Ok(())
+            }
+            Err(e) => Err(e.to_string()),
+        },
         Actions::AddStudent {
             name,
             date_of_birth,
+            upn,
+            class,
+            notes,
+            start_date,
+            leave_date,
+            inactive,
         } => {
             let (first_names, last_name) = parse_name(&name);
-            match Student::new(&first_names, &last_name, &date_of_birth) {
+            let student = Student::new(&first_names, &last_name, &date_of_birth).and_then(|s| {
+                Ok(Student {
+                    upn,
+                    class,
+                    notes: notes.unwrap_or_default(),
+                    start_date: start_date.as_deref().map(date_from_str).transpose()?,
+                    leave_date: leave_date.as_deref().map(date_from_str).transpose()?,
+                    active: !inactive,
+                    ..s
+                })
+            });
+            match student {
                 Ok(new) => students
                     .add_student(&new)
                     .and_then(|_| Ok(()))
-                    .or_else(|e| Err(e.to_string())),
+                    .map_err(describe),
                 Err(e) => Err(e.to_string()),
             }
         }
@@ -81,48 +396,565 @@ fn main() -> CliResult {
             correct,
             incorrect,
             date,
+            deck,
+            duration,
+            missed,
+            got,
+            seq,
         } => {
-            let (first_names, last_name) = parse_name(&name);
-            let id = match students.get_id_for_name(&first_names, &last_name) {
-                Ok(id) => id,
-                Err(_) => return Err("student doesn't exist".into()),
-            };
+            let id = student_id(&students, &name)?;
             let date = match date {
                 Some(date) => date,
                 None => curr_date_str(),
             };
-            match SafmedScore::new(&id, correct, incorrect, &date) {
-                Ok(score) => scores
+            let decks = DeckService::new(Arc::clone(&dao));
+            let deck_id = match deck {
+                Some(deck) => decks.get_by_name(&deck).map_err(describe)?.id,
+                None => Deck::general().id,
+            };
+            let score = match SafmedScore::new(&id, correct, incorrect, &date) {
+                Ok(score) => SafmedScore {
+                    deck_id,
+                    duration,
+                    seq: seq.unwrap_or_default(),
+                    ..score
+                },
+                Err(e) => return Err(e.to_string()),
+            };
+            if missed.is_empty() && got.is_empty() {
+                return scores
                     .add_score(&score)
                     .and_then(|_| Ok(()))
-                    .or_else(|e| Err(e.to_string())),
-                Err(e) => Err(e.to_string()),
+                    .or_else(|e| Err(e.to_string()));
+            }
+            let cards = decks.cards(&score.deck_id).map_err(describe)?;
+            let mut outcomes = Vec::new();
+            for (fronts, correct) in [(missed, false), (got, true)] {
+                for front in fronts {
+                    outcomes.push((card_id(&cards, &front)?, correct));
+                }
             }
+            scores
+                .add_score_with_cards(&score, &outcomes)
+                .map(|_| ())
+                .map_err(describe)
         }
-        Actions::Delete { name } => {
-            let (first_names, last_name) = parse_name(&name);
-            let id = match students.get_id_for_name(&first_names, &last_name) {
-                Ok(id) => id,
-                Err(_) => return Err("student doesn't exist".into()),
+        Actions::Missed {
+            name,
+            from,
+            to,
+            limit,
+        } => {
+            let id = student_id(&students, &name)?;
+            let date = |date: Option<String>, days| match date {
+                Some(date) => date_from_str(&date).map_err(|e| e.to_string()),
+                None => prev_date(days).ok_or_else(|| "date out of range".to_string()),
             };
-            match scores.delete_scores(&id) {
-                Ok(_) => students
-                    .delete_student(&id)
-                    .and_then(|_| Ok(()))
-                    .or_else(|e| Err(e.to_string())),
+            let missed = scores
+                .missed_cards(&id, date(from, 30)?, date(to, 0)?, limit)
+                .map_err(describe)?;
+            for missed in missed {
+                println!(
+                    "{}: missed {} of {} times",
+                    missed.card.front, missed.missed, missed.seen
+                );
+            }
+            Ok(())
+        }
+        Actions::Timings { name, date, deck } => {
+            let id = student_id(&students, &name)?;
+            let date = date
+                .as_deref()
+                .map(date_from_str)
+                .transpose()
+                .map_err(|e| e.to_string())?;
+            let decks = DeckService::new(Arc::clone(&dao)).all().map_err(describe)?;
+            let deck_name = |id: &str| {
+                decks
+                    .iter()
+                    .find(|deck| deck.id == id)
+                    .map_or(id.to_string(), |deck| deck.name.clone())
+            };
+            let timings = scores.timings(&id).map_err(describe)?;
+            for timing in timings {
+                if date.is_some_and(|date| date != timing.date)
+                    || deck
+                        .as_ref()
+                        .is_some_and(|deck| *deck != deck_name(&timing.deck_id))
+                {
+                    continue;
+                }
+                println!(
+                    "{} {} {} at {}: {} correct, {} incorrect in {}s",
+                    timing.date,
+                    timing.seq,
+                    deck_name(&timing.deck_id),
+                    timing.timed_at.format("%H:%M"),
+                    timing.correct,
+                    timing.incorrect,
+                    timing.duration
+                );
+            }
+            Ok(())
+        }
+        Actions::EditTiming {
+            name,
+            date,
+            seq,
+            deck,
+            correct,
+            incorrect,
+            duration,
+        } => {
+            let timing = find_timing(&dao, &students, &scores, &name, &date, seq, deck)?;
+            let edited = SafmedScore {
+                correct: correct.unwrap_or(timing.correct),
+                incorrect: incorrect.unwrap_or(timing.incorrect),
+                duration: duration.unwrap_or(timing.duration),
+                ..timing
+            };
+            scores.update_score(&edited).map(|_| ()).map_err(describe)
+        }
+        Actions::DeleteTiming {
+            name,
+            date,
+            seq,
+            deck,
+        } => {
+            let timing = find_timing(&dao, &students, &scores, &name, &date, seq, deck)?;
+            scores.delete_timing(&timing).map(|_| ()).map_err(describe)
+        }
+        Actions::Archive { name } => {
+            let id = student_id(&students, &name)?;
+            match students.archive(&id) {
+                Ok(0) => Err(format!("{name} is already archived")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        Actions::Unarchive { name } => {
+            let id = student_id(&students, &name)?;
+            match students.unarchive(&id) {
+                Ok(0) => Err(format!("{name} isn't archived")),
+                Ok(_) => Ok(()),
                 Err(e) => Err(e.to_string()),
             }
         }
-        Actions::Import { filepath } => {
-            let importer = Importer::new(Arc::clone(&students), Arc::clone(&scores));
-            importer
-                .import(&filepath)
-                .and_then(|_| Ok(()))
-                .or_else(|e| Err(e.to_string()))
+        Actions::Archived => match students.archived() {
+            Ok(archived) => {
+                println!("{:#?}", archived);
+                Ok(())
+            }
+            Err(e) => Err(e.to_string()),
+        },
+        Actions::Purge { name } => {
+            let id = student_id(&students, &name)?;
+            // purge_student refuses active students, rolling back the scores
+            dao.transaction(&mut |_| {
+                scores.delete_scores(&id)?;
+                students.purge_student(&id)?;
+                Ok(())
+            })
+            .or_else(|e| Err(e.to_string()))
+        }
+        Actions::Import { filepath, dry_run } => {
+            let data = std::fs::read_to_string(&filepath).or_else(|e| Err(e.to_string()))?;
+            let importer =
+                Importer::new(Arc::clone(&dao), Arc::clone(&students), Arc::clone(&scores));
+            let (students_added, scores_added) = match dry_run {
+                true => importer.preview(&data),
+                false => importer.import(&data),
+            }
+            .map_err(describe)?;
+            let verb = match dry_run {
+                true => "would add",
+                false => "added",
+            };
+            println!("{verb} {students_added} students and {scores_added} scores");
+            Ok(())
+        }
+        Actions::History { name } => {
+            let id = student_id(&students, &name)?;
+            let history = students.history(&id).or_else(|e| Err(e.to_string()))?;
+            let usernames: HashMap<String, String> = UserService::new(Arc::clone(&dao))
+                .all()
+                .map_err(describe)?
+                .into_iter()
+                .map(|user| (user.id, user.username))
+                .collect();
+            for entry in history {
+                // users who've since been deleted are shown by their id
+                let user = match entry.user_id {
+                    Some(id) => usernames.get(&id).cloned().unwrap_or(id),
+                    None => "-".into(),
+                };
+                println!(
+                    "{} {} {} {} {}: {} -> {}",
+                    datetime_to_str(entry.changed_at),
+                    user,
+                    entry.operation,
+                    entry.table_name,
+                    entry.row_key,
+                    entry.old_value.unwrap_or_else(|| "-".into()),
+                    entry.new_value.unwrap_or_else(|| "-".into()),
+                );
+            }
+            Ok(())
+        }
+        Actions::Backup => match dao.backup("manual") {
+            Ok(Some(path)) => {
+                println!("saved {}", path.display());
+                Ok(())
+            }
+            Ok(None) => Err("this database doesn't keep snapshots".into()),
+            Err(e) => Err(e.to_string()),
+        },
+        Actions::Doctor { repair } => {
+            let doctor = Doctor::new(Arc::clone(&dao));
+            let problems = doctor.check().or_else(|e| Err(e.to_string()))?;
+            for problem in &problems {
+                println!("{problem}");
+            }
+            if !repair {
+                println!("found {} problems", problems.len());
+                return Ok(());
+            }
+            let unfixed = doctor.repair(&problems).or_else(|e| Err(e.to_string()))?;
+            println!(
+                "fixed {} problems, {} left",
+                problems.len() - unfixed.len(),
+                unfixed.len()
+            );
+            Ok(())
+        }
+        Actions::Group { action } => {
+            let groups = GroupService::new(Arc::clone(&dao)).with_policy(policy);
+            group(action, &groups, &students)
+        }
+        Actions::Deck { action } => deck(action, &DeckService::new(Arc::clone(&dao))),
+        Actions::User { action } => {
+            let users = UserService::new(Arc::clone(&dao));
+            user(action, &users, &students)
+        }
+        Actions::Restore { .. } => unreachable!("restore is handled before init"),
+    }
+}
+
+fn restore(sqlite: &SqliteDao, snapshot: Option<&str>) -> CliResult {
+    match snapshot {
+        Some(name) => {
+            let path = sqlite.restore(name).or_else(|e| Err(e.to_string()))?;
+            println!("restored {}", path.display());
+        }
+        None => {
+            let snapshots = sqlite.snapshots().or_else(|e| Err(e.to_string()))?;
+            for path in snapshots {
+                println!("{}", path.file_name().unwrap_or_default().to_string_lossy());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn group(action: GroupActions, groups: &GroupService, students: &StudentService) -> CliResult {
+    let find = |name: &str| groups.get_by_name(name).map_err(describe);
+    let on = |date: Option<String>| match date {
+        Some(date) => date_from_str(&date).map_err(|e| e.to_string()),
+        None => Ok(curr_date()),
+    };
+    match action {
+        GroupActions::Add { name, description } => groups
+            .add_group(&Group::new(&name, &description))
+            .map(|_| ())
+            .map_err(describe),
+        GroupActions::List => {
+            let all = groups.all().map_err(describe)?;
+            println!("{all:#?}");
+            Ok(())
+        }
+        GroupActions::Edit {
+            group,
+            name,
+            description,
+        } => {
+            let old = find(&group)?;
+            let update = Group {
+                name: name.unwrap_or(old.name.clone()),
+                description: description.unwrap_or(old.description.clone()),
+                ..old
+            };
+            groups.update_group(&update).map(|_| ()).map_err(describe)
+        }
+        GroupActions::Delete { group } => {
+            let id = find(&group)?.id;
+            groups.delete_group(&id).map(|_| ()).map_err(describe)
+        }
+        GroupActions::Join {
+            group,
+            student,
+            date,
+        } => {
+            let id = find(&group)?.id;
+            let student_id = student_id(students, &student)?;
+            groups
+                .add_member(&id, &student_id, on(date)?)
+                .map(|_| ())
+                .map_err(describe)
+        }
+        GroupActions::Leave {
+            group,
+            student,
+            date,
+        } => {
+            let id = find(&group)?.id;
+            let student_id = student_id(students, &student)?;
+            match groups.remove_member(&id, &student_id, on(date)?) {
+                Ok(0) => Err(format!("{student} isn't in {group}")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(describe(e)),
+            }
+        }
+        GroupActions::Members { group } => {
+            let members = groups.members(&find(&group)?.id).map_err(describe)?;
+            println!("{members:#?}");
+            Ok(())
+        }
+        GroupActions::History { group } => {
+            let history = groups.history(&find(&group)?.id).map_err(describe)?;
+            println!("{history:#?}");
+            Ok(())
+        }
+        GroupActions::Scores { group } => {
+            let scores = groups.latest_scores(&find(&group)?.id).map_err(describe)?;
+            for member in scores {
+                let name = format!(
+                    "{} {}",
+                    member.student.first_names, member.student.last_name
+                );
+                match member.latest {
+                    Some(score) => println!(
+                        "{name}: {:.1} correct, {:.1} incorrect a minute on {}",
+                        score.correct_per_minute(),
+                        score.incorrect_per_minute(),
+                        date_to_str(score.date)
+                    ),
+                    None => println!("{name}: no scores yet"),
+                }
+            }
+            Ok(())
         }
     }
 }
 
+fn deck(action: DeckActions, decks: &DeckService) -> CliResult {
+    let find = |name: &str| decks.get_by_name(name).map_err(describe);
+    match action {
+        DeckActions::Add {
+            name,
+            subject,
+            cards,
+            description,
+        } => {
+            let deck = Deck::new(&name, &subject, cards, &description).map_err(describe)?;
+            decks.add_deck(&deck).map(|_| ()).map_err(describe)
+        }
+        DeckActions::List => {
+            let all = decks.all().map_err(describe)?;
+            println!("{all:#?}");
+            Ok(())
+        }
+        DeckActions::Edit {
+            deck,
+            name,
+            subject,
+            cards,
+            description,
+        } => {
+            let old = find(&deck)?;
+            let update = Deck {
+                name: name.unwrap_or(old.name.clone()),
+                subject: subject.unwrap_or(old.subject.clone()),
+                card_count: cards.unwrap_or(old.card_count),
+                description: description.unwrap_or(old.description.clone()),
+                ..old
+            };
+            decks.update_deck(&update).map(|_| ()).map_err(describe)
+        }
+        DeckActions::Delete { deck } => {
+            let id = find(&deck)?.id;
+            decks.delete_deck(&id).map(|_| ()).map_err(describe)
+        }
+        DeckActions::Cards { deck } => {
+            let cards = decks.cards(&find(&deck)?.id).map_err(describe)?;
+            println!("{cards:#?}");
+            Ok(())
+        }
+        DeckActions::AddCard {
+            deck,
+            front,
+            back,
+            tags,
+        } => {
+            let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+            let card = Card::new(&find(&deck)?.id, &front, &back, &tags).map_err(describe)?;
+            decks.add_cards(&[card]).map(|_| ()).map_err(describe)
+        }
+        DeckActions::DeleteCard { deck, front } => {
+            let cards = decks.cards(&find(&deck)?.id).map_err(describe)?;
+            let id = card_id(&cards, &front)?;
+            decks.delete_card(&id).map(|_| ()).map_err(describe)
+        }
+    }
+}
+
+fn card_id(cards: &[Card], front: &str) -> Result<String, String> {
+    match cards.iter().find(|card| card.front == front.trim()) {
+        Some(card) => Ok(card.id.clone()),
+        None => Err(format!("the deck has no card {front}")),
+    }
+}
+
+fn user(action: UserActions, users: &UserService, students: &StudentService) -> CliResult {
+    let find = |name: &str| users.get_by_name(name).map_err(describe);
+    let role = |role: &str| Role::try_from(role).map_err(describe);
+    match action {
+        UserActions::Add { username, role: r } => {
+            let role = role(&r)?;
+            users
+                .add_user(&username, &read_password()?, role)
+                .map(|_| ())
+                .map_err(describe)
+        }
+        UserActions::List => {
+            for user in users.all().map_err(describe)? {
+                println!("{} ({})", user.username, user.role.as_str());
+            }
+            Ok(())
+        }
+        UserActions::Delete { username } => {
+            let id = find(&username)?.id;
+            users.delete_user(&id).map(|_| ()).map_err(describe)
+        }
+        UserActions::Passwd { username } => {
+            let id = find(&username)?.id;
+            users
+                .set_password(&id, &read_password()?)
+                .map(|_| ())
+                .map_err(describe)
+        }
+        UserActions::Role { username, role: r } => {
+            let id = find(&username)?.id;
+            users.set_role(&id, role(&r)?).map(|_| ()).map_err(describe)
+        }
+        UserActions::Assign { username, student } => {
+            let id = find(&username)?.id;
+            let student_id = student_id(students, &student)?;
+            users.assign(&student_id, &id).map(|_| ()).map_err(describe)
+        }
+        UserActions::Unassign { username, student } => {
+            let id = find(&username)?.id;
+            let student_id = student_id(students, &student)?;
+            match users.unassign(&student_id, &id) {
+                Ok(0) => Err(format!("{student} isn't assigned to {username}")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(describe(e)),
+            }
+        }
+        UserActions::Students { username } => {
+            let ids = users.assigned(&find(&username)?.id).map_err(describe)?;
+            let assigned = students.get_many(&ids).map_err(describe)?;
+            println!("{assigned:#?}");
+            Ok(())
+        }
+    }
+}
+
+// the first line of stdin, so passwords can be piped in
+fn read_password() -> Result<String, String> {
+    let mut password = String::new();
+    stdin()
+        .read_line(&mut password)
+        .map_err(|e| e.to_string())?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn student_id(students: &StudentService, name: &str) -> Result<String, String> {
+    let (first_names, last_name) = parse_name(name);
+    match students.get_id_for_name(&first_names, &last_name) {
+        Ok(id) => Ok(id),
+        Err(Error::NotFound(_)) => Err("student doesn't exist".into()),
+        Err(e) => Err(describe(e)),
+    }
+}
+
+fn find_timing(
+    dao: &Arc<dyn Dao>,
+    students: &StudentService,
+    scores: &SafmedScoreService,
+    name: &str,
+    date: &str,
+    seq: i32,
+    deck: Option<String>,
+) -> Result<SafmedScore, String> {
+    let id = student_id(students, name)?;
+    let date = date_from_str(date).map_err(|e| e.to_string())?;
+    let deck_id = match deck {
+        Some(deck) => {
+            DeckService::new(Arc::clone(dao))
+                .get_by_name(&deck)
+                .map_err(describe)?
+                .id
+        }
+        None => Deck::general().id,
+    };
+    scores
+        .get_timing(&id, &deck_id, date, seq)
+        .map_err(describe)
+}
+
+// Says what went wrong in terms of what the user asked for, where the error is
+// one they can do something about.
+fn describe(e: Error) -> String {
+    match e {
+        Error::Duplicate { table, columns, .. } if table == "student" && columns == ["upn"] => {
+            "there's already a student with that UPN".into()
+        }
+        Error::Duplicate { table, columns, .. }
+            if table == "student"
+                && columns
+                    .iter()
+                    .any(|c| c == "first_names" || c == "last_name") =>
+        {
+            "there's already a student with that name".into()
+        }
+        Error::Duplicate { table, .. } if table == "groups" => {
+            "there's already a group with that name".into()
+        }
+        Error::Duplicate { table, .. } if table == "deck" => {
+            "there's already a deck with that name".into()
+        }
+        Error::Duplicate { table, .. } if table == "card" => {
+            "there's already a card with that front in the deck".into()
+        }
+        Error::Duplicate { table, .. } if table == "card_outcome" => {
+            "a card was given more than once".into()
+        }
+        Error::Duplicate { table, .. } if table == "users" => {
+            "there's already a user with that username".into()
+        }
+        Error::NotFound(what) => format!("couldn't find {what}"),
+        Error::Csv {
+            line: Some(line),
+            field,
+            source,
+        } => match field {
+            Some(field) => format!("line {line}, field {field}: {}", describe(*source)),
+            None => format!("line {line}: {}", describe(*source)),
+        },
+        e => e.to_string(),
+    }
+}
+
 fn parse_name(name: &str) -> (String, String) {
     let split_name: Vec<&str> = name.split(" ").collect();
     let first_names = split_name[..split_name.len() - 1].join(" ");
@@ -131,3 +963,22 @@ fn parse_name(name: &str) -> (String, String) {
         None => (first_names, String::new()),
     }
 }
+
+fn list_options(
+    sort: Option<String>,
+    desc: bool,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> QueryOptions {
+    let order = match desc {
+        true => Order::Desc,
+        false => Order::Asc,
+    };
+    let mut options = QueryOptions::new();
+    if let Some(field) = sort {
+        options = options.order_by(&field, order);
+    }
+    options.limit = limit;
+    options.offset = offset;
+    options
+}
@@ -0,0 +1,206 @@
+use std::fs::{create_dir_all, read_dir, remove_file};
+use std::path::{Path, PathBuf};
+
+use crate::errors::{Error, Result};
+
+use chrono::Local;
+use log::*;
+use rusqlite::{backup::Progress, Connection, DatabaseName, OpenFlags};
+
+/// Where snapshots of the database are written and how many of them to keep.
+/// Snapshots are named scorg-<timestamp>-<label>.db so sorting them by name
+/// sorts them by age.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backups {
+    pub dir: PathBuf,
+    pub keep: usize,
+}
+
+impl Backups {
+    pub fn new<P: Into<PathBuf>>(dir: P, keep: usize) -> Backups {
+        Backups {
+            dir: dir.into(),
+            keep,
+        }
+    }
+
+    /// Copies the database into a new snapshot using sqlite's online backup,
+    /// checks the copy and then throws away any snapshots beyond keep.
+    pub fn snapshot(&self, conn: &Connection, label: &str) -> Result<PathBuf> {
+        create_dir_all(&self.dir).map_err(|e| backup_error(&self.dir, e))?;
+        let name = format!(
+            "scorg-{}-{label}.db",
+            Local::now().format("%Y%m%d-%H%M%S%3f")
+        );
+        let path = self.dir.join(name);
+        debug!("writing snapshot to {}", path.display());
+        conn.backup(DatabaseName::Main, &path, None)?;
+        if let Err(e) = check_file(&path) {
+            let _ = remove_file(&path);
+            return Err(e);
+        }
+        self.rotate()?;
+        info!("saved snapshot {}", path.display());
+        Ok(path)
+    }
+
+    /// All the snapshots, newest first.
+    pub fn list(&self) -> Result<Vec<PathBuf>> {
+        let entries = match read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(backup_error(&self.dir, e)),
+        };
+        let mut snapshots: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| is_snapshot(path))
+            .collect();
+        snapshots.sort();
+        snapshots.reverse();
+        Ok(snapshots)
+    }
+
+    /// Finds a snapshot by its path or by its file name in the backup dir.
+    pub fn find(&self, name: &str) -> Result<PathBuf> {
+        let path = PathBuf::from(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        let path = self.dir.join(name);
+        match path.is_file() {
+            true => Ok(path),
+            false => Err(Error::BackupError(format!("no snapshot called {name}"))),
+        }
+    }
+
+    /// Replaces everything in the database with the snapshot called name, as
+    /// long as the snapshot passes an integrity check first.
+    pub fn restore(&self, conn: &mut Connection, name: &str) -> Result<PathBuf> {
+        let path = self.find(name)?;
+        check_file(&path)?;
+        info!("restoring snapshot {}", path.display());
+        conn.restore(DatabaseName::Main, &path, None::<fn(Progress)>)?;
+        integrity_check(conn)?;
+        Ok(path)
+    }
+
+    fn rotate(&self) -> Result<()> {
+        for old in self.list()?.into_iter().skip(self.keep) {
+            debug!("removing old snapshot {}", old.display());
+            remove_file(&old).map_err(|e| backup_error(&old, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs PRAGMA integrity_check, erroring with whatever sqlite found wrong.
+pub fn integrity_check(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let problems = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<std::result::Result<Vec<String>, rusqlite::Error>>()?;
+    match problems.as_slice() {
+        [ok] if ok == "ok" => Ok(()),
+        _ => Err(Error::BackupError(format!(
+            "integrity check failed: {}",
+            problems.join("; ")
+        ))),
+    }
+}
+
+fn check_file(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    integrity_check(&conn)
+        .map_err(|e| Error::BackupError(format!("{} is unusable: {e}", path.display())))
+}
+
+fn is_snapshot(path: &Path) -> bool {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.starts_with("scorg-") && name.ends_with(".db"),
+        None => false,
+    }
+}
+
+fn backup_error(path: &Path, e: std::io::Error) -> Error {
+    Error::BackupError(format!("{}: {e}", path.display()))
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+
+    fn temp_backups(keep: usize) -> Backups {
+        let dir = std::env::temp_dir().join(format!("scorg-test-{}", uuid::Uuid::new_v4()));
+        Backups::new(dir, keep)
+    }
+
+    fn db(value: i32) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE test (field1 INTEGER)")
+            .unwrap();
+        conn.execute("INSERT INTO test VALUES (?1)", [value])
+            .unwrap();
+        conn
+    }
+
+    fn value(conn: &Connection) -> i32 {
+        conn.query_row("SELECT field1 FROM test", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let backups = temp_backups(5);
+        let path = backups.snapshot(&db(1), "test").unwrap();
+        assert_eq!(vec![path.clone()], backups.list().unwrap());
+        let mut conn = db(2);
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(Ok(path.clone()), backups.restore(&mut conn, name));
+        assert_eq!(1, value(&conn));
+        std::fs::remove_dir_all(&backups.dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation() {
+        let backups = temp_backups(2);
+        let conn = db(1);
+        let paths: Vec<PathBuf> = (0..4)
+            .map(|i| {
+                // make sure the timestamps differ
+                std::thread::sleep(std::time::Duration::from_millis(2));
+                backups.snapshot(&conn, &i.to_string()).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            vec![paths[3].clone(), paths[2].clone()],
+            backups.list().unwrap()
+        );
+        std::fs::remove_dir_all(&backups.dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_refuses_bad_snapshots() {
+        let backups = temp_backups(2);
+        let mut conn = db(2);
+        assert_matches!(
+            backups.restore(&mut conn, "missing.db"),
+            Err(Error::BackupError(_))
+        );
+        create_dir_all(&backups.dir).unwrap();
+        let bad = backups.dir.join("scorg-bad.db");
+        std::fs::write(&bad, "not a database at all").unwrap();
+        assert!(backups.restore(&mut conn, "scorg-bad.db").is_err());
+        assert_eq!(2, value(&conn));
+        std::fs::remove_dir_all(&backups.dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_without_dir() {
+        assert_eq!(Ok(vec![]), temp_backups(1).list());
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+use crate::backup::Backups;
+use crate::constant::{BACKUP_DIR, CONFIG_FILE, DATA_DIR, DB_ENV_VAR, DB_FILE, KEEP_BACKUPS};
+use crate::database::SqliteDao;
+use crate::errors::{Error, Result};
+use crate::models::DayPolicy;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+/// Settings read from config.json in the scorg data directory.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub db_path: Option<PathBuf>,
+    pub backup_dir: Option<PathBuf>,
+    pub keep_backups: Option<usize>,
+    /// Which of a day's timings charts and summaries use, best, first or
+    /// last.
+    pub day_policy: Option<DayPolicy>,
+}
+
+impl Config {
+    /// Reads the config file from the data directory, giving the default
+    /// Config if there isn't one.
+    pub fn load() -> Result<Config> {
+        match dirs::data_dir() {
+            Some(dir) => Config::from_file(&dir.join(DATA_DIR).join(CONFIG_FILE)),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                debug!("using config from {}", path.display());
+                serde_json::from_str(&contents)
+                    .map_err(|e| Error::ConfigError(format!("{}: {e}", path.display())))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(Error::ConfigError(format!("{}: {e}", path.display()))),
+        }
+    }
+
+    /// Works out which database to use. In order of preference that's db
+    /// (i.e. the --db flag), the SCORG_DB environment variable, the config
+    /// file and finally scorg_data in the data directory.
+    pub fn db_path(&self, db: Option<&str>) -> Result<PathBuf> {
+        let env = std::env::var(DB_ENV_VAR).ok();
+        self.resolve(db, env.as_deref())
+    }
+
+    fn resolve(&self, db: Option<&str>, env: Option<&str>) -> Result<PathBuf> {
+        if let Some(path) = db.or(env).filter(|p| !p.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        match &self.db_path {
+            Some(path) => Ok(path.clone()),
+            None => Ok(data_dir()?.join(DB_FILE)),
+        }
+    }
+
+    /// Where snapshots go, the backups folder in the data directory unless
+    /// the config file says otherwise.
+    pub fn backups(&self) -> Result<Backups> {
+        let dir = match &self.backup_dir {
+            Some(dir) => dir.clone(),
+            None => data_dir()?.join(BACKUP_DIR),
+        };
+        Ok(Backups::new(dir, self.keep_backups.unwrap_or(KEEP_BACKUPS)))
+    }
+}
+
+/// The scorg data directory, created if it isn't there yet.
+pub fn data_dir() -> Result<PathBuf> {
+    let dir = match dirs::data_dir() {
+        Some(dir) => dir.join(DATA_DIR),
+        None => {
+            return Err(Error::ConfigError(
+                "couldn't find a data directory, set SCORG_DB instead".into(),
+            ))
+        }
+    };
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| Error::ConfigError(format!("{}: {e}", dir.display())))?;
+    Ok(dir)
+}
+
+/// Opens the database picked by the config, see Config::db_path, with
+/// snapshots going where Config::backups says.
+pub fn open_db(db: Option<&str>) -> Result<SqliteDao> {
+    let config = Config::load()?;
+    let path = config.db_path(db)?;
+    debug!("using database {}", path.display());
+    Ok(SqliteDao::new(path)?.with_backups(config.backups()?))
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+
+    #[test]
+    fn test_resolve() {
+        let config = Config {
+            db_path: Some("from_config".into()),
+            ..Config::default()
+        };
+        let tests = vec![
+            (Some("from_flag"), Some("from_env"), "from_flag"),
+            (None, Some("from_env"), "from_env"),
+            (None, Some(""), "from_config"),
+            (None, None, "from_config"),
+        ];
+        for (db, env, exp) in tests {
+            assert_eq!(Ok(PathBuf::from(exp)), config.resolve(db, env));
+        }
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = std::env::temp_dir().join("scorg_test_config.json");
+        std::fs::write(&path, r#"{"db_path": "/some/where.db"}"#).unwrap();
+        let config = Config::from_file(&path);
+        std::fs::write(&path, "not json").unwrap();
+        let bad = Config::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Ok(Config {
+                db_path: Some("/some/where.db".into()),
+                ..Config::default()
+            }),
+            config
+        );
+        assert_matches!(bad, Err(Error::ConfigError(_)));
+        assert_eq!(Ok(Config::default()), Config::from_file(&path));
+    }
+
+    #[test]
+    fn test_backups() {
+        let config = Config {
+            backup_dir: Some("/some/backups".into()),
+            keep_backups: Some(3),
+            ..Config::default()
+        };
+        assert_eq!(Ok(Backups::new("/some/backups", 3)), config.backups());
+    }
+
+    #[test]
+    fn test_day_policy() {
+        let config: Config = serde_json::from_str(r#"{"day_policy": "best"}"#).unwrap();
+        assert_eq!(Some(DayPolicy::Best), config.day_policy);
+        assert!(serde_json::from_str::<Config>(r#"{"day_policy": "worst"}"#).is_err());
+    }
+}
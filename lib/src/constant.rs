@@ -8,12 +8,66 @@ pub const STUDENT_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS student (
     UNIQUE(first_names,last_name)
 )";
 
-pub const STUDENT_FIELDS: [&'static str; 4] = [
-    "id",
-    "first_names",
-    "last_name",
-    "date_of_birth",
-];
+pub const ARCHIVE_STUDENTS: &str = "ALTER TABLE student ADD COLUMN archived_at TEXT";
+
+// many students can be without a upn, sqlite never counts NULLs as duplicates
+pub const STUDENT_DETAILS: &str = "ALTER TABLE student ADD COLUMN upn TEXT;
+ALTER TABLE student ADD COLUMN class TEXT;
+ALTER TABLE student ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+ALTER TABLE student ADD COLUMN start_date TEXT;
+ALTER TABLE student ADD COLUMN leave_date TEXT;
+ALTER TABLE student ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
+CREATE UNIQUE INDEX IF NOT EXISTS student_upn ON student (upn);";
+
+pub const GROUP_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS groups (
+    id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT ''
+)";
+
+// a student can be in a group more than once, as long as it's from different
+// dates
+pub const MEMBERSHIP_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS student_group (
+    group_id TEXT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    student_id TEXT NOT NULL REFERENCES student(id) ON DELETE CASCADE,
+    joined_on TEXT NOT NULL,
+    left_on TEXT,
+    PRIMARY KEY (group_id, student_id, joined_on)
+)";
+
+pub const MEMBERSHIP_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS student_group_student ON student_group (student_id)";
+
+pub const USER_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS users (
+    id TEXT NOT NULL PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('admin', 'teacher', 'viewer'))
+)";
+
+pub const ASSIGNMENT_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS student_user (
+    student_id TEXT NOT NULL REFERENCES student(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (student_id, user_id)
+)";
+
+pub const ASSIGNMENT_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS student_user_user ON student_user (user_id)";
+
+// sqlite can't change a foreign key in place so safmed is rebuilt with one
+// that cascades
+pub const CASCADE_SCORES: &str = "CREATE TABLE safmed_new (
+    id TEXT NOT NULL,
+    correct INTEGER NOT NULL,
+    incorrect INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    CONSTRAINT student_id FOREIGN KEY (id) REFERENCES student(id) ON DELETE CASCADE,
+    UNIQUE(id,date)
+);
+INSERT INTO safmed_new (id, correct, incorrect, date)
+    SELECT id, correct, incorrect, date FROM safmed;
+DROP TABLE safmed;
+ALTER TABLE safmed_new RENAME TO safmed;";
 
 pub const SCORE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS safmed (
     id TEXT NOT NULL,
@@ -24,6 +78,157 @@ pub const SCORE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS safmed (
     UNIQUE(id,date)
 )";
 
-pub const SCORE_FIELDS: [&'static str; 4] = ["id", "correct", "incorrect", "date"];
+pub const DECK_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS deck (
+    id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    subject TEXT NOT NULL DEFAULT '',
+    card_count INTEGER NOT NULL DEFAULT 0,
+    description TEXT NOT NULL DEFAULT ''
+);
+INSERT OR IGNORE INTO deck (id, name) VALUES ('general', 'General')";
+
+// the scores there already were are moved to the general deck, and the same
+// day can now have a score on each deck
+pub const DECK_SCORES: &str = "CREATE TABLE safmed_new (
+    id TEXT NOT NULL,
+    correct INTEGER NOT NULL,
+    incorrect INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    deck_id TEXT NOT NULL DEFAULT 'general' REFERENCES deck(id),
+    CONSTRAINT student_id FOREIGN KEY (id) REFERENCES student(id) ON DELETE CASCADE,
+    UNIQUE(id,deck_id,date)
+);
+INSERT INTO safmed_new (id, correct, incorrect, date)
+    SELECT id, correct, incorrect, date FROM safmed;
+DROP TABLE safmed;
+ALTER TABLE safmed_new RENAME TO safmed;";
+
+// existing scores were all one minute timings
+pub const SCORE_DURATION: &str =
+    "ALTER TABLE safmed ADD COLUMN duration INTEGER NOT NULL DEFAULT 60 CHECK (duration > 0)";
+
+// every timing of the day is kept, numbered in the order they were taken.
+// Existing scores become the first timing of their day
+pub const TIMING_SCORES: &str = "CREATE TABLE safmed_new (
+    id TEXT NOT NULL,
+    correct INTEGER NOT NULL,
+    incorrect INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    deck_id TEXT NOT NULL DEFAULT 'general' REFERENCES deck(id),
+    duration INTEGER NOT NULL DEFAULT 60 CHECK (duration > 0),
+    seq INTEGER NOT NULL DEFAULT 1 CHECK (seq > 0),
+    timed_at TEXT NOT NULL,
+    CONSTRAINT student_id FOREIGN KEY (id) REFERENCES student(id) ON DELETE CASCADE,
+    UNIQUE(id,deck_id,date,seq)
+);
+INSERT INTO safmed_new (id, correct, incorrect, date, deck_id, duration, timed_at)
+    SELECT id, correct, incorrect, date, deck_id, duration, date || ' 00:00:00' FROM safmed;
+DROP TABLE safmed;
+ALTER TABLE safmed_new RENAME TO safmed;";
+
+pub const TIMING_CARD_OUTCOMES: &str = "CREATE TABLE card_outcome_new (
+    student_id TEXT NOT NULL REFERENCES student(id) ON DELETE CASCADE,
+    deck_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    seq INTEGER NOT NULL DEFAULT 1,
+    card_id TEXT NOT NULL REFERENCES card(id) ON DELETE CASCADE,
+    correct INTEGER NOT NULL,
+    PRIMARY KEY (student_id, deck_id, date, seq, card_id)
+);
+INSERT INTO card_outcome_new (student_id, deck_id, date, card_id, correct)
+    SELECT student_id, deck_id, date, card_id, correct FROM card_outcome;
+DROP TABLE card_outcome;
+ALTER TABLE card_outcome_new RENAME TO card_outcome;";
+
+pub const CARD_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS card (
+    id TEXT NOT NULL PRIMARY KEY,
+    deck_id TEXT NOT NULL REFERENCES deck(id) ON DELETE CASCADE,
+    front TEXT NOT NULL,
+    back TEXT NOT NULL DEFAULT '',
+    tags TEXT NOT NULL DEFAULT '',
+    UNIQUE(deck_id,front)
+)";
+
+// outcomes belong to the timing with the same student, deck and date, they
+// go with their student or card. Timings are numbered from version 11 on
+pub const CARD_OUTCOME_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS card_outcome (
+    student_id TEXT NOT NULL REFERENCES student(id) ON DELETE CASCADE,
+    deck_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    card_id TEXT NOT NULL REFERENCES card(id) ON DELETE CASCADE,
+    correct INTEGER NOT NULL,
+    PRIMARY KEY (student_id, deck_id, date, card_id)
+)";
+
+pub const CARD_OUTCOME_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS card_outcome_card ON card_outcome (card_id)";
+
+pub const AUDIT_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS audit (
+    table_name TEXT NOT NULL,
+    row_key TEXT NOT NULL,
+    student_id TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    changed_at TEXT NOT NULL
+)";
+
+pub const AUDIT_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS audit_student ON audit (student_id, changed_at)";
+
+// entries from before users came in, or made without logging in, have no user
+pub const AUDIT_USER: &str = "ALTER TABLE audit ADD COLUMN user_id TEXT";
+
+// the words searched for each student, folded by the search_text function
+// scorg adds to its connections, so only scorg can write to student once this
+// is in place. Trigram tokens let a query match part of a word
+pub const STUDENT_SEARCH_SCHEMA: &str = "CREATE VIRTUAL TABLE student_search USING fts5(
+    id UNINDEXED,
+    words,
+    tokenize = 'trigram'
+);
+INSERT INTO student_search (id, words)
+    SELECT id, search_text(first_names || ' ' || last_name || ' ' || date_of_birth || ' '
+        || coalesce(upn, '') || ' ' || coalesce(class, '') || ' ' || notes)
+    FROM student;";
+
+pub const STUDENT_SEARCH_TRIGGERS: &str = "CREATE TRIGGER student_search_insert
+AFTER INSERT ON student BEGIN
+    INSERT INTO student_search (id, words)
+        VALUES (new.id, search_text(new.first_names || ' ' || new.last_name || ' '
+            || new.date_of_birth || ' ' || coalesce(new.upn, '') || ' '
+            || coalesce(new.class, '') || ' ' || new.notes));
+END;
+CREATE TRIGGER student_search_update
+AFTER UPDATE ON student BEGIN
+    DELETE FROM student_search WHERE id = old.id;
+    INSERT INTO student_search (id, words)
+        VALUES (new.id, search_text(new.first_names || ' ' || new.last_name || ' '
+            || new.date_of_birth || ' ' || coalesce(new.upn, '') || ' '
+            || coalesce(new.class, '') || ' ' || new.notes));
+END;
+CREATE TRIGGER student_search_delete
+AFTER DELETE ON student BEGIN
+    DELETE FROM student_search WHERE id = old.id;
+END;";
+
+/// The id of the deck scores are on when no other is given.
+pub const DEFAULT_DECK: &str = "general";
+/// Timings last a minute unless they say otherwise.
+pub const DEFAULT_DURATION: i32 = 60;
+
+pub const DATA_DIR: &str = "scorg";
 
 pub const DB_FILE: &str = "scorg_data";
+
+pub const CONFIG_FILE: &str = "config.json";
+
+pub const BACKUP_DIR: &str = "backups";
+
+pub const KEEP_BACKUPS: usize = 10;
+
+pub const DB_ENV_VAR: &str = "SCORG_DB";
+
+/// The most parameters bound to one statement, SQLite's lowest default limit
+/// so it's safe whichever build is linked.
+pub const MAX_PARAMS: usize = 999;
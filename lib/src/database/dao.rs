@@ -2,13 +2,20 @@ use crate::database::Value;
 use crate::errors::Result;
 #[cfg(test)]
 use mockall::{predicate::*, *};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 #[cfg_attr(test, automock)]
 pub trait Dao: Send + Sync {
     fn init(&self) -> Result<()>;
-    fn select(&self, fields: &Vec<String>, table: &str, wheres: &Vec<Where>)
-        -> Result<Vec<Record>>;
+    fn select(
+        &self,
+        fields: &Vec<String>,
+        table: &str,
+        wheres: &Vec<Where>,
+        options: &QueryOptions,
+    ) -> Result<Vec<Record>>;
     fn insert(
         &self,
         fields: &Vec<String>,
@@ -24,35 +31,129 @@ pub trait Dao: Send + Sync {
         wheres: &Vec<Where>,
     ) -> Result<usize>;
     fn delete(&self, table: &str, wheres: &Vec<Where>) -> Result<usize>;
+    /// Computes `aggregates` over the rows matching `wheres`, one record per
+    /// group. Records hold the `group_by` fields and each aggregate's alias,
+    /// and `having` filters the groups using those same names.
+    fn aggregate(
+        &self,
+        table: &str,
+        aggregates: &Vec<Aggregate>,
+        group_by: &Vec<String>,
+        wheres: &Vec<Where>,
+        having: &Vec<Where>,
+    ) -> Result<Vec<Record>>;
+    /// The rows of table matching `wheres` whose words are like text's, best
+    /// match first. Only tables with a search index can be searched, which is
+    /// just student for now.
+    fn search(
+        &self,
+        fields: &[String],
+        table: &str,
+        text: &str,
+        wheres: &[Where],
+    ) -> Result<Vec<Record>>;
+    /// Runs sql exactly as given, nothing in it is checked so it must never
+    /// be built from user input.
     fn execute(&self, sql: &str) -> Result<()>;
+    /// Runs `f` inside a transaction, committing if it returns Ok and rolling
+    /// back every change it made otherwise. `f` is handed the Dao to use for
+    /// its statements.
+    fn transaction<'a>(&self, f: &mut Transaction<'a>) -> Result<()>;
+    /// Saves a snapshot of the database labelled `label`, giving back where it
+    /// went or None if this Dao doesn't keep snapshots.
+    fn backup(&self, label: &str) -> Result<Option<PathBuf>>;
     // fn fetch(&self, sql: &str) -> Result<Vec<Vec<Value>>>;
 }
 
-#[derive(Clone, PartialEq)]
-pub struct Where {
-    pub(crate) field: String,
-    pub(crate) symbol: Symbol,
-    pub(crate) value: Value,
+/// The body of a transaction, see [`Dao::transaction`].
+pub type Transaction<'a> = dyn FnMut(&dyn Dao) -> Result<()> + 'a;
+
+/// A filter on the rows a query touches. A list of Wheres passed to the Dao is
+/// treated as if it were wrapped in [`Where::and`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Where {
+    Compare {
+        field: String,
+        symbol: Symbol,
+        value: Value,
+    },
+    Between {
+        field: String,
+        low: Value,
+        high: Value,
+    },
+    IsNull {
+        field: String,
+    },
+    And(Vec<Where>),
+    Or(Vec<Where>),
+    Not(Box<Where>),
 }
 
 impl Where {
     pub fn new(field: &str, symbol: Symbol, value: Value) -> Self {
-        Where {
+        Where::Compare {
             field: field.to_string(),
             symbol,
             value,
         }
     }
+
+    pub fn between(field: &str, low: Value, high: Value) -> Self {
+        Where::Between {
+            field: field.to_string(),
+            low,
+            high,
+        }
+    }
+
+    pub fn is_null(field: &str) -> Self {
+        Where::IsNull {
+            field: field.to_string(),
+        }
+    }
+
+    pub fn and(wheres: Vec<Where>) -> Self {
+        Where::And(wheres)
+    }
+
+    pub fn or(wheres: Vec<Where>) -> Self {
+        Where::Or(wheres)
+    }
+
+    /// Every field the filter refers to, however deeply nested.
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Where::Compare { field, .. }
+            | Where::Between { field, .. }
+            | Where::IsNull { field } => {
+                vec![field.as_str()]
+            }
+            Where::And(wheres) | Where::Or(wheres) => {
+                wheres.iter().flat_map(|w| w.fields()).collect()
+            }
+            Where::Not(w) => w.fields(),
+        }
+    }
 }
 
-#[derive(Clone, PartialEq)]
+impl std::ops::Not for Where {
+    type Output = Where;
+
+    fn not(self) -> Where {
+        Where::Not(Box::new(self))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Symbol {
     EQ,
-    //NEQ,
-    //GT,
+    NEQ,
+    GT,
     LT,
-    //GTE,
-    //LTE,
+    GTE,
+    LTE,
+    LIKE,
     IN,
 }
 
@@ -60,15 +161,123 @@ impl Symbol {
     pub fn to_string(&self) -> String {
         let symbol = match self {
             Symbol::EQ => "=",
-            //Symbol::NEQ => "!=",
-            //Symbol::GT => ">",
+            Symbol::NEQ => "!=",
+            Symbol::GT => ">",
             Symbol::LT => "<",
-            //Symbol::GTE => ">=",
-            //Symbol::LTE => "<=",
+            Symbol::GTE => ">=",
+            Symbol::LTE => "<=",
+            Symbol::LIKE => "LIKE",
             Symbol::IN => "IN",
         };
         symbol.to_string()
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub(crate) function: Function,
+    pub(crate) field: String,
+    pub(crate) alias: String,
+}
+
+impl Aggregate {
+    pub fn new(function: Function, field: &str, alias: &str) -> Self {
+        Aggregate {
+            function,
+            field: field.to_string(),
+            alias: alias.to_string(),
+        }
+    }
+
+    /// COUNT(*) of the rows in each group
+    pub fn count(alias: &str) -> Self {
+        Aggregate::new(Function::Count, "*", alias)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let function = match self {
+            Function::Count => "COUNT",
+            Function::Sum => "SUM",
+            Function::Avg => "AVG",
+            Function::Min => "MIN",
+            Function::Max => "MAX",
+        };
+        write!(f, "{function}")
+    }
+}
+
+/// Everything about a select that isn't which rows it matches.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryOptions {
+    pub order_by: Vec<(String, Order)>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub distinct: bool,
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn order_by(mut self, field: &str, order: Order) -> Self {
+        self.order_by.push((field.to_string(), order));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl std::fmt::Display for Order {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Order::Asc => write!(f, "ASC"),
+            Order::Desc => write!(f, "DESC"),
+        }
+    }
+}
+
 pub type Record = HashMap<String, Value>;
+
+/// Whether name can be spliced into sql as is: letters, digits and
+/// underscores, not starting with a digit. Used for aggregate aliases, which
+/// aren't in the schema to be checked against.
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,177 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::database::{Dao, QueryOptions, Record, Symbol, Value, Where};
+use crate::errors::{Error, Result};
+
+/// A model that's stored as one row of a table. The table, its columns and
+/// the conversions both ways are declared once here, so the Dao calls made
+/// for the model can't drift out of step with it.
+pub trait Entity: TryFrom<Record, Error = Error> + Send + Sync {
+    const TABLE: &'static str;
+    /// Every column, in the order [`Entity::to_values`] gives them.
+    const COLUMNS: &'static [&'static str];
+    /// The columns that pick out a single row.
+    const KEY: &'static [&'static str];
+
+    fn to_values(&self) -> Vec<Value>;
+
+    fn columns() -> Vec<String> {
+        Self::COLUMNS.iter().map(|c| c.to_string()).collect()
+    }
+}
+
+/// The value of column in rec converted to T, for writing
+/// `TryFrom<Record>` without a branch per missing column.
+pub fn column<'a, T>(rec: &'a Record, column: &str) -> Result<T>
+where
+    &'a Value: TryInto<T, Error = Error>,
+{
+    match rec.get(column) {
+        Some(v) => TryInto::<T>::try_into(v),
+        None => Err(Error::ValueError(format!("Missing {column}"))),
+    }
+}
+
+/// The basic operations on an Entity's table, built on the Dao.
+pub struct Repository<E: Entity> {
+    dao: Arc<dyn Dao>,
+    entity: PhantomData<fn() -> E>,
+}
+
+impl<E: Entity> Repository<E> {
+    pub fn new(dao: Arc<dyn Dao>) -> Self {
+        Repository {
+            dao,
+            entity: PhantomData,
+        }
+    }
+
+    /// The row with key, given in the order of [`Entity::KEY`].
+    pub fn get(&self, key: &[Value]) -> Result<Option<E>> {
+        let mut found = self.list(&key_wheres::<E>(key)?, &QueryOptions::new())?;
+        Ok(found.pop())
+    }
+
+    pub fn list(&self, wheres: &Vec<Where>, options: &QueryOptions) -> Result<Vec<E>> {
+        let records = self.dao.select(&E::columns(), E::TABLE, wheres, options)?;
+        records.into_iter().map(E::try_from).collect()
+    }
+
+    /// The entities matching wheres whose words are like text's, best match
+    /// first, see [`Dao::search`].
+    pub fn search(&self, text: &str, wheres: &[Where]) -> Result<Vec<E>> {
+        let records = self.dao.search(&E::columns(), E::TABLE, text, wheres)?;
+        records.into_iter().map(E::try_from).collect()
+    }
+
+    /// Adds entities, failing if any of their keys are already taken.
+    pub fn insert(&self, entities: &[E]) -> Result<usize> {
+        self.write(entities, false)
+    }
+
+    /// Adds entities, replacing any rows with the same keys.
+    pub fn upsert(&self, entities: &[E]) -> Result<usize> {
+        self.write(entities, true)
+    }
+
+    pub fn delete(&self, wheres: &Vec<Where>) -> Result<usize> {
+        self.dao.delete(E::TABLE, wheres)
+    }
+
+    fn write(&self, entities: &[E], replace: bool) -> Result<usize> {
+        if entities.is_empty() {
+            return Ok(0);
+        }
+        let args = entities.iter().flat_map(|e| e.to_values()).collect();
+        self.dao.insert(&E::columns(), E::TABLE, args, replace)
+    }
+}
+
+fn key_wheres<E: Entity>(key: &[Value]) -> Result<Vec<Where>> {
+    if key.len() != E::KEY.len() {
+        return Err(Error::FieldArgMismatch);
+    }
+    Ok(E::KEY
+        .iter()
+        .zip(key)
+        .map(|(column, value)| Where::new(column, Symbol::EQ, value.clone()))
+        .collect())
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{MemoryDao, Order};
+    use crate::models::SafmedScore;
+
+    fn repository() -> Repository<SafmedScore> {
+        Repository::new(Arc::new(MemoryDao::new()))
+    }
+
+    fn score(correct: i32, date: &str) -> SafmedScore {
+        SafmedScore::new("st1", correct, 2, date).unwrap()
+    }
+
+    #[test]
+    fn test_column() {
+        let rec = Record::from([("correct".into(), Value::from(5))]);
+        assert_eq!(Ok(5), column::<i32>(&rec, "correct"));
+        assert_eq!(
+            Err(Error::ValueError("Missing date".into())),
+            column::<String>(&rec, "date")
+        );
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let repository = repository();
+        let scores = vec![score(50, "2021-01-01"), score(60, "2021-01-02")];
+        assert_eq!(Ok(2), repository.insert(&scores));
+        assert_eq!(Ok(0), repository.insert(&[]));
+        // the key is already taken
+        assert!(repository.insert(&[score(70, "2021-01-01")]).is_err());
+        assert_eq!(
+            Ok(Some(scores[1].clone())),
+            repository.get(&[
+                "st1".into(),
+                "general".into(),
+                "2021-01-02".into(),
+                0.into()
+            ])
+        );
+        assert_eq!(
+            Ok(None),
+            repository.get(&[
+                "st1".into(),
+                "general".into(),
+                "2021-01-03".into(),
+                0.into()
+            ])
+        );
+        assert_eq!(
+            Err(Error::FieldArgMismatch),
+            repository.get(&["st1".into()])
+        );
+    }
+
+    #[test]
+    fn test_upsert_list_and_delete() {
+        let repository = repository();
+        repository.insert(&[score(50, "2021-01-01")]).unwrap();
+        let scores = vec![score(70, "2021-01-01"), score(60, "2021-01-02")];
+        repository.upsert(&scores).unwrap();
+        let options = QueryOptions::new().order_by("date", Order::Asc);
+        assert_eq!(Ok(scores.clone()), repository.list(&vec![], &options));
+        let wheres = vec![Where::new("correct", Symbol::LT, Value::from(65))];
+        assert_eq!(Ok(1), repository.delete(&wheres));
+        assert_eq!(
+            Ok(vec![scores[0].clone()]),
+            repository.list(&vec![], &options)
+        );
+    }
+}
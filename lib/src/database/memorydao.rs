@@ -0,0 +1,921 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::database::{
+    is_identifier, Aggregate, Dao, Entity, Function, Order, QueryOptions, Record, Symbol,
+    Transaction, Value, Where,
+};
+use crate::errors::{Error, Result, Source};
+use crate::models::{
+    Assignment, AuditEntry, Card, CardOutcome, Deck, Group, Membership, SafmedScore, Student, User,
+};
+use crate::search;
+use crate::useful::{date_to_str, datetime_to_str};
+
+use parking_lot::ReentrantMutex;
+
+#[derive(Debug, Clone)]
+struct Table {
+    name: String,
+    columns: Vec<String>,
+    unique: Vec<Vec<String>>,
+    rows: Vec<Record>,
+}
+
+/// A Dao that keeps everything in memory and forgets it when dropped. It
+/// knows the same tables and unique constraints as the sqlite schema, but
+/// can't run raw sql.
+pub struct MemoryDao {
+    tables: ReentrantMutex<RefCell<HashMap<String, Table>>>,
+}
+
+impl MemoryDao {
+    pub fn new() -> MemoryDao {
+        let dao = MemoryDao::empty()
+            .with_table(
+                Student::TABLE,
+                Student::COLUMNS,
+                &[Student::KEY, &["first_names", "last_name"], &["upn"]],
+            )
+            .with_table(
+                SafmedScore::TABLE,
+                SafmedScore::COLUMNS,
+                &[SafmedScore::KEY],
+            )
+            .with_table(AuditEntry::TABLE, AuditEntry::COLUMNS, &[])
+            .with_table(Group::TABLE, Group::COLUMNS, &[Group::KEY, &["name"]])
+            .with_table(Membership::TABLE, Membership::COLUMNS, &[Membership::KEY])
+            .with_table(User::TABLE, User::COLUMNS, &[User::KEY, &["username"]])
+            .with_table(Assignment::TABLE, Assignment::COLUMNS, &[Assignment::KEY])
+            .with_table(Deck::TABLE, Deck::COLUMNS, &[Deck::KEY, &["name"]])
+            .with_table(
+                Card::TABLE,
+                Card::COLUMNS,
+                &[Card::KEY, &["deck_id", "front"]],
+            )
+            .with_table(
+                CardOutcome::TABLE,
+                CardOutcome::COLUMNS,
+                &[CardOutcome::KEY],
+            );
+        // sqlite's migration adds the general deck
+        let general = Deck::general();
+        dao.insert(&Deck::columns(), Deck::TABLE, general.to_values(), false)
+            .expect("the deck table is empty");
+        dao
+    }
+
+    /// A MemoryDao with no tables at all.
+    pub fn empty() -> MemoryDao {
+        MemoryDao {
+            tables: ReentrantMutex::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_table(self, name: &str, columns: &[&str], unique: &[&[&str]]) -> MemoryDao {
+        let table = Table {
+            name: name.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            unique: unique
+                .iter()
+                .map(|u| u.iter().map(|c| c.to_string()).collect())
+                .collect(),
+            rows: Vec::new(),
+        };
+        self.tables
+            .lock()
+            .borrow_mut()
+            .insert(name.to_string(), table);
+        self
+    }
+
+    // Runs f against a copy of the table, only keeping the changes if it
+    // succeeds so a failing statement doesn't leave half its rows behind.
+    fn modify<T>(&self, table: &str, f: impl FnOnce(&mut Table) -> Result<T>) -> Result<T> {
+        let guard = self.tables.lock();
+        let mut tables = guard.borrow_mut();
+        let mut copy = match tables.get(table) {
+            Some(t) => t.clone(),
+            None => return Err(no_such_table(table)),
+        };
+        let result = f(&mut copy)?;
+        tables.insert(table.to_string(), copy);
+        Ok(result)
+    }
+
+    fn matching_rows(&self, table: &str, wheres: &[Where]) -> Result<(Table, Vec<Record>)> {
+        let guard = self.tables.lock();
+        let tables = guard.borrow();
+        let table = match tables.get(table) {
+            Some(t) => t.clone(),
+            None => return Err(no_such_table(table)),
+        };
+        check_wheres(&table, wheres)?;
+        let rows = table
+            .rows
+            .iter()
+            .filter(|row| wheres.iter().all(|w| matches(w, row)))
+            .cloned()
+            .collect();
+        Ok((table, rows))
+    }
+}
+
+impl Default for MemoryDao {
+    fn default() -> Self {
+        MemoryDao::new()
+    }
+}
+
+impl Dao for MemoryDao {
+    fn init(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn select(
+        &self,
+        fields: &Vec<String>,
+        table: &str,
+        wheres: &Vec<Where>,
+        options: &QueryOptions,
+    ) -> Result<Vec<Record>> {
+        let (table, mut rows) = self.matching_rows(table, wheres)?;
+        check_columns(&table, fields)?;
+        let order_fields: Vec<String> = options.order_by.iter().map(|(f, _)| f.clone()).collect();
+        check_columns(&table, &order_fields)?;
+        rows.sort_by(|a, b| {
+            for (field, order) in &options.order_by {
+                let ordering = compare_nulls_first(&a[field], &b[field]);
+                let ordering = match order {
+                    Order::Asc => ordering,
+                    Order::Desc => ordering.reverse(),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        let mut records: Vec<Record> = rows
+            .into_iter()
+            .map(|row| fields.iter().map(|f| (f.clone(), row[f].clone())).collect())
+            .collect();
+        if options.distinct {
+            let mut seen: Vec<Record> = Vec::new();
+            records.retain(|r| match seen.contains(r) {
+                true => false,
+                false => {
+                    seen.push(r.clone());
+                    true
+                }
+            });
+        }
+        let records = records
+            .into_iter()
+            .skip(options.offset.unwrap_or(0))
+            .take(options.limit.unwrap_or(usize::MAX))
+            .collect();
+        log::debug!("found: {:?}", &records);
+        Ok(records)
+    }
+
+    fn insert(
+        &self,
+        fields: &Vec<String>,
+        table: &str,
+        args: Vec<Value>,
+        replace: bool,
+    ) -> Result<usize> {
+        if fields.is_empty() || !args.len().is_multiple_of(fields.len()) {
+            return Err(Error::DbError(
+                "number of args doesn't work with number of records".into(),
+                Source::none(),
+            ));
+        }
+        let name = table.to_string();
+        self.modify(table, |table| {
+            check_columns(table, fields)?;
+            let mut added = 0;
+            for values in args.chunks(fields.len()) {
+                let mut row: Record = table
+                    .columns
+                    .iter()
+                    .map(|c| (c.clone(), Value::Null))
+                    .collect();
+                for (field, value) in fields.iter().zip(values) {
+                    row.insert(field.clone(), stored(value));
+                }
+                let clashes: Vec<usize> = (0..table.rows.len())
+                    .filter(|i| clashes(table, &table.rows[*i], &row).is_some())
+                    .collect();
+                if !clashes.is_empty() {
+                    if !replace {
+                        let columns = clashes_on(table, &table.rows[clashes[0]], &row);
+                        return Err(unique_failed(&name, &columns));
+                    }
+                    for i in clashes.into_iter().rev() {
+                        table.rows.remove(i);
+                    }
+                }
+                table.rows.push(row);
+                added += 1;
+            }
+            Ok(added)
+        })
+    }
+
+    fn update(
+        &self,
+        fields: &Vec<String>,
+        table: &str,
+        args: Vec<Value>,
+        wheres: &Vec<Where>,
+    ) -> Result<usize> {
+        let name = table.to_string();
+        self.modify(table, |table| {
+            check_columns(table, &fields[..args.len().min(fields.len())])?;
+            check_wheres(table, wheres)?;
+            let mut updated = Vec::new();
+            for (i, row) in table.rows.iter_mut().enumerate() {
+                if wheres.iter().all(|w| matches(w, row)) {
+                    for (field, value) in fields.iter().zip(&args) {
+                        row.insert(field.clone(), stored(value));
+                    }
+                    updated.push(i);
+                }
+            }
+            for i in &updated {
+                for (j, other) in table.rows.iter().enumerate() {
+                    if *i != j && clashes(table, other, &table.rows[*i]).is_some() {
+                        let columns = clashes_on(table, other, &table.rows[*i]);
+                        return Err(unique_failed(&name, &columns));
+                    }
+                }
+            }
+            Ok(updated.len())
+        })
+    }
+
+    fn delete(&self, table: &str, wheres: &Vec<Where>) -> Result<usize> {
+        self.modify(table, |table| {
+            check_wheres(table, wheres)?;
+            let before = table.rows.len();
+            table
+                .rows
+                .retain(|row| !wheres.iter().all(|w| matches(w, row)));
+            Ok(before - table.rows.len())
+        })
+    }
+
+    fn aggregate(
+        &self,
+        table: &str,
+        aggregates: &Vec<Aggregate>,
+        group_by: &Vec<String>,
+        wheres: &Vec<Where>,
+        having: &Vec<Where>,
+    ) -> Result<Vec<Record>> {
+        if let Some(a) = aggregates.iter().find(|a| !is_identifier(&a.alias)) {
+            return Err(Error::InvalidIdentifier(a.alias.clone()));
+        }
+        let (table, rows) = self.matching_rows(table, wheres)?;
+        check_columns(&table, group_by)?;
+        let aggregated: Vec<String> = aggregates
+            .iter()
+            .filter(|a| a.field != "*")
+            .map(|a| a.field.clone())
+            .collect();
+        check_columns(&table, &aggregated)?;
+        // having can only see what each group comes back with
+        let grouped: Vec<&String> = group_by
+            .iter()
+            .chain(aggregates.iter().map(|a| &a.alias))
+            .collect();
+        if let Some(f) = having
+            .iter()
+            .flat_map(|w| w.fields())
+            .find(|f| !grouped.iter().any(|g| g.as_str() == *f))
+        {
+            return Err(Error::UnknownColumn(table.name.clone(), f.to_string()));
+        }
+        let mut groups: Vec<(Vec<Value>, Vec<Record>)> = Vec::new();
+        for row in rows {
+            let key: Vec<Value> = group_by.iter().map(|g| row[g].clone()).collect();
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, members)) => members.push(row),
+                None => groups.push((key, vec![row])),
+            }
+        }
+        // without a GROUP BY sql still gives back one row for an empty table
+        if groups.is_empty() && group_by.is_empty() {
+            groups.push((vec![], vec![]));
+        }
+        let records = groups
+            .into_iter()
+            .map(|(key, members)| {
+                let mut record: Record = group_by.iter().cloned().zip(key).collect();
+                for aggregate in aggregates {
+                    record.insert(aggregate.alias.clone(), apply(aggregate, &members));
+                }
+                record
+            })
+            .filter(|record| having.iter().all(|w| matches(w, record)))
+            .collect();
+        log::debug!("found: {:?}", &records);
+        Ok(records)
+    }
+
+    // there's no index to search, so students are ranked in rust instead
+    fn search(
+        &self,
+        fields: &[String],
+        table: &str,
+        text: &str,
+        wheres: &[Where],
+    ) -> Result<Vec<Record>> {
+        if table != Student::TABLE {
+            return Err(no_such_table(&format!("{table}_search")));
+        }
+        let (table, rows) = self.matching_rows(table, wheres)?;
+        check_columns(&table, fields)?;
+        let students = rows
+            .iter()
+            .cloned()
+            .map(Student::try_from)
+            .collect::<Result<Vec<Student>>>()?;
+        let records = search::rank(students, text)
+            .into_iter()
+            .filter_map(|student| {
+                rows.iter()
+                    .find(|row| row["id"] == Value::from(student.id.as_str()))
+            })
+            .map(|row| fields.iter().map(|f| (f.clone(), row[f].clone())).collect())
+            .collect();
+        log::debug!("found: {:?}", &records);
+        Ok(records)
+    }
+
+    fn execute(&self, sql: &str) -> Result<()> {
+        Err(Error::DbError(
+            format!("MemoryDao can't execute sql: {sql}"),
+            Source::none(),
+        ))
+    }
+
+    fn transaction<'a>(&self, f: &mut Transaction<'a>) -> Result<()> {
+        let guard = self.tables.lock();
+        let snapshot = guard.borrow().clone();
+        match f(self) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                log::debug!("rolling back after {e}");
+                *guard.borrow_mut() = snapshot;
+                Err(e)
+            }
+        }
+    }
+
+    fn backup(&self, _label: &str) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+fn no_such_table(table: &str) -> Error {
+    Error::UnknownTable(table.to_string())
+}
+
+fn unique_failed(table: &str, columns: &[String]) -> Error {
+    Error::Duplicate {
+        table: table.to_string(),
+        columns: columns.to_vec(),
+        source: Source::none(),
+    }
+}
+
+fn check_columns(table: &Table, fields: &[String]) -> Result<()> {
+    match fields.iter().find(|f| !table.columns.contains(f)) {
+        Some(f) => Err(Error::UnknownColumn(table.name.clone(), f.clone())),
+        None => Ok(()),
+    }
+}
+
+fn check_wheres(table: &Table, wheres: &[Where]) -> Result<()> {
+    let fields: Vec<String> = wheres
+        .iter()
+        .flat_map(|w| w.fields())
+        .map(String::from)
+        .collect();
+    check_columns(table, &fields)
+}
+
+// The first unique constraint the two rows both have values for, NULLs never
+// clash with anything.
+fn clashes<'a>(table: &'a Table, a: &Record, b: &Record) -> Option<&'a Vec<String>> {
+    table.unique.iter().find(|columns| {
+        columns
+            .iter()
+            .all(|c| !a[c].is_null() && compare(&a[c], &b[c]) == Some(Ordering::Equal))
+    })
+}
+
+fn clashes_on(table: &Table, a: &Record, b: &Record) -> Vec<String> {
+    clashes(table, a, b).cloned().unwrap_or_default()
+}
+
+// Values are kept the way sqlite hands them back, so dates become TEXT.
+fn stored(value: &Value) -> Value {
+    match value {
+        Value::Date(d) => Value::Text(date_to_str(*d)),
+        Value::DateTime(dt) => Value::Text(datetime_to_str(*dt)),
+        v => v.clone(),
+    }
+}
+
+// Compares two values the way sqlite would, None if either is NULL or they
+// can't be compared.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (stored(a), stored(b)) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(&b)),
+        (Value::Integer(a), Value::Real(b)) => (a as f64).partial_cmp(&b),
+        (Value::Real(a), Value::Integer(b)) => a.partial_cmp(&(b as f64)),
+        (Value::Real(a), Value::Real(b)) => a.partial_cmp(&b),
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(&b)),
+        (Value::Blob(a), Value::Blob(b)) => Some(a.cmp(&b)),
+        _ => None,
+    }
+}
+
+fn compare_nulls_first(a: &Value, b: &Value) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => compare(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+fn matches(w: &Where, row: &Record) -> bool {
+    match w {
+        Where::Compare {
+            field,
+            symbol,
+            value,
+        } => {
+            let actual = &row[field];
+            match symbol {
+                Symbol::IN => in_list(actual, value),
+                Symbol::LIKE => match (actual, value) {
+                    (Value::Text(text), Value::Text(pattern)) => like(text, pattern),
+                    _ => false,
+                },
+                _ => match compare(actual, value) {
+                    Some(ordering) => match symbol {
+                        Symbol::EQ => ordering == Ordering::Equal,
+                        Symbol::NEQ => ordering != Ordering::Equal,
+                        Symbol::GT => ordering == Ordering::Greater,
+                        Symbol::LT => ordering == Ordering::Less,
+                        Symbol::GTE => ordering != Ordering::Less,
+                        Symbol::LTE => ordering != Ordering::Greater,
+                        Symbol::IN | Symbol::LIKE => unreachable!(),
+                    },
+                    None => false,
+                },
+            }
+        }
+        Where::Between { field, low, high } => {
+            let actual = &row[field];
+            matches!(
+                compare(actual, low),
+                Some(Ordering::Greater) | Some(Ordering::Equal)
+            ) && matches!(
+                compare(actual, high),
+                Some(Ordering::Less) | Some(Ordering::Equal)
+            )
+        }
+        Where::IsNull { field } => row[field].is_null(),
+        Where::And(wheres) => wheres.iter().all(|w| matches(w, row)),
+        Where::Or(wheres) => wheres.iter().any(|w| matches(w, row)),
+        Where::Not(w) => !matches(w, row),
+    }
+}
+
+fn in_list(actual: &Value, list: &Value) -> bool {
+    let items: Vec<Value> = match list {
+        Value::TextList(tl) => tl.iter().map(|t| Value::from(t.as_str())).collect(),
+        Value::IntegerList(il) => il.iter().map(|i| Value::from(*i)).collect(),
+        v => vec![v.clone()],
+    };
+    items
+        .iter()
+        .any(|item| compare(actual, item) == Some(Ordering::Equal))
+}
+
+// sql LIKE: % matches any run of characters, _ any single one, and ascii
+// letters match regardless of case
+fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.to_ascii_lowercase().chars().collect();
+    let pattern: Vec<char> = pattern.to_ascii_lowercase().chars().collect();
+    fn like_from(text: &[char], pattern: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('%', rest)) => (0..=text.len()).any(|i| like_from(&text[i..], rest)),
+            Some(('_', rest)) => !text.is_empty() && like_from(&text[1..], rest),
+            Some((c, rest)) => text.first() == Some(c) && like_from(&text[1..], rest),
+        }
+    }
+    like_from(&text, &pattern)
+}
+
+fn apply(aggregate: &Aggregate, rows: &[Record]) -> Value {
+    if aggregate.function == Function::Count && aggregate.field == "*" {
+        return Value::from(rows.len() as i32);
+    }
+    let values: Vec<&Value> = rows
+        .iter()
+        .map(|row| &row[&aggregate.field])
+        .filter(|v| !v.is_null())
+        .collect();
+    match aggregate.function {
+        Function::Count => Value::from(values.len() as i32),
+        Function::Sum | Function::Avg if values.is_empty() => Value::Null,
+        Function::Sum => match values.iter().all(|v| matches!(v, Value::Integer(_))) {
+            true => Value::from(values.iter().map(|v| as_f64(v) as i32).sum::<i32>()),
+            false => Value::from(values.iter().map(|v| as_f64(v)).sum::<f64>()),
+        },
+        Function::Avg => {
+            Value::from(values.iter().map(|v| as_f64(v)).sum::<f64>() / values.len() as f64)
+        }
+        Function::Min => values
+            .into_iter()
+            .min_by(|a, b| compare_nulls_first(a, b))
+            .cloned()
+            .unwrap_or(Value::Null),
+        Function::Max => values
+            .into_iter()
+            .max_by(|a, b| compare_nulls_first(a, b))
+            .cloned()
+            .unwrap_or(Value::Null),
+    }
+}
+
+fn as_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Real(r) => *r,
+        _ => 0.0,
+    }
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+
+    fn mock() -> MemoryDao {
+        let dao =
+            MemoryDao::empty().with_table("test", &["field1", "field2", "field3"], &[&["field1"]]);
+        dao.insert(
+            &vec!["field1".into(), "field2".into(), "field3".into()],
+            "test",
+            vec![
+                Value::from(123),
+                Value::from("hello"),
+                Value::from("2021-01-01"),
+                Value::from(124),
+                Value::from("hello"),
+                Value::from(""),
+            ],
+            false,
+        )
+        .unwrap();
+        dao
+    }
+
+    fn field1s(records: Vec<Record>) -> Vec<Value> {
+        records.into_iter().map(|r| r["field1"].clone()).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let dao = mock();
+        let act = dao
+            .select(
+                &vec!["field1".into(), "field2".into()],
+                "test",
+                &vec![Where::new("field1", Symbol::EQ, Value::from(123))],
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![Record::from([
+                ("field1".to_string(), Value::from(123)),
+                ("field2".to_string(), Value::from("hello")),
+            ])],
+            act
+        );
+    }
+
+    #[test]
+    fn test_select_errors() {
+        let dao = mock();
+        let err = dao.select(&vec!["bad".into()], "test", &vec![], &QueryOptions::new());
+        assert_eq!(Err(Error::UnknownColumn("test".into(), "bad".into())), err);
+        let err = dao.select(
+            &vec!["field1".into()],
+            "nope",
+            &vec![],
+            &QueryOptions::new(),
+        );
+        assert_eq!(Err(Error::UnknownTable("nope".into())), err);
+        let w = vec![Where::new("bad", Symbol::EQ, Value::from(1))];
+        let err = dao.select(&vec!["field1".into()], "test", &w, &QueryOptions::new());
+        assert_eq!(Err(Error::UnknownColumn("test".into(), "bad".into())), err);
+    }
+
+    #[test]
+    fn test_where_semantics() {
+        let dao = mock();
+        let date = crate::useful::date_from_str("2021-01-01").unwrap();
+        let tests = vec![
+            (
+                Where::new("field3", Symbol::EQ, Value::from(date)),
+                vec![123],
+            ),
+            (
+                Where::new("field3", Symbol::LT, Value::from("2022-01-01")),
+                vec![123, 124],
+            ),
+            (
+                Where::new("field1", Symbol::NEQ, Value::from(123)),
+                vec![124],
+            ),
+            (
+                Where::new("field1", Symbol::GTE, Value::from(123.5)),
+                vec![124],
+            ),
+            (
+                Where::new("field1", Symbol::IN, Value::from(vec![124, 125])),
+                vec![124],
+            ),
+            (
+                Where::new("field2", Symbol::LIKE, Value::from("H_l%")),
+                vec![123, 124],
+            ),
+            (
+                Where::between("field1", Value::from(100), Value::from(123)),
+                vec![123],
+            ),
+            (Where::is_null("field2"), vec![]),
+            (
+                Where::or(vec![
+                    Where::new("field1", Symbol::EQ, Value::from(124)),
+                    !Where::new("field3", Symbol::EQ, Value::from("")),
+                ]),
+                vec![123, 124],
+            ),
+            (Where::and(vec![]), vec![123, 124]),
+            (Where::or(vec![]), vec![]),
+        ];
+        for (w, exp) in tests {
+            let act = dao
+                .select(
+                    &vec!["field1".into()],
+                    "test",
+                    &vec![w.clone()],
+                    &QueryOptions::new(),
+                )
+                .unwrap();
+            let exp: Vec<Value> = exp.into_iter().map(Value::from).collect();
+            assert_eq!(exp, field1s(act), "{w:?}");
+        }
+    }
+
+    #[test]
+    fn test_select_options() {
+        let dao = mock();
+        let fields = vec!["field1".into()];
+        let options = QueryOptions::new().order_by("field1", Order::Desc).limit(1);
+        let act = dao.select(&fields, "test", &vec![], &options).unwrap();
+        assert_eq!(vec![Value::from(124)], field1s(act));
+        let options = QueryOptions::new().order_by("field3", Order::Asc).offset(1);
+        let act = dao.select(&fields, "test", &vec![], &options).unwrap();
+        assert_eq!(vec![Value::from(123)], field1s(act));
+        let options = QueryOptions::new().distinct();
+        let act = dao
+            .select(&vec!["field2".into()], "test", &vec![], &options)
+            .unwrap();
+        assert_eq!(1, act.len());
+    }
+
+    #[test]
+    fn test_insert_unique() {
+        let dao = mock();
+        let fields = vec!["field1".into(), "field2".into()];
+        let err = dao.insert(
+            &fields,
+            "test",
+            vec![
+                Value::from(1),
+                Value::from("new"),
+                Value::from(123),
+                Value::from("clash"),
+            ],
+            false,
+        );
+        assert_eq!(
+            Err(Error::Duplicate {
+                table: "test".into(),
+                columns: vec!["field1".into()],
+                source: Source::none(),
+            }),
+            err
+        );
+        // the whole statement failed so the first row didn't go in either
+        let all = dao
+            .select(&fields, "test", &vec![], &QueryOptions::new())
+            .unwrap();
+        assert_eq!(2, all.len());
+    }
+
+    #[test]
+    fn test_insert_replace() {
+        let dao = mock();
+        let fields = vec!["field1".into(), "field2".into()];
+        let res = dao.insert(
+            &fields,
+            "test",
+            vec![Value::from(123), Value::from("replaced")],
+            true,
+        );
+        assert_eq!(Ok(1), res);
+        let w = vec![Where::new("field1", Symbol::EQ, Value::from(123))];
+        let act = dao
+            .select(
+                &vec!["field2".into(), "field3".into()],
+                "test",
+                &w,
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![Record::from([
+                ("field2".to_string(), Value::from("replaced")),
+                ("field3".to_string(), Value::Null),
+            ])],
+            act
+        );
+    }
+
+    #[test]
+    fn test_insert_stores_dates_as_text() {
+        let dao = mock();
+        let date = crate::useful::date_from_str("2022-02-02").unwrap();
+        dao.insert(
+            &vec!["field1".into(), "field3".into()],
+            "test",
+            vec![Value::from(1), Value::from(date)],
+            false,
+        )
+        .unwrap();
+        let w = vec![Where::new("field1", Symbol::EQ, Value::from(1))];
+        let act = dao
+            .select(&vec!["field3".into()], "test", &w, &QueryOptions::new())
+            .unwrap();
+        assert_eq!(Value::from("2022-02-02"), act[0]["field3"]);
+    }
+
+    #[test]
+    fn test_update() {
+        let dao = mock();
+        let w = vec![Where::new("field1", Symbol::EQ, Value::from(123))];
+        let res = dao.update(
+            &vec!["field2".into()],
+            "test",
+            vec![Value::from("updated")],
+            &w,
+        );
+        assert_eq!(Ok(1), res);
+        let act = dao
+            .select(
+                &vec!["field2".into()],
+                "test",
+                &vec![],
+                &QueryOptions::new().order_by("field1", Order::Asc),
+            )
+            .unwrap();
+        assert_eq!(Value::from("updated"), act[0]["field2"]);
+        assert_eq!(Value::from("hello"), act[1]["field2"]);
+        let err = dao.update(&vec!["field1".into()], "test", vec![Value::from(124)], &w);
+        assert_matches!(err, Err(Error::Duplicate { .. }));
+    }
+
+    #[test]
+    fn test_delete() {
+        let dao = mock();
+        let w = vec![Where::new("field1", Symbol::EQ, Value::from(123))];
+        assert_eq!(Ok(1), dao.delete("test", &w));
+        assert_eq!(Ok(1), dao.delete("test", &vec![]));
+        assert_eq!(Ok(0), dao.delete("test", &vec![]));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let dao = mock();
+        dao.insert(
+            &vec!["field1".into(), "field2".into(), "field3".into()],
+            "test",
+            vec![
+                Value::from(200),
+                Value::from("bye"),
+                Value::from("2021-01-01"),
+            ],
+            false,
+        )
+        .unwrap();
+        let aggregates = vec![
+            Aggregate::count("n"),
+            Aggregate::new(Function::Sum, "field1", "total"),
+            Aggregate::new(Function::Avg, "field1", "average"),
+            Aggregate::new(Function::Min, "field1", "lowest"),
+            Aggregate::new(Function::Max, "field3", "latest"),
+        ];
+        let act = dao
+            .aggregate(
+                "test",
+                &aggregates,
+                &vec!["field2".into()],
+                &vec![Where::new("field1", Symbol::GT, Value::from(0))],
+                &vec![Where::new("n", Symbol::GT, Value::from(1))],
+            )
+            .unwrap();
+        assert_eq!(
+            vec![Record::from([
+                ("field2".to_string(), Value::from("hello")),
+                ("n".to_string(), Value::from(2)),
+                ("total".to_string(), Value::from(247)),
+                ("average".to_string(), Value::from(123.5)),
+                ("lowest".to_string(), Value::from(123)),
+                ("latest".to_string(), Value::from("2021-01-01")),
+            ])],
+            act
+        );
+        dao.delete("test", &vec![]).unwrap();
+        let act = dao
+            .aggregate("test", &aggregates, &vec![], &vec![], &vec![])
+            .unwrap();
+        assert_eq!(Value::from(0), act[0]["n"]);
+        assert_eq!(Value::Null, act[0]["average"]);
+        // having only knows the groups and aggregates, as in sql
+        assert_eq!(
+            Err(Error::UnknownColumn("test".into(), "field3".into())),
+            dao.aggregate(
+                "test",
+                &aggregates,
+                &vec!["field2".into()],
+                &vec![],
+                &vec![Where::new("field3", Symbol::EQ, Value::from("x"))],
+            )
+        );
+    }
+
+    #[test]
+    fn test_transaction_rolls_back() {
+        let dao = mock();
+        let res = dao.transaction(&mut |tx| {
+            tx.delete("test", &vec![])?;
+            tx.execute("DROP TABLE test")
+        });
+        assert_matches!(res, Err(Error::DbError(..)));
+        let all = dao
+            .select(
+                &vec!["field1".into()],
+                "test",
+                &vec![],
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(2, all.len());
+    }
+
+    #[test]
+    fn test_like() {
+        let tests = vec![
+            ("hello", "hello", true),
+            ("hello", "HEL%", true),
+            ("hello", "%l_", true),
+            ("hello", "h_llo%", true),
+            ("hello", "h_lo", false),
+            ("", "%", true),
+            ("hello", "", false),
+        ];
+        for (text, pattern, exp) in tests {
+            assert_eq!(exp, like(text, pattern), "{text} LIKE {pattern}");
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use crate::constant::{
+    ARCHIVE_STUDENTS, ASSIGNMENT_INDEX, ASSIGNMENT_SCHEMA, AUDIT_INDEX, AUDIT_SCHEMA, AUDIT_USER,
+    CARD_OUTCOME_INDEX, CARD_OUTCOME_SCHEMA, CARD_SCHEMA, CASCADE_SCORES, DECK_SCHEMA, DECK_SCORES,
+    GROUP_SCHEMA, MEMBERSHIP_INDEX, MEMBERSHIP_SCHEMA, SCORE_DURATION, SCORE_SCHEMA,
+    STUDENT_DETAILS, STUDENT_SCHEMA, STUDENT_SEARCH_SCHEMA, STUDENT_SEARCH_TRIGGERS,
+    TIMING_CARD_OUTCOMES, TIMING_SCORES, USER_SCHEMA,
+};
+use crate::errors::{Error, Result};
+use crate::search;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+
+/// A single step in the evolution of the database schema. Migrations are
+/// applied in order of `version`, each only ever to a database at the version
+/// before it. The exception is version 1, whose statements must also be safe
+/// to run against databases made before the schema was versioned.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sqls: &'static [&'static str],
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create student and safmed tables",
+        sqls: &[STUDENT_SCHEMA, SCORE_SCHEMA],
+    },
+    Migration {
+        version: 2,
+        description: "add audit log",
+        sqls: &[AUDIT_SCHEMA, AUDIT_INDEX],
+    },
+    Migration {
+        version: 3,
+        description: "let students be archived",
+        sqls: &[ARCHIVE_STUDENTS],
+    },
+    Migration {
+        version: 4,
+        description: "delete scores along with their student",
+        sqls: &[CASCADE_SCORES],
+    },
+    Migration {
+        version: 5,
+        description: "add upn, class, notes, start and leave dates and active to students",
+        sqls: &[STUDENT_DETAILS],
+    },
+    Migration {
+        version: 6,
+        description: "add groups of students",
+        sqls: &[GROUP_SCHEMA, MEMBERSHIP_SCHEMA, MEMBERSHIP_INDEX],
+    },
+    Migration {
+        version: 7,
+        description: "add users and the students assigned to them",
+        sqls: &[USER_SCHEMA, ASSIGNMENT_SCHEMA, ASSIGNMENT_INDEX],
+    },
+    Migration {
+        version: 8,
+        description: "add decks and key scores by student, deck and date",
+        sqls: &[DECK_SCHEMA, DECK_SCORES],
+    },
+    Migration {
+        version: 9,
+        description: "add cards to decks and the outcome of each card in a timing",
+        sqls: &[CARD_SCHEMA, CARD_OUTCOME_SCHEMA, CARD_OUTCOME_INDEX],
+    },
+    Migration {
+        version: 10,
+        description: "add how long each timing lasted to scores",
+        sqls: &[SCORE_DURATION],
+    },
+    Migration {
+        version: 11,
+        description: "keep every timing of the day, numbered and timestamped",
+        sqls: &[TIMING_SCORES, TIMING_CARD_OUTCOMES, CARD_OUTCOME_INDEX],
+    },
+    Migration {
+        version: 12,
+        description: "index students' names, details and notes for searching",
+        sqls: &[STUDENT_SEARCH_SCHEMA, STUDENT_SEARCH_TRIGGERS],
+    },
+    Migration {
+        version: 13,
+        description: "record who made each change in the audit log",
+        sqls: &[AUDIT_USER],
+    },
+];
+
+/// The schema version this binary knows how to work with.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// The schema version recorded in the database, 0 for a database that has
+/// never been migrated.
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    let version = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version)
+}
+
+/// Brings the database up to the latest schema version, returning the version
+/// it ended up on.
+pub fn migrate(conn: &Connection) -> Result<u32> {
+    add_functions(conn)?;
+    migrate_with(conn, MIGRATIONS)
+}
+
+/// Adds the sql functions the schema relies on to conn. Like foreign keys
+/// they're per connection, and the search index's triggers fail without them.
+pub fn add_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "search_text",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(search::index_text(&ctx.get::<String>(0)?)),
+    )
+}
+
+fn migrate_with(conn: &Connection, migrations: &[Migration]) -> Result<u32> {
+    let current = schema_version(conn)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if current > latest {
+        log::error!("database is at schema version {current} but only {latest} is supported");
+        return Err(Error::UnsupportedSchemaVersion(current, latest));
+    }
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        log::debug!("database schema is up to date at version {current}");
+        return Ok(current);
+    }
+    // tables get rebuilt by some migrations, which the foreign keys on the
+    // tables referring to them would get in the way of
+    let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply(conn, &pending);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result?;
+    Ok(latest)
+}
+
+fn apply(conn: &Connection, pending: &[&Migration]) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    for migration in pending {
+        log::info!(
+            "migrating database to version {}: {}",
+            migration.version,
+            migration.description
+        );
+        for sql in migration.sqls {
+            log::debug!("executing {sql}");
+            tx.execute_batch(sql)?;
+        }
+        tx.pragma_update(None, "user_version", migration.version)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v0() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(STUDENT_SCHEMA, []).unwrap();
+        conn.execute(SCORE_SCHEMA, []).unwrap();
+        conn.execute(
+            "INSERT INTO student VALUES ('st1', 'Ben', 'Jones', '1990-01-23')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO safmed VALUES ('st1', 89, 12, '2022-01-01')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_versions_are_ordered() {
+        let versions: Vec<u32> = MIGRATIONS.iter().map(|m| m.version).collect();
+        let mut sorted = versions.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(versions, sorted);
+        assert_eq!(latest_version(), *versions.last().unwrap());
+    }
+
+    #[test]
+    fn test_migrate_new_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(0, schema_version(&conn).unwrap());
+        assert_eq!(Ok(latest_version()), migrate(&conn));
+        assert_eq!(latest_version(), schema_version(&conn).unwrap());
+        assert_eq!(0, count(&conn, "student"));
+        assert_eq!(0, count(&conn, "safmed"));
+    }
+
+    #[test]
+    fn test_migrate_v0_database() {
+        let conn = v0();
+        assert_eq!(Ok(latest_version()), migrate(&conn));
+        assert_eq!(latest_version(), schema_version(&conn).unwrap());
+        assert_eq!(1, count(&conn, "student"));
+        assert_eq!(1, count(&conn, "safmed"));
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let conn = v0();
+        migrate(&conn).unwrap();
+        assert_eq!(Ok(latest_version()), migrate(&conn));
+        assert_eq!(1, count(&conn, "student"));
+    }
+
+    #[test]
+    fn test_scores_cascade() {
+        let conn = v0();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute("INSERT INTO safmed VALUES ('gone', 1, 1, '2022-01-01')", [])
+            .unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        migrate(&conn).unwrap();
+        // orphans are kept for the doctor to deal with
+        assert_eq!(2, count(&conn, "safmed"));
+        let foreign_keys: bool = conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+        conn.execute("DELETE FROM student WHERE id = 'st1'", [])
+            .unwrap();
+        assert_eq!(1, count(&conn, "safmed"));
+    }
+
+    #[test]
+    fn test_scores_move_to_general_deck() {
+        let conn = v0();
+        migrate(&conn).unwrap();
+        let deck: String = conn
+            .query_row("SELECT deck_id FROM safmed", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("general", deck);
+        // the same day can have a score on another deck but not the same one
+        conn.execute("INSERT INTO deck (id, name) VALUES ('d1', 'Phonics')", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO safmed (id, correct, incorrect, date, deck_id, timed_at) \
+             VALUES ('st1', 40, 3, '2022-01-01', 'd1', '2022-01-01 10:00:00')",
+            [],
+        )
+        .unwrap();
+        assert!(conn
+            .execute(
+                "INSERT INTO safmed (id, correct, incorrect, date, deck_id, timed_at) \
+                 VALUES ('st1', 40, 3, '2022-01-01', 'general', '2022-01-01 10:00:00')",
+                [],
+            )
+            .is_err());
+        assert_eq!(2, count(&conn, "safmed"));
+    }
+
+    #[test]
+    fn test_scores_were_a_minute() {
+        let conn = v0();
+        migrate(&conn).unwrap();
+        let duration: i32 = conn
+            .query_row("SELECT duration FROM safmed", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(60, duration);
+        assert!(conn.execute("UPDATE safmed SET duration = 0", []).is_err());
+    }
+
+    #[test]
+    fn test_several_timings_a_day() {
+        let conn = v0();
+        migrate(&conn).unwrap();
+        let (seq, timed_at): (i32, String) = conn
+            .query_row("SELECT seq, timed_at FROM safmed", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((1, "2022-01-01 00:00:00".to_string()), (seq, timed_at));
+        let insert = "INSERT INTO safmed (id, correct, incorrect, date, seq, timed_at) \
+                      VALUES ('st1', 40, 3, '2022-01-01', ?1, '2022-01-01 10:00:00')";
+        conn.execute(insert, [2]).unwrap();
+        assert!(conn.execute(insert, [2]).is_err());
+        assert_eq!(2, count(&conn, "safmed"));
+    }
+
+    #[test]
+    fn test_search_index_follows_students() {
+        let conn = v0();
+        migrate(&conn).unwrap();
+        let words = || -> Vec<String> {
+            let mut stmt = conn
+                .prepare("SELECT words FROM student_search ORDER BY id")
+                .unwrap();
+            stmt.query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<String>>>()
+                .unwrap()
+        };
+        // students from before the index are added to it
+        assert_eq!(vec![" ben jones 1990 01 23 "], words());
+        conn.execute(
+            "INSERT INTO student (id, first_names, last_name, date_of_birth, upn, notes) \
+             VALUES ('st2', 'Zoë', 'Brontë', '2001-04-02', 'A1', 'Extra time')",
+            [],
+        )
+        .unwrap();
+        conn.execute("UPDATE student SET class = '7B' WHERE id = 'st1'", [])
+            .unwrap();
+        assert_eq!(
+            vec![
+                " ben jones 1990 01 23 7b ",
+                " zoe bronte 2001 04 02 a1 extra time "
+            ],
+            words()
+        );
+        conn.execute("DELETE FROM student WHERE id = 'st1'", [])
+            .unwrap();
+        assert_eq!(1, count(&conn, "student_search"));
+    }
+
+    #[test]
+    fn test_refuses_newer_database() {
+        let conn = v0();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        assert_eq!(
+            Err(Error::UnsupportedSchemaVersion(
+                latest_version() + 1,
+                latest_version()
+            )),
+            migrate(&conn)
+        );
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "good",
+                sqls: &["CREATE TABLE good (x INTEGER)"],
+            },
+            Migration {
+                version: 2,
+                description: "bad",
+                sqls: &[
+                    "CREATE TABLE bad (x INTEGER",
+                    "CREATE TABLE never (x INTEGER)",
+                ],
+            },
+        ];
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(migrate_with(&conn, &migrations).is_err());
+        assert_eq!(0, schema_version(&conn).unwrap());
+        let tables: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type='table'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(0, tables);
+    }
+}
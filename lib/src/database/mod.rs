@@ -1,7 +1,13 @@
 pub(crate) mod dao;
+mod entity;
+mod memorydao;
+pub mod migrations;
 mod sqlitedao;
 mod value;
 
-pub use dao::{Dao, Record, Symbol, Where};
+pub(crate) use dao::is_identifier;
+pub use dao::{Aggregate, Dao, Function, Order, QueryOptions, Record, Symbol, Transaction, Where};
+pub use entity::{column, Entity, Repository};
+pub use memorydao::MemoryDao;
 pub use sqlitedao::SqliteDao;
 pub use value::Value;
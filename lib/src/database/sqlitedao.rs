@@ -1,56 +1,112 @@
-use std::{path::PathBuf, sync::Mutex};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 
-use crate::constant::ENABLE_FOREIGN_KEYS;
-use crate::database::{Dao, Record, Value, Where};
-use crate::errors::{Error, Result};
+use crate::backup::Backups;
+use crate::constant::{ENABLE_FOREIGN_KEYS, MAX_PARAMS};
+use crate::database::{
+    is_identifier, migrations, Aggregate, Dao, QueryOptions, Record, Symbol, Transaction, Value,
+    Where,
+};
+use crate::errors::{Error, Result, Source};
+use crate::search;
 
-use dirs::data_dir;
 use log::*;
+use parking_lot::ReentrantMutex;
 use rusqlite::Connection;
 
+// The lock is re-entrant so that Dao calls made from inside a transaction
+// closure on the same thread reuse the connection the transaction holds. The
+// RefCell is only ever borrowed mutably to restore a snapshot.
 pub struct SqliteDao {
-    pub conn: Mutex<Connection>,
+    pub conn: ReentrantMutex<RefCell<Connection>>,
+    backups: Option<Backups>,
 }
 
-// Panics if can't create sqlx Pool as without that the program is useless
 impl SqliteDao {
-    pub fn new() -> SqliteDao {
-        let dir = data_dir().expect("failed to get data directory");
-        let mut db_path = PathBuf::from(dir);
-        db_path.push("scorg");
-        match std::fs::create_dir(&db_path) {
-            Ok(_) => debug!("created new data directory in {}", &db_path.as_os_str().to_str().unwrap()),
-            Err(_) => debug!("using data directory: {}", &db_path.as_os_str().to_str().unwrap()),
-        };
-        db_path.push(crate::constant::DB_FILE);
-        SqliteDao::using_file(db_path.as_os_str().to_str().unwrap())
-    }
-
-    pub fn using_file(file_path: &str) -> SqliteDao {
-        let conn = Connection::open(file_path);
+    /// Opens (or creates) the sqlite database at path, use ":memory:" for a
+    /// database that only lasts as long as the SqliteDao.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<SqliteDao> {
+        let path = path.as_ref();
+        // foreign keys and functions are per connection in sqlite, so they're
+        // set up as soon as it's opened rather than relying on init
+        let conn = Connection::open(path).and_then(|conn| {
+            conn.execute_batch(ENABLE_FOREIGN_KEYS)?;
+            migrations::add_functions(&conn)?;
+            Ok(conn)
+        });
         match conn {
             Ok(conn) => {
-                log::debug!("created new SqliteDao connecting to {file_path}");
-                SqliteDao {
-                    conn: Mutex::new(conn),
-                }
+                debug!("created new SqliteDao connecting to {}", path.display());
+                Ok(SqliteDao {
+                    conn: ReentrantMutex::new(RefCell::new(conn)),
+                    backups: None,
+                })
             }
             Err(e) => {
-                log::error!("failed to connect to db: {e}");
-                panic!();
+                error!("failed to connect to db: {e}");
+                Err(Error::DbError(
+                    format!("failed to open {}: {e}", path.display()),
+                    Source::new(e),
+                ))
             }
         }
     }
+
+    /// Keeps snapshots in backups, one is taken before migrating an existing
+    /// database and whenever Dao::backup is called.
+    pub fn with_backups(mut self, backups: Backups) -> SqliteDao {
+        self.backups = Some(backups);
+        self
+    }
+
+    /// The snapshots that can be restored, newest first.
+    pub fn snapshots(&self) -> Result<Vec<PathBuf>> {
+        self.backups()?.list()
+    }
+
+    /// Replaces the whole database with the snapshot called name, then
+    /// migrates it so the services can carry on using it. Snapshots taken
+    /// before a migration are on an older schema.
+    pub fn restore(&self, name: &str) -> Result<PathBuf> {
+        let backups = self.backups()?;
+        let guard = self.conn.lock();
+        let mut conn = guard
+            .try_borrow_mut()
+            .map_err(|_| Error::BackupError("can't restore while the database is in use".into()))?;
+        let path = backups.restore(&mut conn, name)?;
+        if let Err(e) = migrations::migrate(&conn) {
+            error!("restored {} but couldn't migrate it: {e}", path.display());
+            return Err(Error::BackupError(format!(
+                "restored {} but couldn't bring it up to date, restart scorg: {e}",
+                path.display()
+            )));
+        }
+        Ok(path)
+    }
+
+    fn backups(&self) -> Result<&Backups> {
+        match &self.backups {
+            Some(backups) => Ok(backups),
+            None => Err(Error::BackupError(
+                "snapshots aren't set up for this database".into(),
+            )),
+        }
+    }
 }
 
 impl Dao for SqliteDao {
     fn init(&self) -> Result<()> {
         log::debug!("initialising...");
-        let sqls = [ENABLE_FOREIGN_KEYS];
-        for sql in sqls {
-            log::debug!("executing {sql}");
-            self.execute(sql)?;
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let version = migrations::schema_version(&conn)?;
+        let is_new: bool = conn.query_row("SELECT COUNT(*) = 0 FROM sqlite_master", [], |row| {
+            row.get(0)
+        })?;
+        if version < migrations::latest_version() && !is_new {
+            self.backup("migration")?;
         }
+        migrations::migrate(&conn)?;
         Ok(())
     }
 
@@ -59,11 +115,18 @@ impl Dao for SqliteDao {
         fields: &Vec<String>,
         table: &str,
         wheres: &Vec<Where>,
+        options: &QueryOptions,
     ) -> Result<Vec<Record>> {
-        let conn = self.conn.lock().expect("Failed to get lock on connection");
-        let mut sql_string = select_string(fields, table);
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let mut names: Vec<&str> = fields.iter().map(String::as_str).collect();
+        names.extend(wheres.iter().flat_map(|w| w.fields()));
+        names.extend(options.order_by.iter().map(|(f, _)| f.as_str()));
+        check_fields(&conn, table, &names)?;
+        let mut sql_string = select_string(fields, table, options.distinct);
         let (where_str, args) = process_wheres(wheres, 0);
         sql_string.push_str(&where_str);
+        sql_string.push_str(&options_string(options));
         log::debug!("{} [{:?}]", &sql_string, &args);
         let mut stmt = conn.prepare(&sql_string)?;
         let records: Vec<Record> = stmt
@@ -88,16 +151,44 @@ impl Dao for SqliteDao {
         args: Vec<Value>,
         replace: bool,
     ) -> Result<usize> {
-        let conn = self.conn.lock().expect("Failed to get lock on connection");
-        let sql_string = insert_string(fields, table, args.len(), replace)?;
-        log::debug!("{} [{:?}]", &sql_string, &args);
-        match conn.execute(&sql_string, rusqlite::params_from_iter(&mut args.iter())) {
-            Ok(n) => Ok(n),
-            Err(e) => {
-                log::error!("{}", &e);
-                Err(Error::DbError(e.to_string()))
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let names: Vec<&str> = fields.iter().map(String::as_str).collect();
+        check_fields(&conn, table, &names)?;
+        if fields.is_empty() || !args.len().is_multiple_of(fields.len()) {
+            return Err(Error::DbError(
+                "number of args doesn't work with number of records".into(),
+                Source::none(),
+            ));
+        }
+        // only whole records fit in a chunk, and every full chunk has the same
+        // sql so its statement gets reused from the cache
+        let chunk_size = (MAX_PARAMS / fields.len()).max(1) * fields.len();
+        let insert_chunk = |args: &[Value]| -> Result<usize> {
+            let sql_string = insert_string(fields, table, args.len(), replace)?;
+            log::debug!("{} [{:?}]", &sql_string, &args);
+            let mut stmt = conn.prepare_cached(&sql_string)?;
+            match stmt.execute(rusqlite::params_from_iter(args.iter())) {
+                Ok(n) => Ok(n),
+                Err(e) => {
+                    log::error!("{}", &e);
+                    Err(e.into())
+                }
             }
+        };
+        if args.len() <= chunk_size {
+            return insert_chunk(&args);
         }
+        // too many for one statement, so split it up but keep it all or nothing
+        let mut inserted = 0;
+        self.transaction(&mut |_| {
+            inserted = 0;
+            for chunk in args.chunks(chunk_size) {
+                inserted += insert_chunk(chunk)?;
+            }
+            Ok(())
+        })?;
+        Ok(inserted)
     }
 
     fn update(
@@ -107,7 +198,11 @@ impl Dao for SqliteDao {
         args: Vec<Value>,
         wheres: &Vec<Where>,
     ) -> Result<usize> {
-        let conn = self.conn.lock().expect("Failed to get lock on connection");
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let mut names: Vec<&str> = fields.iter().map(String::as_str).collect();
+        names.extend(wheres.iter().flat_map(|w| w.fields()));
+        check_fields(&conn, table, &names)?;
         let mut sql_string = update_string(fields, table, args.len());
         let (where_str, w_args) = process_wheres(wheres, args.len());
         sql_string.push_str(&where_str);
@@ -120,27 +215,199 @@ impl Dao for SqliteDao {
             rusqlite::params_from_iter(&mut full_args.iter()),
         ) {
             Ok(n) => Ok(n),
-            Err(e) => Err(Error::DbError(e.to_string())),
+            Err(e) => Err(e.into()),
         }
     }
 
     fn delete(&self, table: &str, wheres: &Vec<Where>) -> Result<usize> {
-        let conn = self.conn.lock().expect("Failed to get lock on connection");
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let names: Vec<&str> = wheres.iter().flat_map(|w| w.fields()).collect();
+        check_fields(&conn, table, &names)?;
         let mut sql_string = delete_string(table);
         let (where_str, args) = process_wheres(wheres, 0);
         sql_string.push_str(&where_str);
         log::debug!("{}", &sql_string);
         match conn.execute(&sql_string, rusqlite::params_from_iter(&mut args.iter())) {
             Ok(n) => Ok(n),
-            Err(e) => Err(Error::DbError(e.to_string())),
+            Err(e) => Err(e.into()),
         }
     }
 
+    fn aggregate(
+        &self,
+        table: &str,
+        aggregates: &Vec<Aggregate>,
+        group_by: &Vec<String>,
+        wheres: &Vec<Where>,
+        having: &Vec<Where>,
+    ) -> Result<Vec<Record>> {
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        if let Some(a) = aggregates.iter().find(|a| !is_identifier(&a.alias)) {
+            return Err(Error::InvalidIdentifier(a.alias.clone()));
+        }
+        let mut names: Vec<&str> = group_by.iter().map(String::as_str).collect();
+        names.extend(
+            aggregates
+                .iter()
+                .filter(|a| a.field != "*")
+                .map(|a| a.field.as_str()),
+        );
+        names.extend(wheres.iter().flat_map(|w| w.fields()));
+        check_fields(&conn, table, &names)?;
+        // having can only refer to what each group comes back with, sqlite
+        // would otherwise pick a value from any row of the group
+        if let Some(f) = having
+            .iter()
+            .flat_map(|w| w.fields())
+            .find(|f| !group_by.iter().any(|g| g == f) && !aggregates.iter().any(|a| a.alias == *f))
+        {
+            return Err(Error::UnknownColumn(table.to_string(), f.to_string()));
+        }
+        let mut sql_string = aggregate_string(table, aggregates, group_by);
+        let (where_str, mut args) = process_wheres(wheres, 0);
+        sql_string.push_str(&where_str);
+        if !group_by.is_empty() {
+            sql_string.push_str(&format!(" GROUP BY {}", group_by.join(",")));
+        }
+        let (having_str, having_args) = process_wheres(having, args.len());
+        sql_string.push_str(&having_str.replacen(" WHERE ", " HAVING ", 1));
+        args.extend(having_args);
+        log::debug!("{} [{:?}]", &sql_string, &args);
+        let mut stmt = conn.prepare(&sql_string)?;
+        let fields: Vec<&str> = group_by
+            .iter()
+            .map(|g| g.as_str())
+            .chain(aggregates.iter().map(|a| a.alias.as_str()))
+            .collect();
+        let records = stmt
+            .query_map(rusqlite::params_from_iter(&mut args.iter()), |row| {
+                let mut record = Record::new();
+                for field in &fields {
+                    record.insert(field.to_string(), row.get(*field)?);
+                }
+                Ok(record)
+            })?
+            .collect::<std::result::Result<Vec<Record>, rusqlite::Error>>()?;
+        log::debug!("found: {:?}", &records);
+        Ok(records)
+    }
+
+    fn search(
+        &self,
+        fields: &[String],
+        table: &str,
+        text: &str,
+        wheres: &[Where],
+    ) -> Result<Vec<Record>> {
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let mut names: Vec<&str> = fields.iter().map(String::as_str).collect();
+        names.extend(wheres.iter().flat_map(|w| w.fields()));
+        check_fields(&conn, table, &names)?;
+        let index = format!("{table}_search");
+        check_fields(&conn, &index, &["id", "words"])?;
+        let words = search::index_trigrams(text);
+        if words.is_empty() {
+            return Ok(vec![]);
+        }
+        // the index finds rows with any of the trigrams, then each word has
+        // to have enough of its own in the row to count
+        let phrases: Vec<String> = words.iter().flatten().map(|t| format!("\"{t}\"")).collect();
+        let mut args = vec![Value::from(phrases.join(" OR "))];
+        let mut found = Vec::new();
+        let mut enough = Vec::new();
+        for trigrams in &words {
+            let hits: Vec<String> = trigrams
+                .iter()
+                .map(|trigram| {
+                    args.push(Value::from(trigram.as_str()));
+                    format!("(instr(words, ?{}) > 0)", args.len())
+                })
+                .collect();
+            let hits = hits.join(" + ");
+            let needed = (trigrams.len() as f64 * search::MIN_TRIGRAMS).ceil() as usize;
+            enough.push(format!("{hits} >= {needed}"));
+            found.push(hits);
+        }
+        let (where_str, where_args) = process_wheres(wheres, args.len());
+        args.extend(where_args);
+        let sql_string = format!(
+            "{} JOIN (SELECT id AS match_id, {} AS match_hits, bm25({index}) AS match_rank \
+             FROM {index} WHERE {index} MATCH ?1 AND {}) ON match_id = id{where_str} \
+             ORDER BY match_hits DESC, match_rank",
+            select_string(fields, table, false),
+            found.join(" + "),
+            enough.join(" AND ")
+        );
+        log::debug!("{} [{:?}]", &sql_string, &args);
+        let mut stmt = conn.prepare(&sql_string)?;
+        let records = stmt
+            .query_map(rusqlite::params_from_iter(&mut args.iter()), |row| {
+                let mut record = Record::new();
+                for field in fields {
+                    record.insert(field.clone(), row.get(field.as_str())?);
+                }
+                Ok(record)
+            })?
+            .collect::<std::result::Result<Vec<Record>, rusqlite::Error>>()?;
+        log::debug!("found: {:?}", &records);
+        Ok(records)
+    }
+
     fn execute(&self, sql: &str) -> Result<()> {
-        let conn = self.conn.lock().expect("Failed to get lock on connection");
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
         match conn.execute(sql, []) {
             Ok(_) => Ok(()),
-            Err(e) => Err(Error::DbError(e.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn transaction<'a>(&self, f: &mut Transaction<'a>) -> Result<()> {
+        // hold the lock for the whole transaction so other threads can't
+        // interleave their statements with ours
+        let guard = self.conn.lock();
+        let conn = guard.borrow();
+        let (begin, commit, rollback) = match conn.is_autocommit() {
+            true => ("BEGIN", "COMMIT", "ROLLBACK"),
+            false => (
+                "SAVEPOINT nested",
+                "RELEASE nested",
+                "ROLLBACK TO nested; RELEASE nested",
+            ),
+        };
+        log::debug!("{begin}");
+        conn.execute_batch(begin)?;
+        match f(self) {
+            Ok(()) => {
+                log::debug!("{commit}");
+                // a failed commit leaves the transaction open, and every
+                // later statement on the connection would end up in it
+                if let Err(e) = conn.execute_batch(commit) {
+                    log::debug!("{rollback} after {e}");
+                    conn.execute_batch(rollback)?;
+                    return Err(e.into());
+                }
+                Ok(())
+            }
+            Err(e) => {
+                log::debug!("{rollback} after {e}");
+                conn.execute_batch(rollback)?;
+                Err(e)
+            }
+        }
+    }
+
+    fn backup(&self, label: &str) -> Result<Option<PathBuf>> {
+        match &self.backups {
+            Some(backups) => {
+                let guard = self.conn.lock();
+                let conn = guard.borrow();
+                Ok(Some(backups.snapshot(&conn, label)?))
+            }
+            None => Ok(None),
         }
     }
 
@@ -155,20 +422,37 @@ impl Dao for SqliteDao {
     // }
 }
 
-fn process_wheres(wheres: &Vec<Where>, start: usize) -> (String, Vec<Value>) {
+// Table and field names can't be bound as parameters so they get spliced into
+// the sql, which is only safe once they're known to be in the live schema.
+fn check_fields(conn: &Connection, table: &str, fields: &[&str]) -> Result<()> {
+    let mut stmt = conn.prepare_cached("SELECT name FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<String>, rusqlite::Error>>()?;
+    // a table with no columns doesn't exist, names that need quoting are
+    // turned away too so they can't be mistaken for sql
+    if columns.is_empty() || !is_identifier(table) {
+        return Err(Error::UnknownTable(table.to_string()));
+    }
+    match fields
+        .iter()
+        .find(|f| !columns.iter().any(|c| c.eq_ignore_ascii_case(f)))
+    {
+        Some(f) => Err(Error::UnknownColumn(table.to_string(), f.to_string())),
+        None => Ok(()),
+    }
+}
+
+fn process_wheres(wheres: &[Where], start: usize) -> (String, Vec<Value>) {
     let mut args: Vec<Value> = Vec::new();
     let mut where_string = String::new();
-    if wheres.len() >= 1 {
+    if !wheres.is_empty() {
         where_string.push_str(" WHERE ");
         let mut arg_count = start;
         where_string.push_str(
             &wheres
                 .iter()
-                .map(|w| {
-                    arg_count += 1;
-                    args.push(w.value.clone());
-                    format!("{}{}?{}", w.field, w.symbol.to_string(), arg_count)
-                })
+                .map(|w| render_where(w, &mut args, &mut arg_count))
                 .collect::<Vec<String>>()
                 .join(" AND "),
         );
@@ -176,41 +460,134 @@ fn process_wheres(wheres: &Vec<Where>, start: usize) -> (String, Vec<Value>) {
     (where_string, args)
 }
 
-fn select_string(fields: &Vec<String>, table: &str) -> String {
-    let field_string = fields.clone().join(",");
+// Renders a single Where, pushing its values onto args and numbering their
+// parameters on from arg_count.
+fn render_where(w: &Where, args: &mut Vec<Value>, arg_count: &mut usize) -> String {
+    match w {
+        Where::Compare {
+            field,
+            symbol,
+            value,
+        } => {
+            if let Symbol::IN = symbol {
+                return format!("{field} IN ({})", render_list(value, args, arg_count));
+            }
+            *arg_count += 1;
+            args.push(value.clone());
+            match symbol {
+                Symbol::LIKE => format!("{field} LIKE ?{arg_count}"),
+                _ => format!("{field}{}?{arg_count}", symbol.to_string()),
+            }
+        }
+        Where::Between { field, low, high } => {
+            args.push(low.clone());
+            args.push(high.clone());
+            *arg_count += 2;
+            format!("{field} BETWEEN ?{} AND ?{arg_count}", *arg_count - 1)
+        }
+        Where::IsNull { field } => format!("{field} IS NULL"),
+        Where::And(wheres) => render_group(wheres, " AND ", "1", args, arg_count),
+        Where::Or(wheres) => render_group(wheres, " OR ", "0", args, arg_count),
+        Where::Not(w) => format!("NOT ({})", render_where(w, args, arg_count)),
+    }
+}
+
+// Lists can't be bound to a single parameter, so each item gets its own.
+fn render_list(value: &Value, args: &mut Vec<Value>, arg_count: &mut usize) -> String {
+    let items: Vec<Value> = match value {
+        Value::TextList(tl) => tl.iter().map(|t| Value::from(t.as_str())).collect(),
+        Value::IntegerList(il) => il.iter().map(|i| Value::from(*i)).collect(),
+        v => vec![v.clone()],
+    };
+    items
+        .into_iter()
+        .map(|item| {
+            *arg_count += 1;
+            args.push(item);
+            format!("?{arg_count}")
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn render_group(
+    wheres: &[Where],
+    joiner: &str,
+    empty: &str,
+    args: &mut Vec<Value>,
+    arg_count: &mut usize,
+) -> String {
+    match wheres.len() {
+        0 => empty.to_string(),
+        1 => render_where(&wheres[0], args, arg_count),
+        _ => {
+            let rendered: Vec<String> = wheres
+                .iter()
+                .map(|w| render_where(w, args, arg_count))
+                .collect();
+            format!("({})", rendered.join(joiner))
+        }
+    }
+}
+
+fn select_string(fields: &[String], table: &str, distinct: bool) -> String {
+    let field_string = fields.join(",");
+    let distinct = match distinct {
+        true => "DISTINCT ",
+        false => "",
+    };
+    format!("SELECT {distinct}{field_string} FROM {table}")
+}
+
+fn aggregate_string(table: &str, aggregates: &[Aggregate], group_by: &[String]) -> String {
+    let field_string = group_by
+        .iter()
+        .cloned()
+        .chain(
+            aggregates
+                .iter()
+                .map(|a| format!("{}({}) AS {}", a.function, a.field, a.alias)),
+        )
+        .collect::<Vec<String>>()
+        .join(",");
     format!("SELECT {field_string} FROM {table}")
 }
 
-fn insert_string(fields: &Vec<String>, table: &str, args: usize, replace: bool) -> Result<String> {
-    let field_string = fields.clone().join(",");
+fn options_string(options: &QueryOptions) -> String {
+    let mut options_string = String::new();
+    if !options.order_by.is_empty() {
+        let order_by: Vec<String> = options
+            .order_by
+            .iter()
+            .map(|(field, order)| format!("{field} {order}"))
+            .collect();
+        options_string.push_str(&format!(" ORDER BY {}", order_by.join(",")));
+    }
+    match (options.limit, options.offset) {
+        (Some(limit), Some(offset)) => {
+            options_string.push_str(&format!(" LIMIT {limit} OFFSET {offset}"))
+        }
+        (Some(limit), None) => options_string.push_str(&format!(" LIMIT {limit}")),
+        // sqlite won't take an OFFSET without a LIMIT, -1 means no limit
+        (None, Some(offset)) => options_string.push_str(&format!(" LIMIT -1 OFFSET {offset}")),
+        (None, None) => {}
+    }
+    options_string
+}
+
+fn insert_string(fields: &[String], table: &str, args: usize, replace: bool) -> Result<String> {
+    let field_string = fields.join(",");
     let num_fields = fields.len();
     let value_string: String = match num_fields {
-        n if n == args => {
-            let mut args_list = Vec::new();
-            let mut count = 1;
-            for _ in 0..args {
-                args_list.push(format!("${count}"));
-                count += 1;
-            }
-            format!("({})", args_list.join(","))
-        }
-        n if args % n == 0 => {
-            let num_recs = args / n;
-            let mut count = 1;
-            let mut temp = Vec::new();
-            for _ in 0..num_recs {
-                let mut args_list: Vec<String> = Vec::new();
-                for _ in 0..num_fields {
-                    args_list.push(format!("${count}"));
-                    count += 1;
-                }
-                temp.push(format!("({})", args_list.join(",")));
-            }
-            format!("{}", temp.join(","))
-        }
+        n if n == args => format!("({})", placeholders(1..=args)),
+        n if args.is_multiple_of(n) => (0..args / n)
+            .map(|rec| format!("({})", placeholders(rec * n + 1..=(rec + 1) * n)))
+            .collect::<Vec<String>>()
+            .join(","),
         _ => {
             return Err(Error::DbError(
                 "number of args doesn't work with number of records".into(),
+                Source::none(),
             ))
         }
     };
@@ -223,14 +600,21 @@ fn insert_string(fields: &Vec<String>, table: &str, args: usize, replace: bool)
     ))
 }
 
-fn update_string(fields: &Vec<String>, table: &str, args: usize) -> String {
-    let mut updates_list = Vec::new();
-    let mut count = 1;
-    for i in 0..args {
-        updates_list.push(format!("{}=${count}", fields[i]));
-        count += 1;
-    }
-    format!("UPDATE {table} SET {}", updates_list.join(","))
+// $first,...,$last for the args of one record
+fn placeholders(args: std::ops::RangeInclusive<usize>) -> String {
+    args.map(|i| format!("${i}"))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn update_string(fields: &[String], table: &str, args: usize) -> String {
+    let updates: Vec<String> = fields
+        .iter()
+        .take(args)
+        .enumerate()
+        .map(|(i, field)| format!("{field}=${}", i + 1))
+        .collect();
+    format!("UPDATE {table} SET {}", updates.join(","))
 }
 
 fn delete_string(table: &str) -> String {
@@ -244,7 +628,7 @@ fn delete_string(table: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::{Symbol, Value};
+    use crate::database::{Function, Order, Value};
     use matches::assert_matches;
     use std::collections::HashMap;
 
@@ -261,7 +645,8 @@ mod tests {
         )
         .expect("failed to insert test data");
         SqliteDao {
-            conn: Mutex::new(conn),
+            conn: ReentrantMutex::new(RefCell::new(conn)),
+            backups: None,
         }
     }
 
@@ -279,7 +664,12 @@ mod tests {
             ]),
         ];
         let act = dao
-            .select(&vec!["field1".into(), "field2".into()], "test", &vec![])
+            .select(
+                &vec!["field1".into(), "field2".into()],
+                "test",
+                &vec![],
+                &QueryOptions::new(),
+            )
             .expect("Select failed");
         assert_eq!(exp, act);
     }
@@ -293,7 +683,12 @@ mod tests {
             ("field2".to_string(), Value::from("hello")),
         ])];
         let act = dao
-            .select(&vec!["field1".into(), "field2".into()], "test", &wheres)
+            .select(
+                &vec!["field1".into(), "field2".into()],
+                "test",
+                &wheres,
+                &QueryOptions::new(),
+            )
             .expect("select failed");
         assert_eq!(exp, act);
     }
@@ -301,15 +696,84 @@ mod tests {
     #[test]
     fn test_select_bad_field() {
         let dao = mock();
-        let err = dao.select(&vec!["bad".into()], "test", &vec![]);
-        assert_matches!(&err, Err(Error::DbError(_)));
-        match err {
-            Err(Error::DbError(ms)) => assert_eq!(
-                ms,
-                "no such column: bad in SELECT bad FROM test at offset 7"
-            ), // TODO offset 7?
-            _ => panic!("this should be an error"),
-        };
+        let err = dao.select(&vec!["bad".into()], "test", &vec![], &QueryOptions::new());
+        assert_eq!(Err(Error::UnknownColumn("test".into(), "bad".into())), err);
+        let err = dao.select(
+            &vec!["field1".into()],
+            "nope",
+            &vec![],
+            &QueryOptions::new(),
+        );
+        assert_eq!(Err(Error::UnknownTable("nope".into())), err);
+    }
+
+    #[test]
+    fn test_hostile_identifiers() {
+        let dao = mock();
+        let field1 = vec!["field1".to_string()];
+        let hostile = "field1 FROM test; DROP TABLE test; --";
+        assert_eq!(
+            Err(Error::UnknownColumn("test".into(), hostile.into())),
+            dao.select(&vec![hostile.into()], "test", &vec![], &QueryOptions::new())
+        );
+        assert_eq!(
+            Err(Error::UnknownTable("test; DROP TABLE test".into())),
+            dao.select(
+                &field1,
+                "test; DROP TABLE test",
+                &vec![],
+                &QueryOptions::new()
+            )
+        );
+        let wheres = vec![Where::or(vec![Where::is_null("1=1 OR field1")])];
+        assert_matches!(
+            dao.select(&field1, "test", &wheres, &QueryOptions::new()),
+            Err(Error::UnknownColumn(..))
+        );
+        let options = QueryOptions::new().order_by("field1; DELETE FROM test", Order::Asc);
+        assert_matches!(
+            dao.select(&field1, "test", &vec![], &options),
+            Err(Error::UnknownColumn(..))
+        );
+        assert_matches!(
+            dao.insert(
+                &vec!["field1) VALUES (1); --".into()],
+                "test",
+                vec![1.into()],
+                false
+            ),
+            Err(Error::UnknownColumn(..))
+        );
+        assert_matches!(
+            dao.update(
+                &vec!["field2=1,field1".into()],
+                "test",
+                vec![1.into()],
+                &vec![]
+            ),
+            Err(Error::UnknownColumn(..))
+        );
+        assert_matches!(
+            dao.delete("test WHERE 1=1 OR 1", &vec![]),
+            Err(Error::UnknownTable(..))
+        );
+        let aggregates = vec![Aggregate::count("n FROM test; DROP TABLE test; --")];
+        assert_matches!(
+            dao.aggregate("test", &aggregates, &vec![], &vec![], &vec![]),
+            Err(Error::InvalidIdentifier(..))
+        );
+        let aggregates = vec![Aggregate::new(Function::Max, "field1) FROM test; --", "n")];
+        assert_matches!(
+            dao.aggregate("test", &aggregates, &vec![], &vec![], &vec![]),
+            Err(Error::UnknownColumn(..))
+        );
+        // nothing got through
+        assert_eq!(
+            2,
+            dao.select(&field1, "test", &vec![], &QueryOptions::new())
+                .unwrap()
+                .len()
+        );
     }
 
     #[test]
@@ -320,10 +784,54 @@ mod tests {
             HashMap::from([("field1".to_string(), Value::from(123))]),
             HashMap::from([("field1".to_string(), Value::from(124))]),
         ];
-        let act = dao.select(&vec!["field1".into()], "test", &w).unwrap();
+        let act = dao
+            .select(&vec!["field1".into()], "test", &w, &QueryOptions::new())
+            .unwrap();
         assert_eq!(exp, act);
     }
 
+    #[test]
+    fn test_search() {
+        let dao = mock();
+        let id = vec!["id".to_string()];
+        assert_eq!(
+            Err(Error::UnknownTable("test_search".into())),
+            dao.search(&["field1".into()], "test", "hello", &[])
+        );
+        dao.execute("CREATE TABLE thing (id TEXT, kept INTEGER)")
+            .unwrap();
+        dao.execute("CREATE VIRTUAL TABLE thing_search USING fts5(id UNINDEXED, words, tokenize = 'trigram')")
+            .unwrap();
+        dao.execute("INSERT INTO thing VALUES ('t1', 1), ('t2', 1), ('t3', 0), ('t4', 1)")
+            .unwrap();
+        dao.execute(
+            "INSERT INTO thing_search VALUES ('t1', ' smyth '), ('t2', ' smith '), \
+             ('t3', ' smith '), ('t4', ' jones ')",
+        )
+        .unwrap();
+        let ids = |records: Vec<Record>| -> Vec<Value> {
+            records.into_iter().map(|r| r["id"].clone()).collect()
+        };
+        let kept = vec![Where::new("kept", Symbol::EQ, Value::from(1))];
+        assert_eq!(
+            vec![Value::from("t2"), Value::from("t1")],
+            ids(dao.search(&id, "thing", "SMITH", &kept).unwrap())
+        );
+        // a trigram or two in common isn't enough
+        assert_eq!(
+            vec![Value::from("t4")],
+            ids(dao.search(&id, "thing", "jonas", &kept).unwrap())
+        );
+        assert!(dao
+            .search(&id, "thing", "smooth", &kept)
+            .unwrap()
+            .is_empty());
+        assert!(dao
+            .search(&id, "thing", "\" OR *", &kept)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_insert() {
         let dao = mock();
@@ -332,7 +840,12 @@ mod tests {
         let res = dao.insert(&fields, "test", args, false);
         assert_matches!(res, Ok(_));
         let w = vec![Where::new("field1", Symbol::EQ, Value::from(99))];
-        let db_check = dao.select(&vec!["field1".into(), "field2".into()], "test", &w);
+        let db_check = dao.select(
+            &vec!["field1".into(), "field2".into()],
+            "test",
+            &w,
+            &QueryOptions::new(),
+        );
         assert_eq!(
             db_check.unwrap()[0],
             Record::from([
@@ -342,17 +855,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_insert_in_chunks() {
+        let dao = mock();
+        let fields = vec!["field1".into(), "field2".into(), "field3".into()];
+        let args: Vec<Value> = (0..40_000)
+            .flat_map(|i| {
+                vec![
+                    Value::from(i),
+                    Value::from("bulk"),
+                    Value::from("2021-01-01"),
+                ]
+            })
+            .collect();
+        assert_eq!(Ok(40_000), dao.insert(&fields, "test", args, false));
+        let count = dao
+            .aggregate(
+                "test",
+                &vec![Aggregate::count("n")],
+                &vec![],
+                &vec![],
+                &vec![],
+            )
+            .unwrap();
+        assert_eq!(Value::from(40_002), count[0]["n"]);
+    }
+
+    #[test]
+    fn test_insert_in_chunks_is_all_or_nothing() {
+        let dao = mock();
+        dao.execute("CREATE TABLE uniq (n INTEGER UNIQUE)").unwrap();
+        // the clash is in the last chunk, long after the first ones went in
+        let mut args: Vec<Value> = (0..5_000).map(Value::from).collect();
+        args.push(Value::from(0));
+        let err = dao.insert(&vec!["n".into()], "uniq", args, false);
+        assert_eq!(
+            Err(Error::Duplicate {
+                table: "uniq".into(),
+                columns: vec!["n".into()],
+                source: Source::none(),
+            }),
+            err
+        );
+        let count = dao
+            .aggregate(
+                "uniq",
+                &vec![Aggregate::count("n")],
+                &vec![],
+                &vec![],
+                &vec![],
+            )
+            .unwrap();
+        assert_eq!(Value::from(0), count[0]["n"]);
+    }
+
     #[test]
     fn test_insert_error() {
         let dao = mock();
         let fields = vec!["field1".into(), "bad".into()];
         let args = vec![Value::from(99), Value::from("inserted")];
         let err = dao.insert(&fields, "test", args, false);
-        assert_matches!(&err, Err(Error::DbError(_)));
-        match err {
-            Err(Error::DbError(ms)) => assert_eq!(ms, "table test has no column named bad"),
-            _ => panic!("this should be an error"),
-        };
+        assert_eq!(Err(Error::UnknownColumn("test".into(), "bad".into())), err);
     }
 
     #[test]
@@ -362,7 +925,12 @@ mod tests {
         let args = vec![Value::from("updated")];
         let res = dao.update(&fields, "test", args, &vec![]);
         assert_matches!(res, Ok(_));
-        let db_check = dao.select(&vec!["field1".into(), "field2".into()], "test", &vec![]);
+        let db_check = dao.select(
+            &vec!["field1".into(), "field2".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
         assert_eq!(
             db_check.unwrap(),
             vec![
@@ -386,7 +954,12 @@ mod tests {
         let w = vec![Where::new("field1", Symbol::EQ, Value::from(123))];
         let res = dao.update(&fields, "test", args, &w);
         assert_matches!(res, Ok(_));
-        let db_check = dao.select(&vec!["field1".into(), "field2".into()], "test", &vec![]);
+        let db_check = dao.select(
+            &vec!["field1".into(), "field2".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
         assert_eq!(
             db_check.unwrap(),
             vec![
@@ -408,11 +981,7 @@ mod tests {
         let fields = vec!["field1".into(), "bad".into()];
         let args = vec![Value::from(99), Value::from("inserted")];
         let err = dao.update(&fields, "test", args, &vec![]);
-        assert_matches!(&err, Err(Error::DbError(_)));
-        match err {
-            Err(Error::DbError(ms)) => assert_eq!(ms, "no such column: bad"),
-            _ => panic!("this should be an error"),
-        };
+        assert_eq!(Err(Error::UnknownColumn("test".into(), "bad".into())), err);
     }
 
     #[test]
@@ -420,7 +989,12 @@ mod tests {
         let dao = mock();
         let res = dao.delete("test", &vec![]);
         assert_matches!(res, Ok(_));
-        let db_check = dao.select(&vec!["field1".into(), "field2".into()], "test", &vec![]);
+        let db_check = dao.select(
+            &vec!["field1".into(), "field2".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
         assert_eq!(db_check.unwrap(), vec![]);
     }
 
@@ -430,7 +1004,12 @@ mod tests {
         let w = vec![Where::new("field1", Symbol::EQ, Value::from(123))];
         let res = dao.delete("test", &w);
         assert_matches!(res, Ok(_));
-        let db_check = dao.select(&vec!["field1".into(), "field2".into()], "test", &vec![]);
+        let db_check = dao.select(
+            &vec!["field1".into(), "field2".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
         assert_eq!(
             db_check.unwrap(),
             vec![Record::from([
@@ -440,6 +1019,105 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_transaction_commits() {
+        let dao = mock();
+        let res = dao.transaction(&mut |tx| {
+            tx.insert(&vec!["field1".into()], "test", vec![Value::from(99)], false)?;
+            tx.delete(
+                "test",
+                &vec![Where::new("field1", Symbol::EQ, Value::from(123))],
+            )?;
+            Ok(())
+        });
+        assert_matches!(res, Ok(()));
+        let db_check = dao.select(
+            &vec!["field1".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
+        assert_eq!(
+            db_check.unwrap(),
+            vec![
+                Record::from([("field1".to_string(), Value::from(124))]),
+                Record::from([("field1".to_string(), Value::from(99))]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transaction_rolls_back() {
+        let dao = mock();
+        let res = dao.transaction(&mut |tx| {
+            tx.delete("test", &vec![])?;
+            tx.insert(&vec!["bad".into()], "test", vec![Value::from(99)], false)?;
+            Ok(())
+        });
+        assert_matches!(res, Err(Error::UnknownColumn(..)));
+        let db_check = dao.select(
+            &vec!["field1".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
+        assert_eq!(db_check.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_failed_commit_rolls_back() {
+        let dao = mock();
+        for sql in [
+            "PRAGMA foreign_keys=on",
+            "CREATE TABLE parent (id TEXT PRIMARY KEY)",
+            "CREATE TABLE child (id TEXT REFERENCES parent(id) DEFERRABLE INITIALLY DEFERRED)",
+        ] {
+            dao.execute(sql).unwrap();
+        }
+        // the foreign key is only checked on commit
+        let res = dao.transaction(&mut |tx| {
+            tx.insert(
+                &vec!["id".into()],
+                "child",
+                vec![Value::from("nope")],
+                false,
+            )?;
+            Ok(())
+        });
+        assert_matches!(res, Err(Error::ForeignKeyViolation(..)));
+        assert!(dao.conn.lock().borrow().is_autocommit());
+        let children = dao.select(&vec!["id".into()], "child", &vec![], &QueryOptions::new());
+        assert_eq!(Ok(vec![]), children);
+    }
+
+    #[test]
+    fn test_nested_transaction_rolls_back_alone() {
+        let dao = mock();
+        let res = dao.transaction(&mut |tx| {
+            tx.delete(
+                "test",
+                &vec![Where::new("field1", Symbol::EQ, Value::from(123))],
+            )?;
+            let inner = tx.transaction(&mut |tx| {
+                tx.delete("test", &vec![])?;
+                Err(Error::NotFound("student".into()))
+            });
+            assert_eq!(inner, Err(Error::NotFound("student".into())));
+            Ok(())
+        });
+        assert_matches!(res, Ok(()));
+        let db_check = dao.select(
+            &vec!["field1".into()],
+            "test",
+            &vec![],
+            &QueryOptions::new(),
+        );
+        assert_eq!(
+            db_check.unwrap(),
+            vec![Record::from([("field1".to_string(), Value::from(124))])]
+        );
+    }
+
     // #[test]
     // fn test_fetch() {
     //     let dao = mock();
@@ -465,15 +1143,291 @@ mod tests {
         assert_eq!(exp_args, act.1);
     }
 
+    #[test]
+    fn test_process_wheres_expressions() {
+        let tests = vec![
+            (
+                vec![Where::or(vec![
+                    Where::new("x", Symbol::GTE, Value::from(1)),
+                    Where::new("y", Symbol::NEQ, Value::from("derp")),
+                ])],
+                0,
+                " WHERE (x>=?1 OR y!=?2)",
+                vec![Value::from(1), Value::from("derp")],
+            ),
+            (
+                vec![
+                    Where::between("date", Value::from("2021-01-01"), Value::from("2021-02-01")),
+                    Where::new("name", Symbol::LIKE, Value::from("Be%")),
+                ],
+                2,
+                " WHERE date BETWEEN ?3 AND ?4 AND name LIKE ?5",
+                vec![
+                    Value::from("2021-01-01"),
+                    Value::from("2021-02-01"),
+                    Value::from("Be%"),
+                ],
+            ),
+            (
+                vec![!Where::and(vec![
+                    Where::is_null("x"),
+                    Where::or(vec![
+                        Where::new("y", Symbol::GT, Value::from(1)),
+                        Where::new("y", Symbol::LTE, Value::from(-1)),
+                    ]),
+                ])],
+                0,
+                " WHERE NOT ((x IS NULL AND (y>?1 OR y<=?2)))",
+                vec![Value::from(1), Value::from(-1)],
+            ),
+            (
+                vec![Where::or(vec![]), Where::and(vec![])],
+                0,
+                " WHERE 0 AND 1",
+                vec![],
+            ),
+        ];
+        for (wheres, start, exp_where_str, exp_args) in tests {
+            let act = process_wheres(&wheres, start);
+            assert_eq!(exp_where_str, act.0);
+            assert_eq!(exp_args, act.1);
+        }
+    }
+
+    #[test]
+    fn test_select_or() {
+        let dao = mock();
+        let w = vec![Where::or(vec![
+            Where::new("field1", Symbol::GT, Value::from(123)),
+            !Where::new("field3", Symbol::NEQ, Value::from("2021-01-01")),
+        ])];
+        let exp = vec![
+            HashMap::from([("field1".to_string(), Value::from(123))]),
+            HashMap::from([("field1".to_string(), Value::from(124))]),
+        ];
+        let act = dao
+            .select(&vec!["field1".into()], "test", &w, &QueryOptions::new())
+            .unwrap();
+        assert_eq!(exp, act);
+        let w = vec![Where::between("field1", Value::from(124), Value::from(200))];
+        let act = dao
+            .select(&vec!["field1".into()], "test", &w, &QueryOptions::new())
+            .unwrap();
+        assert_eq!(
+            vec![HashMap::from([("field1".to_string(), Value::from(124))])],
+            act
+        );
+    }
+
+    #[test]
+    fn test_process_wheres_in() {
+        let wheres = vec![
+            Where::new("x", Symbol::IN, Value::from(vec!["a", "b", "c"])),
+            Where::new("y", Symbol::IN, Value::from(vec![1, 2])),
+            Where::new("z", Symbol::IN, Value::IntegerList(vec![])),
+            Where::new("w", Symbol::EQ, Value::from(3)),
+        ];
+        let act = process_wheres(&wheres, 1);
+        assert_eq!(
+            " WHERE x IN (?2,?3,?4) AND y IN (?5,?6) AND z IN () AND w=?7",
+            act.0
+        );
+        assert_eq!(
+            vec![
+                Value::from("a"),
+                Value::from("b"),
+                Value::from("c"),
+                Value::from(1),
+                Value::from(2),
+                Value::from(3),
+            ],
+            act.1
+        );
+    }
+
+    #[test]
+    fn test_in() {
+        let dao = mock();
+        let w = vec![Where::new(
+            "field1",
+            Symbol::IN,
+            Value::from(vec![124, 125]),
+        )];
+        let act = dao
+            .select(&vec!["field1".into()], "test", &w, &QueryOptions::new())
+            .unwrap();
+        assert_eq!(
+            vec![HashMap::from([("field1".to_string(), Value::from(124))])],
+            act
+        );
+
+        let w = vec![Where::new(
+            "field3",
+            Symbol::IN,
+            Value::from(vec!["2021-01-01", ""]),
+        )];
+        let res = dao.update(
+            &vec!["field2".into()],
+            "test",
+            vec![Value::from("updated")],
+            &w,
+        );
+        assert_eq!(Ok(2), res);
+
+        let w = vec![
+            Where::new("field2", Symbol::EQ, Value::from("updated")),
+            Where::new("field1", Symbol::IN, Value::from(vec![123])),
+        ];
+        assert_eq!(Ok(1), dao.delete("test", &w));
+        let act = dao
+            .select(
+                &vec!["field1".into()],
+                "test",
+                &vec![],
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![HashMap::from([("field1".to_string(), Value::from(124))])],
+            act
+        );
+    }
+
+    #[test]
+    fn test_aggregate_string() {
+        let aggregates = vec![
+            Aggregate::count("n"),
+            Aggregate::new(Function::Avg, "correct", "average"),
+        ];
+        assert_eq!(
+            "SELECT id,COUNT(*) AS n,AVG(correct) AS average FROM safmed",
+            aggregate_string("safmed", &aggregates, &["id".into()])
+        );
+        assert_eq!(
+            "SELECT COUNT(*) AS n,AVG(correct) AS average FROM safmed",
+            aggregate_string("safmed", &aggregates, &[])
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let dao = mock();
+        dao.execute("INSERT INTO test VALUES (200, 'bye', '2021-01-01')")
+            .unwrap();
+        let aggregates = vec![
+            Aggregate::count("n"),
+            Aggregate::new(Function::Sum, "field1", "total"),
+            Aggregate::new(Function::Avg, "field1", "average"),
+            Aggregate::new(Function::Min, "field1", "lowest"),
+            Aggregate::new(Function::Max, "field3", "latest"),
+        ];
+        let act = dao
+            .aggregate(
+                "test",
+                &aggregates,
+                &vec!["field2".into()],
+                &vec![Where::new("field1", Symbol::GT, Value::from(0))],
+                &vec![Where::new("n", Symbol::GT, Value::from(1))],
+            )
+            .unwrap();
+        assert_eq!(
+            vec![Record::from([
+                ("field2".to_string(), Value::from("hello")),
+                ("n".to_string(), Value::from(2)),
+                ("total".to_string(), Value::from(247)),
+                ("average".to_string(), Value::from(123.5)),
+                ("lowest".to_string(), Value::from(123)),
+                ("latest".to_string(), Value::from("2021-01-01")),
+            ])],
+            act
+        );
+        let act = dao
+            .aggregate(
+                "test",
+                &vec![Aggregate::count("n")],
+                &vec![],
+                &vec![],
+                &vec![],
+            )
+            .unwrap();
+        assert_eq!(vec![Record::from([("n".to_string(), Value::from(3))])], act);
+        assert_eq!(
+            Err(Error::UnknownColumn("test".into(), "field3".into())),
+            dao.aggregate(
+                "test",
+                &aggregates,
+                &vec!["field2".into()],
+                &vec![],
+                &vec![Where::new("field3", Symbol::EQ, Value::from("x"))],
+            )
+        );
+    }
+
     #[test]
     fn test_select_string() {
         let fields = vec!["one".to_owned(), "two".to_owned(), "three".to_owned()];
         let table = "table";
         let exp = "SELECT one,two,three FROM table".to_string();
-        let act = select_string(&fields, table);
+        let act = select_string(&fields, table, false);
+        assert_eq!(exp, act);
+        let exp = "SELECT DISTINCT one,two,three FROM table".to_string();
+        let act = select_string(&fields, table, true);
         assert_eq!(exp, act);
     }
 
+    #[test]
+    fn test_options_string() {
+        let tests = vec![
+            (QueryOptions::new(), ""),
+            (
+                QueryOptions::new()
+                    .order_by("one", Order::Asc)
+                    .order_by("two", Order::Desc),
+                " ORDER BY one ASC,two DESC",
+            ),
+            (QueryOptions::new().limit(10), " LIMIT 10"),
+            (
+                QueryOptions::new().limit(10).offset(20),
+                " LIMIT 10 OFFSET 20",
+            ),
+            (QueryOptions::new().offset(20), " LIMIT -1 OFFSET 20"),
+        ];
+        for (options, exp) in tests {
+            assert_eq!(exp, options_string(&options));
+        }
+    }
+
+    #[test]
+    fn test_select_options() {
+        let dao = mock();
+        let fields = vec!["field1".into()];
+        let options = QueryOptions::new().order_by("field1", Order::Desc).limit(1);
+        let act = dao.select(&fields, "test", &vec![], &options).unwrap();
+        assert_eq!(
+            vec![HashMap::from([("field1".to_string(), Value::from(124))])],
+            act
+        );
+        let options = QueryOptions::new()
+            .order_by("field1", Order::Desc)
+            .offset(1);
+        let act = dao.select(&fields, "test", &vec![], &options).unwrap();
+        assert_eq!(
+            vec![HashMap::from([("field1".to_string(), Value::from(123))])],
+            act
+        );
+        let options = QueryOptions::new().distinct();
+        let act = dao
+            .select(&vec!["field2".into()], "test", &vec![], &options)
+            .unwrap();
+        assert_eq!(
+            vec![HashMap::from([(
+                "field2".to_string(),
+                Value::from("hello")
+            )])],
+            act
+        );
+    }
+
     #[test]
     fn test_insert_string() {
         let tests = vec![
@@ -506,6 +1460,7 @@ mod tests {
                 false,
                 Err(Error::DbError(
                     "number of args doesn't work with number of records".into(),
+                    Source::none(),
                 )),
             ),
             (
@@ -532,6 +1487,60 @@ mod tests {
         assert_eq!(exp, act);
     }
 
+    #[test]
+    fn test_backups() {
+        let dir = std::env::temp_dir().join(format!("scorg-test-{}", uuid::Uuid::new_v4()));
+        let dao = mock().with_backups(Backups::new(&dir, 5));
+        // test exists but the schema isn't versioned yet, so it gets migrated
+        dao.init().unwrap();
+        let snapshots = dao.snapshots().unwrap();
+        assert_eq!(1, snapshots.len());
+        dao.delete("test", &vec![]).unwrap();
+        let name = snapshots[0].file_name().unwrap().to_str().unwrap();
+        assert_eq!(Ok(snapshots[0].clone()), dao.restore(name));
+        // the snapshot was taken before migrating, restoring migrates it again
+        let version = migrations::schema_version(&dao.conn.lock().borrow()).unwrap();
+        assert_eq!(migrations::latest_version(), version);
+        let all = dao
+            .select(
+                &vec!["field1".into()],
+                "test",
+                &vec![],
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(2, all.len());
+        // a brand new database has nothing worth keeping
+        let new = SqliteDao::new(":memory:")
+            .unwrap()
+            .with_backups(Backups::new(&dir, 5));
+        new.init().unwrap();
+        assert_eq!(1, new.snapshots().unwrap().len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_keys_without_init() {
+        let dao = SqliteDao::new(":memory:").unwrap();
+        let guard = dao.conn.lock();
+        let foreign_keys: bool = guard
+            .borrow()
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[test]
+    fn test_restore_inside_transaction() {
+        let dir = std::env::temp_dir().join(format!("scorg-test-{}", uuid::Uuid::new_v4()));
+        let dao = mock().with_backups(Backups::new(&dir, 5));
+        let name = dao.backup("test").unwrap().unwrap();
+        let name = name.file_name().unwrap().to_str().unwrap();
+        let res = dao.transaction(&mut |_| dao.restore(name).map(|_| ()));
+        assert_matches!(res, Err(Error::BackupError(_)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_delete_string() {
         let exp = String::from("DELETE FROM test");
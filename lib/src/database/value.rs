@@ -1,8 +1,8 @@
 use crate::{
     errors::{Error, Result},
-    useful::{date_from_str, date_to_str},
+    useful::{date_from_str, date_to_str, datetime_from_str, datetime_to_str},
 };
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use rusqlite::types::Value as RVal;
 use rusqlite::{
     types::{FromSql, ToSqlOutput},
@@ -11,8 +11,13 @@ use rusqlite::{
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Value {
+    Null,
     Text(String),
     Integer(i32),
+    Real(f64),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Blob(Vec<u8>),
     TextList(Vec<String>),
     IntegerList(Vec<i32>),
 }
@@ -33,13 +38,23 @@ impl Value {
             )),
         }
     }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
 }
 
 impl ToSql for Value {
-    fn to_sql(&self) -> std::result::Result<ToSqlOutput, SqlError> {
+    fn to_sql(&self) -> std::result::Result<ToSqlOutput<'_>, SqlError> {
         match self {
+            Self::Null => Ok(ToSqlOutput::Owned(RVal::Null)),
             Self::Integer(i) => Ok(ToSqlOutput::Owned(RVal::Integer(*i as i64))),
+            Self::Real(r) => Ok(ToSqlOutput::Owned(RVal::Real(*r))),
             Self::Text(t) => Ok(ToSqlOutput::Owned(RVal::Text(t.clone()))),
+            // dates are stored as TEXT so they sort and compare correctly in sql
+            Self::Date(d) => Ok(ToSqlOutput::Owned(RVal::Text(date_to_str(*d)))),
+            Self::DateTime(dt) => Ok(ToSqlOutput::Owned(RVal::Text(datetime_to_str(*dt)))),
+            Self::Blob(b) => Ok(ToSqlOutput::Owned(RVal::Blob(b.clone()))),
             _ => Err(SqlError::ToSqlConversionFailure(Box::new(ValueError::new(
                 "Unable to convert to sql",
             )))),
@@ -50,14 +65,14 @@ impl ToSql for Value {
 impl FromSql for Value {
     fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
         match value {
-            rusqlite::types::ValueRef::Null => todo!(),
+            rusqlite::types::ValueRef::Null => Ok(Value::Null),
             rusqlite::types::ValueRef::Integer(i) => Ok(Value::from(i as i32)),
-            rusqlite::types::ValueRef::Real(_) => Err(rusqlite::types::FromSqlError::InvalidType),
-            rusqlite::types::ValueRef::Text(t) => unsafe {
-                // unsafe because unchecked conversion
-                Ok(Value::from(String::from_utf8_unchecked(t.to_vec())))
+            rusqlite::types::ValueRef::Real(r) => Ok(Value::from(r)),
+            rusqlite::types::ValueRef::Text(t) => match std::str::from_utf8(t) {
+                Ok(t) => Ok(Value::from(t)),
+                Err(e) => Err(rusqlite::types::FromSqlError::Other(Box::new(e))),
             },
-            rusqlite::types::ValueRef::Blob(_) => Err(rusqlite::types::FromSqlError::InvalidType),
+            rusqlite::types::ValueRef::Blob(b) => Ok(Value::from(b.to_vec())),
         }
     }
 }
@@ -107,9 +122,55 @@ impl From<Vec<&str>> for Value {
     }
 }
 
+impl From<Vec<String>> for Value {
+    fn from(val: Vec<String>) -> Self {
+        Value::TextList(val)
+    }
+}
+
+impl From<Vec<i32>> for Value {
+    fn from(val: Vec<i32>) -> Self {
+        Value::IntegerList(val)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(val: f64) -> Self {
+        Value::Real(val)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(val: Vec<u8>) -> Self {
+        Value::Blob(val)
+    }
+}
+
 impl From<NaiveDate> for Value {
     fn from(val: NaiveDate) -> Self {
-        Value::Text(date_to_str(val))
+        Value::Date(val)
+    }
+}
+
+impl From<NaiveDateTime> for Value {
+    fn from(val: NaiveDateTime) -> Self {
+        Value::DateTime(val)
+    }
+}
+
+// sqlite has no booleans, they're stored as 0 and 1
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Integer(b as i32)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(val: Option<T>) -> Self {
+        match val {
+            Some(v) => v.into(),
+            None => Value::Null,
+        }
     }
 }
 
@@ -117,10 +178,7 @@ impl TryInto<NaiveDate> for Value {
     type Error = Error;
 
     fn try_into(self) -> Result<NaiveDate> {
-        match self {
-            Value::Text(date_string) => date_from_str(&date_string),
-            _ => Err(Error::ValueError("Not a date".to_string())),
-        }
+        (&self).try_into()
     }
 }
 
@@ -129,7 +187,8 @@ impl TryInto<NaiveDate> for &Value {
 
     fn try_into(self) -> Result<NaiveDate> {
         match self {
-            Value::Text(date_string) => date_from_str(&date_string),
+            Value::Date(d) => Ok(*d),
+            Value::Text(date_string) => date_from_str(date_string),
             _ => Err(Error::ValueError("Not a date".to_string())),
         }
     }
@@ -189,3 +248,178 @@ impl TryInto<Vec<String>> for &Value {
         }
     }
 }
+
+impl TryInto<NaiveDateTime> for &Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<NaiveDateTime> {
+        match self {
+            Value::DateTime(dt) => Ok(*dt),
+            Value::Text(datetime_string) => datetime_from_str(datetime_string),
+            _ => Err(Error::ValueError("Not a datetime".to_string())),
+        }
+    }
+}
+
+impl TryInto<NaiveDateTime> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<NaiveDateTime> {
+        (&self).try_into()
+    }
+}
+
+impl TryInto<f64> for &Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<f64> {
+        match self {
+            Value::Real(r) => Ok(*r),
+            Value::Integer(n) => Ok(*n as f64),
+            _ => Err(Error::ValueError("Not an f64".to_string())),
+        }
+    }
+}
+
+impl TryInto<f64> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<f64> {
+        (&self).try_into()
+    }
+}
+
+impl TryInto<Vec<u8>> for Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Vec<u8>> {
+        match self {
+            Value::Blob(b) => Ok(b),
+            _ => Err(Error::ValueError("Not a Vec<u8>".to_string())),
+        }
+    }
+}
+
+impl TryInto<Vec<u8>> for &Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<Vec<u8>> {
+        match self {
+            Value::Blob(b) => Ok(b.clone()),
+            _ => Err(Error::ValueError("Not a Vec<u8>".to_string())),
+        }
+    }
+}
+
+impl TryInto<bool> for &Value {
+    type Error = Error;
+
+    fn try_into(self) -> Result<bool> {
+        match self {
+            Value::Integer(i) => Ok(*i != 0),
+            _ => Err(Error::ValueError("Not a bool".to_string())),
+        }
+    }
+}
+
+// NULL columns become None, anything else has to convert to T
+impl<'a, T> TryInto<Option<T>> for &'a Value
+where
+    &'a Value: TryInto<T, Error = Error>,
+{
+    type Error = Error;
+
+    fn try_into(self) -> Result<Option<T>> {
+        match self {
+            Value::Null => Ok(None),
+            v => Ok(Some(TryInto::<T>::try_into(v)?)),
+        }
+    }
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::useful::date_from_str;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE test (field)", []).unwrap();
+        let date = date_from_str("2021-01-01").unwrap();
+        let datetime = date.and_hms_opt(9, 30, 0).unwrap();
+        let tests = vec![
+            (Value::Null, Value::Null),
+            (Value::from(12), Value::from(12)),
+            (Value::from(1.5), Value::from(1.5)),
+            (Value::from("text"), Value::from("text")),
+            (Value::from(vec![0_u8, 1, 2]), Value::from(vec![0_u8, 1, 2])),
+            (Value::from(date), Value::from("2021-01-01")),
+            (Value::from(datetime), Value::from("2021-01-01 09:30:00")),
+        ];
+        for (value, exp) in tests {
+            conn.execute("DELETE FROM test", []).unwrap();
+            conn.execute("INSERT INTO test VALUES (?1)", [&value])
+                .unwrap();
+            let act: Value = conn
+                .query_row("SELECT field FROM test", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(exp, act);
+        }
+    }
+
+    #[test]
+    fn test_lists_dont_convert_to_sql() {
+        assert!(Value::from(vec![1, 2]).to_sql().is_err());
+    }
+
+    #[test]
+    fn test_dates() {
+        let date = date_from_str("2021-01-01").unwrap();
+        let datetime = date.and_hms_opt(9, 30, 0).unwrap();
+        let as_date: Result<NaiveDate> = Value::from("2021-01-01").try_into();
+        assert_eq!(Ok(date), as_date);
+        let as_date: Result<NaiveDate> = Value::from(date).try_into();
+        assert_eq!(Ok(date), as_date);
+        let as_datetime: Result<NaiveDateTime> = Value::from("2021-01-01 09:30:00").try_into();
+        assert_eq!(Ok(datetime), as_datetime);
+        let as_date: Result<NaiveDate> = Value::from(3).try_into();
+        assert_eq!(Err(Error::ValueError("Not a date".into())), as_date);
+    }
+
+    #[test]
+    fn test_reals() {
+        let real: Result<f64> = Value::from(2.5).try_into();
+        assert_eq!(Ok(2.5), real);
+        let real: Result<f64> = Value::from(2).try_into();
+        assert_eq!(Ok(2.0), real);
+        let real: Result<f64> = Value::from("2").try_into();
+        assert!(real.is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(Value::Null, Value::from(None::<i32>));
+        assert_eq!(Value::from(3), Value::from(Some(3)));
+        let none: Result<Option<String>> = (&Value::Null).try_into();
+        assert_eq!(Ok(None), none);
+        let some: Result<Option<String>> = (&Value::from("notes")).try_into();
+        assert_eq!(Ok(Some("notes".to_string())), some);
+        let bad: Result<Option<i32>> = (&Value::from("notes")).try_into();
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn test_bools() {
+        assert_eq!(Value::Integer(1), Value::from(true));
+        let b: Result<bool> = (&Value::from(false)).try_into();
+        assert_eq!(Ok(false), b);
+        let b: Result<bool> = (&Value::from("true")).try_into();
+        assert!(b.is_err());
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::database::{column, Dao, Order, QueryOptions, Record, Symbol, Value, Where};
+use crate::errors::Result;
+use crate::useful::{date_to_str, datetime_to_str};
+use chrono::{NaiveDate, NaiveDateTime};
+use log::*;
+use serde::Serialize;
+
+/// Something wrong with the data that the schema doesn't stop from happening.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Problem {
+    /// A score whose student doesn't exist.
+    OrphanedScore { id: String, date: String },
+    /// A date that doesn't parse, in the row of `table` for student `id`.
+    BadDate {
+        table: String,
+        id: String,
+        field: String,
+        value: String,
+    },
+    /// Students whose names only differ by case or whitespace, the first id
+    /// is the one the others get merged into.
+    DuplicateStudents { name: String, ids: Vec<String> },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::OrphanedScore { id, date } => {
+                write!(f, "score on {date} belongs to missing student {id}")
+            }
+            Problem::BadDate {
+                table,
+                id,
+                field,
+                value,
+            } => write!(f, "{table} {id} has a bad {field}: {value:?}"),
+            Problem::DuplicateStudents { name, ids } => {
+                write!(f, "{name} is in more than once: {}", ids.join(", "))
+            }
+        }
+    }
+}
+
+/// Finds, and can fix, problems in the data.
+pub struct Doctor {
+    dao: Arc<dyn Dao>,
+}
+
+impl Doctor {
+    pub fn new(dao: Arc<dyn Dao>) -> Doctor {
+        Doctor { dao }
+    }
+
+    /// Looks through the database for problems without changing anything.
+    pub fn check(&self) -> Result<Vec<Problem>> {
+        let students = self.dao.select(
+            &fields(&[
+                "id",
+                "first_names",
+                "last_name",
+                "date_of_birth",
+                "archived_at",
+                "start_date",
+                "leave_date",
+            ]),
+            "student",
+            &vec![],
+            &QueryOptions::new().order_by("id", Order::Asc),
+        )?;
+        let scores = self.dao.select(
+            &fields(&["id", "date"]),
+            "safmed",
+            &vec![],
+            &QueryOptions::new()
+                .order_by("id", Order::Asc)
+                .order_by("date", Order::Asc),
+        )?;
+        let mut problems = Vec::new();
+        let ids: HashSet<String> = students.iter().map(|s| text(&s["id"])).collect();
+        for score in &scores {
+            if !ids.contains(&text(&score["id"])) {
+                problems.push(Problem::OrphanedScore {
+                    id: text(&score["id"]),
+                    date: text(&score["date"]),
+                });
+            }
+        }
+        for student in &students {
+            problems.extend(bad_date(student, "student", "date_of_birth", false));
+            problems.extend(bad_date(student, "student", "archived_at", true));
+            problems.extend(bad_date(student, "student", "start_date", true));
+            problems.extend(bad_date(student, "student", "leave_date", true));
+        }
+        for score in &scores {
+            problems.extend(bad_date(score, "safmed", "date", false));
+        }
+        problems.extend(duplicates(&students));
+        debug!("found {} problems", problems.len());
+        Ok(problems)
+    }
+
+    /// Fixes what it can after taking a snapshot, giving back the problems it
+    /// couldn't fix. Orphaned scores are deleted, dates in other common
+    /// formats are rewritten and duplicate students are merged into one.
+    pub fn repair(&self, problems: &[Problem]) -> Result<Vec<Problem>> {
+        if problems.is_empty() {
+            return Ok(vec![]);
+        }
+        self.dao.backup("doctor")?;
+        let mut unfixed = Vec::new();
+        self.dao.transaction(&mut |_| {
+            unfixed.clear();
+            for problem in problems {
+                if !self.repair_one(problem)? {
+                    unfixed.push(problem.clone());
+                }
+            }
+            Ok(())
+        })?;
+        info!(
+            "fixed {} of {} problems",
+            problems.len() - unfixed.len(),
+            problems.len()
+        );
+        Ok(unfixed)
+    }
+
+    fn repair_one(&self, problem: &Problem) -> Result<bool> {
+        match problem {
+            Problem::OrphanedScore { id, date } => {
+                let wheres = vec![
+                    Where::new("id", Symbol::EQ, id.as_str().into()),
+                    Where::new("date", Symbol::EQ, date.as_str().into()),
+                ];
+                self.dao.delete("safmed", &wheres)?;
+                Ok(true)
+            }
+            Problem::BadDate {
+                table,
+                id,
+                field,
+                value,
+            } => {
+                let fixed = match field.as_str() {
+                    "archived_at" => fix_datetime(value),
+                    _ => fix_date(value),
+                };
+                let fixed = match fixed {
+                    Some(fixed) => fixed,
+                    None => return Ok(false),
+                };
+                let wheres = vec![
+                    Where::new("id", Symbol::EQ, id.as_str().into()),
+                    Where::new(field, Symbol::EQ, value.as_str().into()),
+                ];
+                // the fixed date can clash with a score already on that day
+                match self
+                    .dao
+                    .update(&vec![field.clone()], table, vec![fixed.into()], &wheres)
+                {
+                    Ok(_) => Ok(true),
+                    Err(e) => {
+                        warn!("couldn't fix {problem}: {e}");
+                        Ok(false)
+                    }
+                }
+            }
+            Problem::DuplicateStudents { ids, .. } => {
+                let keeper = &ids[0];
+                let mut last_seqs: HashMap<(String, String), i32> = HashMap::new();
+                for (deck, date, seq) in self.timings(keeper)? {
+                    let last = last_seqs.entry((deck, date)).or_insert(0);
+                    *last = (*last).max(seq);
+                }
+                for duplicate in &ids[1..] {
+                    // every timing moves over, going after the keeper's
+                    // timings on the same deck and day
+                    for (deck, date, seq) in self.timings(duplicate)? {
+                        let last = last_seqs.entry((deck.clone(), date.clone())).or_insert(0);
+                        *last += 1;
+                        self.dao.update(
+                            &fields(&["id", "seq"]),
+                            "safmed",
+                            vec![keeper.as_str().into(), (*last).into()],
+                            &timing_wheres("id", duplicate, &deck, &date, seq),
+                        )?;
+                        self.dao.update(
+                            &fields(&["student_id", "seq"]),
+                            "card_outcome",
+                            vec![keeper.as_str().into(), (*last).into()],
+                            &timing_wheres("student_id", duplicate, &deck, &date, seq),
+                        )?;
+                    }
+                    let wheres = vec![Where::new("id", Symbol::EQ, duplicate.as_str().into())];
+                    self.dao.delete("student", &wheres)?;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    // the deck, date and seq of each of the student's timings
+    fn timings(&self, id: &str) -> Result<Vec<(String, String, i32)>> {
+        let records = self.dao.select(
+            &fields(&["deck_id", "date", "seq"]),
+            "safmed",
+            &vec![Where::new("id", Symbol::EQ, id.into())],
+            &QueryOptions::new().order_by("seq", Order::Asc),
+        )?;
+        records
+            .iter()
+            .map(|r| {
+                Ok((
+                    text(&r["deck_id"]),
+                    text(&r["date"]),
+                    column::<i32>(r, "seq")?,
+                ))
+            })
+            .collect()
+    }
+}
+
+fn timing_wheres(id_field: &str, id: &str, deck: &str, date: &str, seq: i32) -> Vec<Where> {
+    vec![
+        Where::new(id_field, Symbol::EQ, id.into()),
+        Where::new("deck_id", Symbol::EQ, deck.into()),
+        Where::new("date", Symbol::EQ, date.into()),
+        Where::new("seq", Symbol::EQ, seq.into()),
+    ]
+}
+
+fn fields(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Text(t) => t.clone(),
+        Value::Null => String::new(),
+        other => format!("{other:?}"),
+    }
+}
+
+fn bad_date(record: &Record, table: &str, field: &str, nullable: bool) -> Option<Problem> {
+    let value = &record[field];
+    let ok = match (value, field) {
+        (Value::Null, _) => nullable,
+        (Value::Text(t), "archived_at") => {
+            NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S").is_ok()
+        }
+        (Value::Text(t), _) => NaiveDate::parse_from_str(t, "%Y-%m-%d").is_ok(),
+        _ => false,
+    };
+    match ok {
+        true => None,
+        false => Some(Problem::BadDate {
+            table: table.into(),
+            id: text(&record["id"]),
+            field: field.into(),
+            value: text(value),
+        }),
+    }
+}
+
+fn duplicates(students: &[Record]) -> Vec<Problem> {
+    let mut groups: Vec<(String, String, Vec<&Record>)> = Vec::new();
+    for student in students {
+        let name = format!(
+            "{} {}",
+            text(&student["first_names"]),
+            text(&student["last_name"])
+        );
+        let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let key = name.to_lowercase();
+        match groups.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, members)) => members.push(student),
+            None => groups.push((key, name, vec![student])),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, _, members)| members.len() > 1)
+        .map(|(_, name, mut members)| {
+            // keep an active student over archived ones
+            members.sort_by_key(|m| !m["archived_at"].is_null());
+            Problem::DuplicateStudents {
+                name,
+                ids: members.iter().map(|m| text(&m["id"])).collect(),
+            }
+        })
+        .collect()
+}
+
+fn fix_date(value: &str) -> Option<String> {
+    ["%Y-%m-%d", "%Y/%m/%d", "%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value.trim(), format).ok())
+        .map(date_to_str)
+}
+
+fn fix_datetime(value: &str) -> Option<String> {
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value.trim(), format).ok())
+        .map(datetime_to_str)
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant::DEFAULT_DECK;
+    use crate::database::MemoryDao;
+
+    fn dao_with(students: &[(&str, &str, &str)], scores: &[(&str, &str)]) -> Arc<dyn Dao> {
+        let dao: Arc<dyn Dao> = Arc::new(MemoryDao::new());
+        for (id, name, dob) in students {
+            dao.insert(
+                &fields(&["id", "first_names", "last_name", "date_of_birth"]),
+                "student",
+                vec![(*id).into(), (*name).into(), "Jones".into(), (*dob).into()],
+                false,
+            )
+            .unwrap();
+        }
+        for (id, date) in scores {
+            dao.insert(
+                &fields(&["id", "correct", "incorrect", "date", "deck_id", "seq"]),
+                "safmed",
+                vec![
+                    (*id).into(),
+                    50.into(),
+                    2.into(),
+                    (*date).into(),
+                    DEFAULT_DECK.into(),
+                    1.into(),
+                ],
+                false,
+            )
+            .unwrap();
+        }
+        dao
+    }
+
+    #[test]
+    fn test_healthy() {
+        let dao = dao_with(&[("st1", "Ben", "1990-01-23")], &[("st1", "2022-01-01")]);
+        assert_eq!(Ok(vec![]), Doctor::new(dao).check());
+    }
+
+    #[test]
+    fn test_orphaned_scores() {
+        let dao = dao_with(&[("st1", "Ben", "1990-01-23")], &[("gone", "2022-01-01")]);
+        let doctor = Doctor::new(Arc::clone(&dao));
+        let problems = doctor.check().unwrap();
+        assert_eq!(
+            vec![Problem::OrphanedScore {
+                id: "gone".into(),
+                date: "2022-01-01".into()
+            }],
+            problems
+        );
+        assert_eq!(Ok(vec![]), doctor.repair(&problems));
+        assert_eq!(Ok(vec![]), doctor.check());
+    }
+
+    #[test]
+    fn test_bad_dates() {
+        let dao = dao_with(
+            &[("st1", "Ben", "23/01/1990"), ("st2", "Sam", "whenever")],
+            &[("st1", " 2022/01/02"), ("st1", "2022-01-02")],
+        );
+        let doctor = Doctor::new(Arc::clone(&dao));
+        let problems = doctor.check().unwrap();
+        assert_eq!(3, problems.len());
+        let unfixed = doctor.repair(&problems).unwrap();
+        // whenever can't be fixed and the score would clash with the other
+        assert_eq!(2, unfixed.len());
+        assert_eq!(unfixed, doctor.check().unwrap());
+        let ben = dao
+            .select(
+                &fields(&["date_of_birth"]),
+                "student",
+                &vec![Where::new("id", Symbol::EQ, "st1".into())],
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(Value::from("1990-01-23"), ben[0]["date_of_birth"]);
+    }
+
+    #[test]
+    fn test_duplicate_students() {
+        let dao = dao_with(
+            &[("st1", "Ben", "1990-01-23"), ("st2", " ben ", "1990-01-23")],
+            &[
+                ("st1", "2022-01-01"),
+                ("st2", "2022-01-01"),
+                ("st2", "2022-01-02"),
+            ],
+        );
+        let doctor = Doctor::new(Arc::clone(&dao));
+        let problems = doctor.check().unwrap();
+        assert_eq!(
+            vec![Problem::DuplicateStudents {
+                name: "Ben Jones".into(),
+                ids: vec!["st1".into(), "st2".into()]
+            }],
+            problems
+        );
+        assert_eq!(Ok(vec![]), doctor.repair(&problems));
+        assert_eq!(Ok(vec![]), doctor.check());
+        let general = |date: &str, seq| (DEFAULT_DECK.to_string(), date.to_string(), seq);
+        assert_eq!(
+            vec![
+                general("2022-01-01", 1),
+                general("2022-01-01", 2),
+                general("2022-01-02", 1)
+            ],
+            {
+                let mut timings = doctor.timings("st1").unwrap();
+                timings.sort();
+                timings
+            }
+        );
+    }
+
+    #[test]
+    fn test_duplicate_timings_on_the_same_day() {
+        let dao = dao_with(
+            &[("st1", "Ben", "1990-01-23"), ("st2", "BEN", "1990-01-23")],
+            &[("st1", "2022-01-01"), ("st2", "2022-01-01")],
+        );
+        let timing = |id: &str, seq: i32, correct: i32| {
+            dao.insert(
+                &fields(&["id", "correct", "incorrect", "date", "deck_id", "seq"]),
+                "safmed",
+                vec![
+                    id.into(),
+                    correct.into(),
+                    2.into(),
+                    "2022-01-01".into(),
+                    DEFAULT_DECK.into(),
+                    seq.into(),
+                ],
+                false,
+            )
+            .unwrap()
+        };
+        timing("st1", 2, 60);
+        timing("st2", 2, 70);
+        dao.insert(
+            &fields(&["student_id", "deck_id", "date", "seq", "card_id", "correct"]),
+            "card_outcome",
+            vec![
+                "st2".into(),
+                DEFAULT_DECK.into(),
+                "2022-01-01".into(),
+                2.into(),
+                "c1".into(),
+                1.into(),
+            ],
+            false,
+        )
+        .unwrap();
+        let doctor = Doctor::new(Arc::clone(&dao));
+        assert_eq!(Ok(vec![]), doctor.repair(&doctor.check().unwrap()));
+        // the duplicate's timings go after the keeper's, in their own order
+        let timings = dao
+            .select(
+                &fields(&["correct", "seq"]),
+                "safmed",
+                &vec![Where::new("id", Symbol::EQ, "st1".into())],
+                &QueryOptions::new().order_by("seq", Order::Asc),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![(50, 1), (60, 2), (50, 3), (70, 4)],
+            timings
+                .iter()
+                .map(|t| (
+                    column::<i32>(t, "correct").unwrap(),
+                    column::<i32>(t, "seq").unwrap()
+                ))
+                .collect::<Vec<(i32, i32)>>()
+        );
+        let outcomes = dao
+            .select(
+                &fields(&["student_id", "seq"]),
+                "card_outcome",
+                &vec![],
+                &QueryOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(1, outcomes.len());
+        assert_eq!(Value::from("st1"), outcomes[0]["student_id"]);
+        assert_eq!(Value::from(4), outcomes[0]["seq"]);
+    }
+}
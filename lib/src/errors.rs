@@ -1,38 +1,200 @@
-use serde::Serialize;
+use rusqlite::ffi;
+use serde::{Serialize, Serializer};
+use std::sync::Arc;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The error underneath one of ours, e.g. the rusqlite::Error a DbError came
+/// from, given back by Error::source. Errors compare equal whatever their
+/// sources, which can't be compared themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Source(Option<Arc<dyn std::error::Error + Send + Sync>>);
+
+impl Source {
+    pub fn new<E: std::error::Error + Send + Sync + 'static>(e: E) -> Self {
+        Source(Some(Arc::new(e)))
+    }
+
+    /// For errors we raise ourselves.
+    pub fn none() -> Self {
+        Source(None)
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, _: &Source) -> bool {
+        true
+    }
+}
+
+// Serialized as {"kind": ..., "detail": ...} so the app can tell the kinds of
+// error apart without parsing messages.
 #[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "detail")]
 pub enum Error {
-    DbError(String),
-    NoStudent,
+    /// A database failure that isn't one of the more specific kinds below.
+    #[serde(serialize_with = "message")]
+    DbError(String, Source),
+    /// Whatever was looked for, e.g. "student Ben Jones".
+    NotFound(String),
+    /// A write clashed with a unique constraint on these columns of table.
+    Duplicate {
+        table: String,
+        columns: Vec<String>,
+        #[serde(skip)]
+        source: Source,
+    },
+    #[serde(serialize_with = "message")]
+    ForeignKeyViolation(String, Source),
+    /// Input that breaks a rule, as opposed to input that can't be read.
+    ValidationError(String),
     ValueError(String),
     FieldArgMismatch,
     BadDateConversion(String),
     ImporterError(String),
+    /// Something wrong in a csv file, at the line and field (both from 1)
+    /// where they're known.
+    Csv {
+        line: Option<u64>,
+        field: Option<usize>,
+        source: Box<Error>,
+    },
     ParseIntError(String),
-    NoScoresToPlot
+    #[serde(serialize_with = "message")]
+    IoError(String, Source),
+    NoScoresToPlot,
+    UnsupportedSchemaVersion(u32, u32),
+    ConfigError(String),
+    BackupError(String),
+    UnknownTable(String),
+    UnknownColumn(String, String),
+    InvalidIdentifier(String),
+    /// The username or password was wrong, without saying which.
+    LoginFailed,
+    /// The logged in user isn't allowed to do this, or nobody is logged in.
+    PermissionDenied(String),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::DbError(s) => write!(f, "DbError: {s}"),
-            Error::NoStudent => write!(f, "No Student found in DB"),
+            Error::DbError(s, _) => write!(f, "DbError: {s}"),
+            Error::NotFound(s) => write!(f, "NotFound: {s}"),
+            Error::Duplicate { table, columns, .. } => write!(
+                f,
+                "Duplicate: there's already a {table} with that {}",
+                columns.join(" and ")
+            ),
+            Error::ForeignKeyViolation(s, _) => write!(f, "ForeignKeyViolation: {s}"),
+            Error::ValidationError(s) => write!(f, "ValidationError: {s}"),
             Error::ValueError(s) => write!(f, "ValueError: {s}"),
             Error::FieldArgMismatch => write!(f, "FieldArgMismatch"),
             Error::BadDateConversion(s) => write!(f, "BadDateConversion: {s}"),
             Error::ImporterError(s) => write!(f, "ImporterError: {s}"),
+            Error::Csv {
+                line,
+                field,
+                source,
+            } => {
+                write!(f, "Csv")?;
+                if let Some(line) = line {
+                    write!(f, " line {line}")?;
+                }
+                if let Some(field) = field {
+                    write!(f, " field {field}")?;
+                }
+                write!(f, ": {source}")
+            }
             Error::ParseIntError(s) => write!(f, "ParseIntError: {s}"),
+            Error::IoError(s, _) => write!(f, "IoError: {s}"),
             Error::NoScoresToPlot => write!(f, "No scores found to plot"),
+            Error::UnsupportedSchemaVersion(found, supported) => write!(
+                f,
+                "UnsupportedSchemaVersion: database is at version {found} but only {supported} is supported"
+            ),
+            Error::ConfigError(s) => write!(f, "ConfigError: {s}"),
+            Error::BackupError(s) => write!(f, "BackupError: {s}"),
+            Error::UnknownTable(t) => write!(f, "UnknownTable: no table called {t:?}"),
+            Error::UnknownColumn(t, c) => write!(f, "UnknownColumn: {t} has no column {c:?}"),
+            Error::InvalidIdentifier(s) => write!(f, "InvalidIdentifier: {s:?}"),
+            Error::LoginFailed => write!(f, "LoginFailed: wrong username or password"),
+            Error::PermissionDenied(s) => write!(f, "PermissionDenied: {s}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Csv { source, .. } => Some(source.as_ref()),
+            Error::DbError(_, Source(Some(source)))
+            | Error::Duplicate {
+                source: Source(Some(source)),
+                ..
+            }
+            | Error::ForeignKeyViolation(_, Source(Some(source)))
+            | Error::IoError(_, Source(Some(source))) => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// the source stays out of what the app is sent, which only gets the message
+fn message<S: Serializer>(
+    message: &str,
+    _: &Source,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(message)
+}
+
+impl Error {
+    /// Places an error found reading a csv file.
+    pub fn at(self, line: Option<u64>, field: Option<usize>) -> Error {
+        Error::Csv {
+            line,
+            field,
+            source: Box::new(self),
         }
     }
 }
 
 impl From<rusqlite::Error> for Error {
     fn from(re: rusqlite::Error) -> Error {
-        let e_string = re.to_string();
-        Error::DbError(e_string.to_owned())
+        if let rusqlite::Error::SqliteFailure(e, Some(message)) = &re {
+            match e.extended_code {
+                ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY => {
+                    let message = message.clone();
+                    return duplicate(&message, re);
+                }
+                ffi::SQLITE_CONSTRAINT_FOREIGNKEY => {
+                    return Error::ForeignKeyViolation(message.clone(), Source::new(re))
+                }
+                _ => (),
+            }
+        }
+        Error::DbError(re.to_string(), Source::new(re))
+    }
+}
+
+// sqlite words these as "UNIQUE constraint failed: table.column, table.column"
+fn duplicate(message: &str, e: rusqlite::Error) -> Error {
+    let mut table = String::new();
+    let mut columns = Vec::new();
+    let failed = message.split_once(": ").map_or("", |(_, failed)| failed);
+    for column in failed.split(", ") {
+        if let Some((t, c)) = column.split_once('.') {
+            table = t.to_string();
+            columns.push(c.to_string());
+        }
+    }
+    match columns.is_empty() {
+        true => Error::DbError(message.to_string(), Source::new(e)),
+        false => Error::Duplicate {
+            table,
+            columns,
+            source: Source::new(e),
+        },
     }
 }
 
@@ -52,7 +214,113 @@ impl From<std::num::ParseIntError> for Error {
 
 impl From<csv::Error> for Error {
     fn from(e: csv::Error) -> Error {
-        let e_string = e.to_string();
-        Error::ParseIntError(e_string.to_owned())
+        let line = e.position().map(|p| p.line());
+        let source = match e.is_io_error() {
+            true => Error::IoError(e.to_string(), Source::new(e)),
+            false => Error::ImporterError(e.to_string()),
+        };
+        source.at(line, None)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::IoError(e.to_string(), Source::new(e))
+    }
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_constraint_violations() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys=on;
+            CREATE TABLE parent (id TEXT PRIMARY KEY, a TEXT, b TEXT, UNIQUE(a, b));
+            CREATE TABLE child (id TEXT REFERENCES parent(id));
+            INSERT INTO parent VALUES ('p1', 'x', 'y');",
+        )
+        .unwrap();
+        let err: Error = conn
+            .execute("INSERT INTO parent VALUES ('p2', 'x', 'y')", [])
+            .unwrap_err()
+            .into();
+        assert_eq!(
+            Error::Duplicate {
+                table: "parent".into(),
+                columns: vec!["a".into(), "b".into()],
+                source: Source::none(),
+            },
+            err
+        );
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.downcast_ref::<rusqlite::Error>().is_some());
+        let err: Error = conn
+            .execute("INSERT INTO parent VALUES ('p1', 'z', 'z')", [])
+            .unwrap_err()
+            .into();
+        assert_eq!(
+            Error::Duplicate {
+                table: "parent".into(),
+                columns: vec!["id".into()],
+                source: Source::none(),
+            },
+            err
+        );
+        let err: Error = conn
+            .execute("INSERT INTO child VALUES ('nope')", [])
+            .unwrap_err()
+            .into();
+        assert!(matches!(err, Error::ForeignKeyViolation(..)));
+        let err: Error = conn.execute("SELECT nonsense", []).unwrap_err().into();
+        assert!(matches!(err, Error::DbError(..)));
+        // the rusqlite error is kept underneath
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.downcast_ref::<rusqlite::Error>().is_some());
+    }
+
+    #[test]
+    fn test_csv_location() {
+        let data = "a,b\n1,2\n3";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let err: Error = reader
+            .records()
+            .collect::<std::result::Result<Vec<_>, csv::Error>>()
+            .unwrap_err()
+            .into();
+        assert!(matches!(
+            &err,
+            Error::Csv { line: Some(3), field: None, source } if matches!(**source, Error::ImporterError(_))
+        ));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let err = Error::Duplicate {
+            table: "student".into(),
+            columns: vec!["first_names".into(), "last_name".into()],
+            source: Source::none(),
+        };
+        assert_eq!(
+            r#"{"kind":"Duplicate","detail":{"table":"student","columns":["first_names","last_name"]}}"#,
+            serde_json::to_string(&err).unwrap()
+        );
+        assert_eq!(
+            r#"{"kind":"NotFound","detail":"student st1"}"#,
+            serde_json::to_string(&Error::NotFound("student st1".into())).unwrap()
+        );
+        let err: Error = std::io::Error::other("disk full").into();
+        assert_eq!(
+            r#"{"kind":"IoError","detail":"disk full"}"#,
+            serde_json::to_string(&err).unwrap()
+        );
     }
 }
@@ -1,34 +1,66 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::constant::{DEFAULT_DECK, DEFAULT_DURATION};
+use crate::database::{Dao, MemoryDao, QueryOptions, Repository};
 use crate::errors::{Error, Result};
-use crate::models::{SafmedScore, Student};
-use crate::services::{SafmedScoreService, StudentService};
+use crate::models::{Deck, SafmedScore, Student};
+use crate::services::{DeckService, SafmedScoreService, StudentService};
 use crate::useful::*;
 use csv::{Reader, StringRecord};
 
 pub struct Importer {
+    dao: Arc<dyn Dao>,
     student_service: Arc<StudentService>,
     score_service: Arc<SafmedScoreService>,
+    decks: DeckService,
 }
 use log::*;
 
+/// The optional columns that can come between a student's date of birth and
+/// the first date, in any order. All but deck and duration are details of the
+/// student, deck names the deck the row's scores are on, so a student can have
+/// a row for each of their decks, and duration how many seconds the row's
+/// timings lasted.
+const DETAIL_COLUMNS: [&str; 8] = [
+    "upn",
+    "class",
+    "notes",
+    "start_date",
+    "leave_date",
+    "active",
+    "deck",
+    "duration",
+];
+
 impl Importer {
     pub fn new(
+        dao: Arc<dyn Dao>,
         student_service: Arc<StudentService>,
         score_service: Arc<SafmedScoreService>,
     ) -> Importer {
         log::debug!("created new Importer");
         Importer {
+            decks: DeckService::new(Arc::clone(&dao)),
+            dao,
             student_service,
             score_service,
         }
     }
 
-    fn parse_scores(record: Vec<&str>, id: &str, dates: Vec<&str>) -> Result<Vec<SafmedScore>> {
+    fn parse_scores(
+        record: Vec<&str>,
+        id: &str,
+        deck_id: &str,
+        duration: i32,
+        dates: Vec<&str>,
+        first_field: usize,
+    ) -> Result<Vec<SafmedScore>> {
         let parsed_scores: Result<Vec<Option<(i32, i32, String)>>> = record
             .into_iter()
             .zip(dates)
-            .map(Self::parse_score)
+            .enumerate()
+            .map(|(i, cell)| Self::parse_score(cell).map_err(|e| e.at(None, Some(i + first_field))))
             .collect();
         let parsed_scores = match parsed_scores {
             Err(err) => return Err(err),
@@ -39,7 +71,14 @@ impl Importer {
             match score {
                 Some(sc) => {
                     let new_score = SafmedScore::new(id, sc.0, sc.1, &sc.2)?;
-                    scores.push(new_score);
+                    // a file has a score a day, taken as the day's first
+                    // timing so importing it again replaces it
+                    scores.push(SafmedScore {
+                        deck_id: deck_id.to_string(),
+                        duration,
+                        seq: 1,
+                        ..new_score
+                    });
                 }
                 None => continue,
             };
@@ -85,35 +124,111 @@ impl Importer {
             Some(s) => {
                 let name = s.trim().to_owned();
                 if name.is_empty() {
-                    return Err(Error::ImporterError("blank first names".into()));
+                    return Err(Error::ImporterError("blank first names".into()).at(None, Some(1)));
                 }
                 name
             }
-            None => return Err(Error::ImporterError("no first names found".into())),
+            None => {
+                return Err(Error::ImporterError("no first names found".into()).at(None, Some(1)))
+            }
         };
         let last_name = match record.get(1) {
             Some(s) => {
                 let name = s.trim().to_owned();
                 if name.is_empty() {
-                    return Err(Error::ImporterError("blank last name".into()));
+                    return Err(Error::ImporterError("blank last name".into()).at(None, Some(2)));
                 }
                 name
             }
-            None => return Err(Error::ImporterError("no last name found".into())),
+            None => return Err(Error::ImporterError("no last name found".into()).at(None, Some(2))),
         };
         let dob = match record.get(2) {
             Some(s) => {
                 let name = s.trim().to_owned();
                 if name.is_empty() {
-                    return Err(Error::ImporterError("blank dob".into()));
+                    return Err(Error::ImporterError("blank dob".into()).at(None, Some(3)));
                 }
                 name
             }
-            None => return Err(Error::ImporterError("no dob found".into())),
+            None => return Err(Error::ImporterError("no dob found".into()).at(None, Some(3))),
         };
         Ok((first_names, last_name, dob))
     }
 
+    /// The detail columns at the start of the file, lower cased.
+    fn detail_columns(headers: &StringRecord) -> Vec<String> {
+        headers
+            .iter()
+            .skip(3)
+            .map(|h| h.trim().to_lowercase())
+            .take_while(|h| DETAIL_COLUMNS.contains(&h.as_str()))
+            .collect()
+    }
+
+    // Blank cells leave the detail as it is on a new student.
+    fn add_details(student: &mut Student, columns: &[String], record: &StringRecord) -> Result<()> {
+        for (i, column) in columns.iter().enumerate() {
+            let cell = record.get(i + 3).unwrap_or_default().trim();
+            if cell.is_empty() {
+                continue;
+            }
+            let at = |e: Error| e.at(None, Some(i + 4));
+            match column.as_str() {
+                "upn" => student.upn = Some(cell.to_string()),
+                "class" => student.class = Some(cell.to_string()),
+                "notes" => student.notes = cell.to_string(),
+                "start_date" => student.start_date = Some(date_from_str(cell).map_err(at)?),
+                "leave_date" => student.leave_date = Some(date_from_str(cell).map_err(at)?),
+                "active" => student.active = Self::parse_active(cell).map_err(at)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    // The id of the deck named in the row's deck column, the general deck if
+    // there isn't one or it's blank.
+    fn deck_id(&self, columns: &[String], record: &StringRecord) -> Result<String> {
+        let i = match columns.iter().position(|c| c == "deck") {
+            Some(i) => i,
+            None => return Ok(DEFAULT_DECK.into()),
+        };
+        match record.get(i + 3).unwrap_or_default().trim() {
+            "" => Ok(DEFAULT_DECK.into()),
+            name => match self.decks.get_by_name(name) {
+                Ok(deck) => Ok(deck.id),
+                Err(e) => Err(e.at(None, Some(i + 4))),
+            },
+        }
+    }
+
+    // The seconds in the row's duration column, a minute if there isn't one
+    // or it's blank.
+    fn duration(columns: &[String], record: &StringRecord) -> Result<i32> {
+        let i = match columns.iter().position(|c| c == "duration") {
+            Some(i) => i,
+            None => return Ok(DEFAULT_DURATION),
+        };
+        match record.get(i + 3).unwrap_or_default().trim() {
+            "" => Ok(DEFAULT_DURATION),
+            cell => match cell.parse::<i32>() {
+                Ok(seconds) if seconds > 0 => Ok(seconds),
+                _ => Err(
+                    Error::ImporterError(format!("{cell} should be a number of seconds"))
+                        .at(None, Some(i + 4)),
+                ),
+            },
+        }
+    }
+
+    fn parse_active(cell: &str) -> Result<bool> {
+        match cell.to_lowercase().as_str() {
+            "yes" | "y" | "true" | "1" => Ok(true),
+            "no" | "n" | "false" | "0" => Ok(false),
+            _ => Err(Error::ImporterError(format!("{cell} should be yes or no"))),
+        }
+    }
+
     fn get_id(
         &self,
         first_names: &str,
@@ -127,57 +242,138 @@ impl Importer {
             // if there is one, just get it's id
             Ok(id) => Ok((id, None)),
             // add the student to students_to_add and get its id if not
-            Err(_) => {
+            Err(Error::NotFound(_)) => {
                 let new_student = Student::new(&first_names, &last_name, &dob)?;
                 let new_id = new_student.id.clone();
                 Ok((new_id, Some(new_student)))
             }
+            Err(e) => Err(e),
         }
     }
 
     pub fn import(&self, data: &str) -> Result<(usize, usize)> {
         let mut reader = Reader::from_reader(data.as_bytes());
         let headers = reader.headers()?.clone();
-        let dates: Vec<&str> = headers.into_iter().skip(3).collect();
+        let details = Self::detail_columns(&headers);
+        let skip = 3 + details.len();
+        let dates: Vec<&str> = headers.into_iter().skip(skip).collect();
         let records = reader.records();
         let mut students_to_add: Vec<Student> = vec![];
+        // new students can have a row for each deck, they're only added once
+        let mut new_ids: HashMap<(String, String), String> = HashMap::new();
         let mut scores_to_add: Vec<SafmedScore> = vec![];
         for record in records {
             let r = record?;
-            let (first_names, last_name, dob) = Self::extract_data(&r)?;
+            let line = r.position().map(|p| p.line());
+            let (first_names, last_name, dob) =
+                Self::extract_data(&r).map_err(|e| on_line(e, line))?;
             debug!("found {} {}", &first_names, &last_name);
-            let (id, new_student) = self.get_id(&first_names, &last_name, &dob)?;
-            if new_student.is_some() {
-                debug!("will add {} {} as new student", &first_names, &last_name);
-                students_to_add.push(new_student.unwrap())
+            let name = (first_names.clone(), last_name.clone());
+            let id = match new_ids.get(&name) {
+                Some(id) => id.clone(),
+                None => {
+                    let (id, new_student) = self.get_id(&first_names, &last_name, &dob)?;
+                    // students already in the database keep the details they have
+                    if let Some(mut new_student) = new_student {
+                        debug!("will add {} {} as new student", &first_names, &last_name);
+                        Self::add_details(&mut new_student, &details, &r)
+                            .map_err(|e| on_line(e, line))?;
+                        new_ids.insert(name, id.clone());
+                        students_to_add.push(new_student)
+                    };
+                    id
+                }
             };
-            let scores_in_record = r.into_iter().skip(3).collect();
-            let scores = Self::parse_scores(scores_in_record, &id, dates.clone())?;
+            let deck_id = self.deck_id(&details, &r).map_err(|e| on_line(e, line))?;
+            let duration = Self::duration(&details, &r).map_err(|e| on_line(e, line))?;
+            let scores_in_record = r.into_iter().skip(skip).collect();
+            let scores = Self::parse_scores(
+                scores_in_record,
+                &id,
+                &deck_id,
+                duration,
+                dates.clone(),
+                skip + 1,
+            )
+            .map_err(|e| on_line(e, line))?;
             scores_to_add.extend(scores);
         }
         let (mut students_added, mut scores_added) = (0, 0);
-        if students_to_add.len() > 0_usize {
-            debug!("adding {} new students", &students_to_add.len());
-            students_added = self.student_service.add_students(&students_to_add)?;
-        }
-        if scores_to_add.len() > 0 {
-            debug!("adding {} scores", &scores_to_add.len());
-            scores_added = self.score_service.add_scores(&scores_to_add)?;
+        if !students_to_add.is_empty() || !scores_to_add.is_empty() {
+            self.dao.backup("import")?;
         }
+        // either everything in the file goes in or nothing does
+        self.dao.transaction(&mut |_| {
+            if !students_to_add.is_empty() {
+                debug!("adding {} new students", &students_to_add.len());
+                students_added = self.student_service.add_students(&students_to_add)?;
+            }
+            if !scores_to_add.is_empty() {
+                debug!("adding {} scores", &scores_to_add.len());
+                scores_added = self.score_service.add_scores(&scores_to_add)?;
+            }
+            Ok(())
+        })?;
         debug!(
             "added {} students and {} scores",
             students_added, scores_added
         );
         Ok((students_added, scores_added))
     }
+
+    /// Works out how many students and scores importing data would add, by
+    /// running the import against a throwaway copy of the existing students.
+    /// Archived students are copied too, as the import matches them by name.
+    pub fn preview(&self, data: &str) -> Result<(usize, usize)> {
+        let dao: Arc<dyn Dao> = Arc::new(MemoryDao::new());
+        let student_service = Arc::new(StudentService::new(Arc::clone(&dao)));
+        let score_service = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
+        let existing =
+            Repository::<Student>::new(Arc::clone(&self.dao)).list(&vec![], &QueryOptions::new())?;
+        Repository::<Student>::new(Arc::clone(&dao)).insert(&existing)?;
+        Repository::<Deck>::new(Arc::clone(&dao)).upsert(&self.decks.all()?)?;
+        Importer::new(dao, student_service, score_service).import(data)
+    }
+}
+
+// Fills in the line of the file an error was found on, keeping the field if
+// it's already known.
+fn on_line(e: Error, line: Option<u64>) -> Error {
+    match e {
+        Error::Csv { field, source, .. } => Error::Csv {
+            line,
+            field,
+            source,
+        },
+        e => e.at(line, None),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::{dao::MockDao, Dao, Value};
+    use crate::backup::Backups;
+    use crate::database::{dao::MockDao, Aggregate, Dao, MemoryDao, SqliteDao, Value};
+    use crate::models::Role;
+    use crate::services::{Session, UserService};
+    use chrono::NaiveDate;
+    use matches::assert_matches;
     use std::collections::HashMap;
 
+    const CSV: &str = "\
+first_names,last_name,date_of_birth,2021-02-01,2021-02-02
+Ben,Jones,1990-01-23,89/23,78/21
+Gemma Victoria,Mercer-Forbes,1988-08-30,98/12,";
+
+    fn sqlite_importer() -> (Arc<dyn Dao>, Importer) {
+        let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        dao.init().unwrap();
+        let student_service = Arc::new(StudentService::new(Arc::clone(&dao)));
+        let score_service = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
+        let importer = Importer::new(Arc::clone(&dao), student_service, score_service);
+        (dao, importer)
+    }
+
     #[test]
     fn test_parse_score() {
         let tests: Vec<((&str, &str), Result<Option<(i32, i32, String)>>)> = vec![
@@ -225,12 +421,18 @@ mod tests {
             "st1",
             vec!["2021-01-01", "2021-01-02"],
             Ok(vec![
-                SafmedScore::new("st1", 67, 23, "2021-01-01").unwrap(),
-                SafmedScore::new("st1", 89, 34, "2021-01-02").unwrap(),
+                SafmedScore {
+                    seq: 1,
+                    ..SafmedScore::new("st1", 67, 23, "2021-01-01").unwrap()
+                },
+                SafmedScore {
+                    seq: 1,
+                    ..SafmedScore::new("st1", 89, 34, "2021-01-02").unwrap()
+                },
             ]),
         )];
         for (rec, id, dates, exp) in tests {
-            let actual = Importer::parse_scores(rec, id, dates);
+            let actual = Importer::parse_scores(rec, id, DEFAULT_DECK, DEFAULT_DURATION, dates, 4);
             assert_eq!(exp, actual);
         }
     }
@@ -244,11 +446,11 @@ mod tests {
             ),
             (
                 StringRecord::from(vec!["", "Jones", ""]),
-                Err(Error::ImporterError("blank first names".into())),
+                Err(Error::ImporterError("blank first names".into()).at(None, Some(1))),
             ),
             (
                 StringRecord::from(vec!["Ben", "", ""]),
-                Err(Error::ImporterError("blank last name".into())),
+                Err(Error::ImporterError("blank last name".into()).at(None, Some(2))),
             ),
         ];
         for (sr, exp) in tests {
@@ -256,10 +458,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_import_error_location() {
+        let (_, importer) = sqlite_importer();
+        let csv = format!("{CSV}\nSam,Smith,1990-02-02,50/1,oops");
+        assert_eq!(
+            Err(Error::Csv {
+                line: Some(4),
+                field: Some(5),
+                source: Box::new(Error::ImporterError(
+                    "must provide 2 scores per date".into()
+                )),
+            }),
+            importer.import(&csv)
+        );
+        let csv = "first_names,last_name,date_of_birth\nSam,,1990-02-02";
+        assert_matches!(
+            importer.import(csv),
+            Err(Error::Csv {
+                line: Some(2),
+                field: Some(2),
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_get_id_existing_student() {
         let mut dao = MockDao::new();
-        dao.expect_select().returning(move |_, _, _| {
+        dao.expect_select().returning(move |_, _, _, _| {
             Ok(vec![HashMap::from([(
                 "id".to_owned(),
                 Value::from("existing_id"),
@@ -268,7 +495,7 @@ mod tests {
         let arc_dao: Arc<dyn Dao> = Arc::new(dao);
         let student_service = Arc::new(StudentService::new(Arc::clone(&arc_dao)));
         let score_service = Arc::new(SafmedScoreService::new(Arc::clone(&arc_dao)));
-        let importer = Importer::new(student_service, score_service);
+        let importer = Importer::new(arc_dao, student_service, score_service);
         let actual = importer.get_id("Ben", "Jones", "1999-01-23").unwrap();
         assert_eq!(actual.0, "existing_id".to_owned());
         assert!(actual.1.is_none());
@@ -278,13 +505,226 @@ mod tests {
     fn test_get_id_new_student() {
         let mut dao = MockDao::new();
         dao.expect_select()
-            .returning(move |_, _, _| Err(Error::NoStudent));
+            .returning(move |_, _, _, _| Err(Error::NotFound("student".into())));
         let arc_dao: Arc<dyn Dao> = Arc::new(dao);
         let student_service = Arc::new(StudentService::new(Arc::clone(&arc_dao)));
         let score_service = Arc::new(SafmedScoreService::new(Arc::clone(&arc_dao)));
-        let importer = Importer::new(student_service, score_service);
+        let importer = Importer::new(arc_dao, student_service, score_service);
         let actual = importer.get_id("Ben", "Jones", "1990-01-23").unwrap();
         assert!(!actual.0.is_empty());
         assert_eq!(actual.0, actual.1.unwrap().id);
     }
+
+    #[test]
+    fn test_import() {
+        let (dao, importer) = sqlite_importer();
+        assert_eq!(Ok((2, 3)), importer.import(CSV));
+        let students = StudentService::new(Arc::clone(&dao)).all().unwrap();
+        assert_eq!(2, students.len());
+    }
+
+    #[test]
+    fn test_import_details() {
+        let (dao, importer) = sqlite_importer();
+        let csv = "\
+first_names,last_name,date_of_birth,UPN,class,active,start_date,2021-02-01
+Ben,Jones,1990-01-23,A123456789012,7B,no,2021-09-01,89/23
+Sam,Smith,1990-02-02,,,,,50/1";
+        assert_eq!(Ok((2, 2)), importer.import(csv));
+        let students = StudentService::new(Arc::clone(&dao));
+        let ben = students
+            .get(&students.get_id_for_name("Ben", "Jones").unwrap())
+            .unwrap();
+        assert_eq!(Some("A123456789012".into()), ben.upn);
+        assert_eq!(Some("7B".into()), ben.class);
+        assert!(!ben.active);
+        assert_eq!(date_from_str("2021-09-01").ok(), ben.start_date);
+        let sam = students
+            .get(&students.get_id_for_name("Sam", "Smith").unwrap())
+            .unwrap();
+        assert_eq!((None, true), (sam.upn, sam.active));
+        let csv = "first_names,last_name,date_of_birth,active\nAl,Lee,1990-02-02,maybe";
+        assert_matches!(
+            importer.import(csv),
+            Err(Error::Csv {
+                line: Some(2),
+                field: Some(4),
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_import_decks() {
+        let (dao, importer) = sqlite_importer();
+        let decks = DeckService::new(Arc::clone(&dao));
+        let phonics = Deck::new("Phonics", "reading", 40, "").unwrap();
+        decks.add_deck(&phonics).unwrap();
+        let csv = "\
+first_names,last_name,date_of_birth,class,deck,2021-02-01,2021-02-02
+Ben,Jones,1990-01-23,7B,,89/23,78/21
+Ben,Jones,1990-01-23,,Phonics,30/2,
+Sam,Smith,1990-02-02,7B,phonics,50/1,";
+        // deck names have to match exactly
+        assert_matches!(
+            importer.preview(csv),
+            Err(Error::Csv {
+                line: Some(4),
+                field: Some(5),
+                ..
+            })
+        );
+        let csv = csv.replace("phonics", "Phonics");
+        assert_eq!(Ok((2, 4)), importer.preview(&csv));
+        assert_eq!(Ok((2, 4)), importer.import(&csv));
+        let students = StudentService::new(Arc::clone(&dao));
+        let ben = students.get_id_for_name("Ben", "Jones").unwrap();
+        let scores = SafmedScoreService::new(Arc::clone(&dao));
+        assert_eq!(3, scores.get_safmed_scores(&ben).unwrap().len());
+        let on_phonics = scores.get_deck_scores(&ben, &phonics.id).unwrap();
+        assert_eq!(vec![30], on_phonics.iter().map(|s| s.correct).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_import_durations() {
+        let (dao, importer) = sqlite_importer();
+        let csv = "\
+first_names,last_name,date_of_birth,duration,2021-02-01,2021-02-02
+Ben,Jones,1990-01-23,30,20/2,
+Sam,Smith,1990-02-02,,50/1,
+Amy,Brown,1990-03-03,half,40/1,";
+        assert_matches!(
+            importer.import(csv),
+            Err(Error::Csv {
+                line: Some(4),
+                field: Some(4),
+                ..
+            })
+        );
+        let csv = csv.replace("half", "120");
+        assert_eq!(Ok((3, 3)), importer.import(&csv));
+        let students = StudentService::new(Arc::clone(&dao));
+        let scores = SafmedScoreService::new(Arc::clone(&dao));
+        let rates: Vec<f64> = [("Ben", "Jones"), ("Sam", "Smith"), ("Amy", "Brown")]
+            .iter()
+            .map(|(first, last)| {
+                let id = students.get_id_for_name(first, last).unwrap();
+                scores.get_safmed_scores(&id).unwrap()[0].correct_per_minute()
+            })
+            .collect();
+        assert_eq!(vec![40.0, 50.0, 20.0], rates);
+    }
+
+    #[test]
+    fn test_import_a_whole_year() {
+        let (dao, importer) = sqlite_importer();
+        let start = NaiveDate::from_ymd_opt(2021, 9, 1).unwrap();
+        let dates: Vec<String> = (0..150)
+            .map(|d| date_to_str(start + chrono::Duration::days(d)))
+            .collect();
+        let mut csv = format!("first_names,last_name,date_of_birth,{}", dates.join(","));
+        for s in 0..200 {
+            let scores = vec!["50/2"; dates.len()].join(",");
+            csv.push_str(&format!("\nStudent,Number{s},2010-01-01,{scores}"));
+        }
+        assert_eq!(Ok((200, 30_000)), importer.import(&csv));
+        let count = dao
+            .aggregate(
+                "safmed",
+                &vec![Aggregate::count("n")],
+                &vec![],
+                &vec![],
+                &vec![],
+            )
+            .unwrap();
+        assert_eq!(Value::from(30_000), count[0]["n"]);
+    }
+
+    #[test]
+    fn test_import_into_memory() {
+        let dao: Arc<dyn Dao> = Arc::new(MemoryDao::new());
+        let students = Arc::new(StudentService::new(Arc::clone(&dao)));
+        let scores = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
+        let importer = Importer::new(Arc::clone(&dao), Arc::clone(&students), Arc::clone(&scores));
+        assert_eq!(Ok((2, 3)), importer.import(CSV));
+        let ben = students.get_id_for_name("Ben", "Jones").unwrap();
+        assert_eq!(2, scores.get_safmed_scores(&ben).unwrap().len());
+        // importing again matches the students that are already there
+        assert_eq!(Ok((0, 3)), importer.import(CSV));
+        assert_eq!(2, students.all().unwrap().len());
+    }
+
+    #[test]
+    fn test_preview() {
+        let (dao, importer) = sqlite_importer();
+        let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+        StudentService::new(Arc::clone(&dao))
+            .add_student(&ben)
+            .unwrap();
+        assert_eq!(Ok((1, 3)), importer.preview(CSV));
+        // nothing was actually saved
+        let students = StudentService::new(Arc::clone(&dao)).all().unwrap();
+        assert_eq!(1, students.len());
+        let scores = SafmedScoreService::new(Arc::clone(&dao));
+        assert!(scores.get_safmed_scores(&ben.id).unwrap().is_empty());
+        // archived students are matched as they would be by the import
+        let gemma = Student::new("Gemma Victoria", "Mercer-Forbes", "1988-08-30").unwrap();
+        let students = StudentService::new(Arc::clone(&dao));
+        students.add_student(&gemma).unwrap();
+        students.archive(&gemma.id).unwrap();
+        assert_eq!(Ok((0, 3)), importer.preview(CSV));
+        assert_eq!(Ok((0, 3)), importer.import(CSV));
+    }
+
+    #[test]
+    fn test_import_takes_a_snapshot() {
+        let dir = std::env::temp_dir().join(format!("scorg-test-{}", uuid::Uuid::new_v4()));
+        let sqlite = SqliteDao::new(":memory:")
+            .unwrap()
+            .with_backups(Backups::new(&dir, 5));
+        sqlite.init().unwrap();
+        let dao: Arc<dyn Dao> = Arc::new(sqlite);
+        let student_service = Arc::new(StudentService::new(Arc::clone(&dao)));
+        let score_service = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
+        let importer = Importer::new(Arc::clone(&dao), student_service, score_service);
+        importer.import(CSV).unwrap();
+        let snapshots: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(1, snapshots.len());
+        assert!(snapshots[0].ends_with("-import.db"));
+    }
+
+    #[test]
+    fn test_import_someone_elses_student() {
+        let dao: Arc<dyn Dao> = Arc::new(MemoryDao::new());
+        let session = Session::new();
+        let users = UserService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+        users.add_user("admin", "pw", Role::Admin).unwrap();
+        users.login("admin", "pw").unwrap();
+        users.add_user("teacher", "pw", Role::Teacher).unwrap();
+        let students = StudentService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+        students
+            .add_student(&Student::new("Ben", "Jones", "1990-01-23").unwrap())
+            .unwrap();
+        users.login("teacher", "pw").unwrap();
+        let student_service = Arc::new(students);
+        let score_service =
+            Arc::new(SafmedScoreService::new(Arc::clone(&dao)).with_session(session));
+        let importer = Importer::new(Arc::clone(&dao), student_service, score_service);
+        // Ben is there, he just isn't the teacher's
+        assert_matches!(importer.import(CSV), Err(Error::PermissionDenied(_)));
+    }
+
+    #[test]
+    fn test_import_is_all_or_nothing() {
+        let (dao, importer) = sqlite_importer();
+        // make the score insert fail after the students have gone in
+        dao.execute("DROP TABLE safmed").unwrap();
+        assert!(importer.import(CSV).is_err());
+        let students = StudentService::new(Arc::clone(&dao)).all().unwrap();
+        assert!(students.is_empty());
+    }
 }
@@ -1,8 +1,12 @@
+pub mod backup;
+pub mod config;
 pub mod constant;
 pub mod database;
+pub mod doctor;
 pub mod errors;
 pub mod importer;
 pub mod models;
 pub mod plotter;
+pub mod search;
 pub mod services;
 pub mod useful;
@@ -0,0 +1,176 @@
+use crate::database::{column, Entity, Record, Value};
+use crate::errors::{Error, Result};
+use crate::useful::curr_datetime;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Insert => write!(f, "insert"),
+            Operation::Update => write!(f, "update"),
+            Operation::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+impl TryFrom<&str> for Operation {
+    type Error = Error;
+    fn try_from(s: &str) -> Result<Self> {
+        match s {
+            "insert" => Ok(Operation::Insert),
+            "update" => Ok(Operation::Update),
+            "delete" => Ok(Operation::Delete),
+            _ => Err(Error::ValueError(format!("{s} is not an operation"))),
+        }
+    }
+}
+
+/// One change to one row. `old_value` and `new_value` hold the row as json
+/// before and after the change, None for the side of an insert or delete that
+/// didn't exist. `user_id` is whoever was logged in, None if nobody was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub table_name: String,
+    pub row_key: String,
+    pub student_id: String,
+    pub operation: Operation,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: NaiveDateTime,
+    pub user_id: Option<String>,
+}
+
+impl AuditEntry {
+    /// An entry for a change happening now, working out the operation from
+    /// which of old and new there are.
+    pub fn new<T: Serialize>(
+        table_name: &str,
+        row_key: &str,
+        student_id: &str,
+        old: Option<&T>,
+        new: Option<&T>,
+    ) -> Result<Self> {
+        let operation = match (&old, &new) {
+            (None, Some(_)) => Operation::Insert,
+            (Some(_), Some(_)) => Operation::Update,
+            (Some(_), None) => Operation::Delete,
+            (None, None) => return Err(Error::ValueError("nothing changed".into())),
+        };
+        Ok(AuditEntry {
+            table_name: table_name.into(),
+            row_key: row_key.into(),
+            student_id: student_id.into(),
+            operation,
+            old_value: old.map(to_json).transpose()?,
+            new_value: new.map(to_json).transpose()?,
+            changed_at: curr_datetime(),
+            user_id: None,
+        })
+    }
+}
+
+impl Entity for AuditEntry {
+    const TABLE: &'static str = "audit";
+    const COLUMNS: &'static [&'static str] = &[
+        "table_name",
+        "row_key",
+        "student_id",
+        "operation",
+        "old_value",
+        "new_value",
+        "changed_at",
+        "user_id",
+    ];
+    // entries are only ever added and listed, never looked up one at a time
+    const KEY: &'static [&'static str] = &[];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.table_name.clone().into(),
+            self.row_key.clone().into(),
+            self.student_id.clone().into(),
+            self.operation.to_string().into(),
+            self.old_value.clone().into(),
+            self.new_value.clone().into(),
+            self.changed_at.into(),
+            self.user_id.clone().into(),
+        ]
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| Error::ValueError(e.to_string()))
+}
+
+impl TryFrom<Record> for AuditEntry {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        Ok(AuditEntry {
+            table_name: column(&rec, "table_name")?,
+            row_key: column(&rec, "row_key")?,
+            student_id: column(&rec, "student_id")?,
+            operation: Operation::try_from(column::<String>(&rec, "operation")?.as_str())?,
+            old_value: column(&rec, "old_value")?,
+            new_value: column(&rec, "new_value")?,
+            changed_at: column(&rec, "changed_at")?,
+            user_id: column(&rec, "user_id")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_audit {
+    use super::*;
+    use crate::models::Student;
+    use crate::useful::datetime_from_str;
+
+    #[test]
+    fn test_new() {
+        let s = Student::new("first", "last", "1990-01-23").unwrap();
+        let entry = AuditEntry::new("student", &s.id, &s.id, None, Some(&s)).unwrap();
+        assert_eq!(Operation::Insert, entry.operation);
+        assert_eq!(None, entry.old_value);
+        assert_eq!(Some(serde_json::to_string(&s).unwrap()), entry.new_value);
+        let entry = AuditEntry::new("student", &s.id, &s.id, Some(&s), None).unwrap();
+        assert_eq!(Operation::Delete, entry.operation);
+        assert!(AuditEntry::new::<Student>("student", "", "", None, None).is_err());
+    }
+
+    #[test]
+    fn test_try_from() {
+        let rec = Record::from([
+            ("table_name".into(), "safmed".into()),
+            ("row_key".into(), "st1 2021-01-01".into()),
+            ("student_id".into(), "st1".into()),
+            ("operation".into(), "update".into()),
+            ("old_value".into(), "{}".into()),
+            ("new_value".into(), Value::Null),
+            ("changed_at".into(), "2021-01-01 10:11:12".into()),
+            ("user_id".into(), "u1".into()),
+        ]);
+        let exp = AuditEntry {
+            table_name: "safmed".into(),
+            row_key: "st1 2021-01-01".into(),
+            student_id: "st1".into(),
+            operation: Operation::Update,
+            old_value: Some("{}".into()),
+            new_value: None,
+            changed_at: datetime_from_str("2021-01-01 10:11:12").unwrap(),
+            user_id: Some("u1".into()),
+        };
+        assert_eq!(Ok(exp), AuditEntry::try_from(rec));
+        let rec = Record::from([("table_name".into(), "safmed".into())]);
+        assert_eq!(
+            Err(Error::ValueError("Missing row_key".into())),
+            AuditEntry::try_from(rec)
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::database::{column, Entity, Record, Value};
+use crate::errors::{Error, Result};
+use crate::models::{SafmedScore, Student};
+use crate::useful::*;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A class or intervention group that students can be members of.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Group {
+    pub fn new(name: &str, description: &str) -> Self {
+        Group {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
+
+impl Entity for Group {
+    const TABLE: &'static str = "groups";
+    const COLUMNS: &'static [&'static str] = &["id", "name", "description"];
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.id.clone().into(),
+            self.name.clone().into(),
+            self.description.clone().into(),
+        ]
+    }
+}
+
+impl TryFrom<Record> for Group {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        Ok(Group {
+            id: column(&rec, "id")?,
+            name: column(&rec, "name")?,
+            description: column(&rec, "description")?,
+        })
+    }
+}
+
+/// A spell of a student being in a group. Students who leave and rejoin get
+/// a membership for each time, so together they're the group's history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Membership {
+    pub group_id: String,
+    pub student_id: String,
+    pub joined_on: NaiveDate,
+    /// None while the student is still in the group.
+    pub left_on: Option<NaiveDate>,
+}
+
+impl Membership {
+    pub fn new(group_id: &str, student_id: &str, joined_on: &str) -> Result<Self> {
+        Ok(Membership {
+            group_id: group_id.to_string(),
+            student_id: student_id.to_string(),
+            joined_on: date_from_str(joined_on)?,
+            left_on: None,
+        })
+    }
+}
+
+impl Entity for Membership {
+    const TABLE: &'static str = "student_group";
+    const COLUMNS: &'static [&'static str] = &["group_id", "student_id", "joined_on", "left_on"];
+    const KEY: &'static [&'static str] = &["group_id", "student_id", "joined_on"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.group_id.clone().into(),
+            self.student_id.clone().into(),
+            self.joined_on.into(),
+            self.left_on.into(),
+        ]
+    }
+}
+
+impl TryFrom<Record> for Membership {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        Ok(Membership {
+            group_id: column(&rec, "group_id")?,
+            student_id: column(&rec, "student_id")?,
+            joined_on: column(&rec, "joined_on")?,
+            left_on: column(&rec, "left_on")?,
+        })
+    }
+}
+
+/// A member of a group with their most recent score, if they have one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MemberScore {
+    pub student: Student,
+    pub latest: Option<SafmedScore>,
+}
+
+#[cfg(test)]
+mod test_group {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let group = Group::new("Year 4 maths", "Tuesday mornings");
+        let rec: Record = Group::columns()
+            .into_iter()
+            .zip(group.to_values())
+            .collect();
+        assert_eq!(Ok(group), Group::try_from(rec));
+        let membership = Membership {
+            left_on: date_from_str("2022-07-20").ok(),
+            ..Membership::new("g1", "st1", "2021-09-01").unwrap()
+        };
+        let rec: Record = Membership::columns()
+            .into_iter()
+            .zip(membership.to_values())
+            .collect();
+        assert_eq!(Ok(membership), Membership::try_from(rec));
+        assert_eq!(
+            Err(Error::ValueError("Missing left_on".into())),
+            Membership::try_from(Record::from([
+                ("group_id".into(), "g1".into()),
+                ("student_id".into(), "st1".into()),
+                ("joined_on".into(), "2021-09-01".into()),
+            ]))
+        );
+    }
+}
@@ -1,5 +1,13 @@
+pub mod audit;
+pub mod group;
 pub mod scores;
 pub mod student;
+pub mod user;
 
-pub use scores::safmed::SafmedScore;
+pub use audit::{AuditEntry, Operation};
+pub use group::{Group, MemberScore, Membership};
+pub use scores::card::{Card, CardOutcome, MissedCard};
+pub use scores::deck::Deck;
+pub use scores::safmed::{DayPolicy, SafmedScore, SafmedSummary};
 pub use student::Student;
+pub use user::{Assignment, Role, User};
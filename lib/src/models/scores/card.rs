@@ -0,0 +1,172 @@
+use crate::database::{column, Entity, Record, Value};
+use crate::errors::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// One card in a deck, with what's shown on the front and the answer on the
+/// back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Card {
+    pub id: String,
+    pub deck_id: String,
+    pub front: String,
+    #[serde(default)]
+    pub back: String,
+    /// Free text labels such as "7x" or "split digraph", for grouping cards.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Card {
+    pub fn new(deck_id: &str, front: &str, back: &str, tags: &[&str]) -> Result<Self> {
+        if front.trim().is_empty() {
+            return Err(Error::ValidationError("cards need a front".into()));
+        }
+        if tags.iter().any(|tag| tag.contains(',')) {
+            return Err(Error::ValidationError("tags can't contain commas".into()));
+        }
+        Ok(Card {
+            id: Uuid::new_v4().to_string(),
+            deck_id: deck_id.to_string(),
+            front: front.trim().to_string(),
+            back: back.trim().to_string(),
+            tags: tags
+                .iter()
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        })
+    }
+}
+
+impl Entity for Card {
+    const TABLE: &'static str = "card";
+    const COLUMNS: &'static [&'static str] = &["id", "deck_id", "front", "back", "tags"];
+    const KEY: &'static [&'static str] = &["id"];
+
+    // tags are kept in one column, separated by commas
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.id.clone().into(),
+            self.deck_id.clone().into(),
+            self.front.clone().into(),
+            self.back.clone().into(),
+            self.tags.join(",").into(),
+        ]
+    }
+}
+
+impl TryFrom<Record> for Card {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        let tags: String = column(&rec, "tags")?;
+        Ok(Card {
+            id: column(&rec, "id")?,
+            deck_id: column(&rec, "deck_id")?,
+            front: column(&rec, "front")?,
+            back: column(&rec, "back")?,
+            tags: tags
+                .split(',')
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+        })
+    }
+}
+
+/// Whether a student got a card right in one of their timings, which is the
+/// score with the same student, deck, date and seq.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardOutcome {
+    pub student_id: String,
+    pub deck_id: String,
+    pub date: NaiveDate,
+    pub seq: i32,
+    pub card_id: String,
+    pub correct: bool,
+}
+
+impl Entity for CardOutcome {
+    const TABLE: &'static str = "card_outcome";
+    const COLUMNS: &'static [&'static str] =
+        &["student_id", "deck_id", "date", "seq", "card_id", "correct"];
+    const KEY: &'static [&'static str] = &["student_id", "deck_id", "date", "seq", "card_id"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.student_id.clone().into(),
+            self.deck_id.clone().into(),
+            self.date.into(),
+            self.seq.into(),
+            self.card_id.clone().into(),
+            self.correct.into(),
+        ]
+    }
+}
+
+impl TryFrom<Record> for CardOutcome {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        Ok(CardOutcome {
+            student_id: column(&rec, "student_id")?,
+            deck_id: column(&rec, "deck_id")?,
+            date: column(&rec, "date")?,
+            seq: column(&rec, "seq")?,
+            card_id: column(&rec, "card_id")?,
+            correct: column(&rec, "correct")?,
+        })
+    }
+}
+
+/// A card a student has got wrong, with how often over the timings asked
+/// about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MissedCard {
+    pub card: Card,
+    pub missed: i32,
+    pub seen: i32,
+}
+
+#[cfg(test)]
+mod test_card {
+    use super::*;
+    use crate::useful::date_from_str;
+
+    #[test]
+    fn test_new() {
+        let card = Card::new("d1", " 7 x 8 ", "56", &["7x", " ", "hard "]).unwrap();
+        assert_eq!("7 x 8", card.front);
+        assert_eq!(vec!["7x", "hard"], card.tags);
+        assert_eq!(
+            Err(Error::ValidationError("cards need a front".into())),
+            Card::new("d1", "  ", "56", &[])
+        );
+        assert_eq!(
+            Err(Error::ValidationError("tags can't contain commas".into())),
+            Card::new("d1", "7 x 8", "56", &["7x,8x"])
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for tags in [vec![], vec!["7x", "hard"]] {
+            let card = Card::new("d1", "7 x 8", "56", &tags).unwrap();
+            let rec: Record = Card::columns().into_iter().zip(card.to_values()).collect();
+            assert_eq!(Ok(card), Card::try_from(rec));
+        }
+        let outcome = CardOutcome {
+            student_id: "st1".into(),
+            deck_id: "d1".into(),
+            date: date_from_str("2022-01-01").unwrap(),
+            seq: 1,
+            card_id: "c1".into(),
+            correct: false,
+        };
+        let rec: Record = CardOutcome::columns()
+            .into_iter()
+            .zip(outcome.to_values())
+            .collect();
+        assert_eq!(Ok(outcome), CardOutcome::try_from(rec));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::constant::DEFAULT_DECK;
+use crate::database::{column, Entity, Record, Value};
+use crate::errors::{Error, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A set of SAFMEDS cards a student is timed on, such as times tables or
+/// phonics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deck {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub card_count: i32,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Deck {
+    pub fn new(name: &str, subject: &str, card_count: i32, description: &str) -> Result<Self> {
+        if card_count < 0 {
+            return Err(Error::ValidationError(
+                "a deck can't have fewer than 0 cards".into(),
+            ));
+        }
+        Ok(Deck {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            subject: subject.to_string(),
+            card_count,
+            description: description.to_string(),
+        })
+    }
+
+    /// The deck scores go in when no other is given, which every score
+    /// recorded before there were decks was moved to.
+    pub fn general() -> Self {
+        Deck {
+            id: DEFAULT_DECK.into(),
+            name: "General".into(),
+            subject: String::new(),
+            card_count: 0,
+            description: String::new(),
+        }
+    }
+}
+
+impl Entity for Deck {
+    const TABLE: &'static str = "deck";
+    const COLUMNS: &'static [&'static str] =
+        &["id", "name", "subject", "card_count", "description"];
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.id.clone().into(),
+            self.name.clone().into(),
+            self.subject.clone().into(),
+            self.card_count.into(),
+            self.description.clone().into(),
+        ]
+    }
+}
+
+impl TryFrom<Record> for Deck {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        Ok(Deck {
+            id: column(&rec, "id")?,
+            name: column(&rec, "name")?,
+            subject: column(&rec, "subject")?,
+            card_count: column(&rec, "card_count")?,
+            description: column(&rec, "description")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_deck {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let deck = Deck::new("Times tables", "maths", 60, "up to 12x12").unwrap();
+        assert_eq!(("Times tables", 60), (deck.name.as_str(), deck.card_count));
+        assert_eq!(
+            Err(Error::ValidationError(
+                "a deck can't have fewer than 0 cards".into()
+            )),
+            Deck::new("Phonics", "", -1, "")
+        );
+        let rec: Record = Deck::columns().into_iter().zip(deck.to_values()).collect();
+        assert_eq!(Ok(deck), Deck::try_from(rec));
+    }
+}
@@ -1 +1,3 @@
+pub mod card;
+pub mod deck;
 pub mod safmed;
\ No newline at end of file
@@ -1,8 +1,10 @@
-use crate::database::Record;
+use crate::constant::{DEFAULT_DECK, DEFAULT_DURATION};
+use crate::database::{column, Entity, Record, Value};
 use crate::errors::{Error, Result};
 use crate::useful::*;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SafmedScore {
@@ -10,47 +12,268 @@ pub struct SafmedScore {
     pub correct: i32,
     pub incorrect: i32,
     pub date: NaiveDate,
+    /// The deck the timing was on, together with date and seq it picks out
+    /// one of the student's timings.
+    #[serde(default = "default_deck")]
+    pub deck_id: String,
+    /// How long the timing lasted in seconds.
+    #[serde(default = "default_duration")]
+    pub duration: i32,
+    /// Which of the day's timings on the deck this is, counting from 1. A
+    /// new score has 0 until it's added as the day's next timing.
+    #[serde(default)]
+    pub seq: i32,
+    /// When the timing was taken.
+    pub timed_at: NaiveDateTime,
 }
 
 impl SafmedScore {
+    /// A one minute score on the general deck, see Deck::general, taken now
+    /// if date is today and otherwise at the start of the day.
     pub fn new(id: &str, correct: i32, incorrect: i32, date: &str) -> Result<Self> {
+        let date = date_from_str(date)?;
         Ok(SafmedScore {
             id: String::from(id),
             correct,
             incorrect,
-            date: date_from_str(date)?,
+            date,
+            deck_id: default_deck(),
+            duration: DEFAULT_DURATION,
+            seq: 0,
+            timed_at: match date == curr_date() {
+                true => curr_datetime(),
+                false => start_of(date),
+            },
         })
     }
+
+    /// Correct answers per minute, so timings of different lengths compare.
+    pub fn correct_per_minute(&self) -> f64 {
+        per_minute(self.correct as f64, self.duration)
+    }
+
+    pub fn incorrect_per_minute(&self) -> f64 {
+        per_minute(self.incorrect as f64, self.duration)
+    }
+}
+
+fn per_minute(count: f64, duration: i32) -> f64 {
+    count * 60.0 / duration as f64
+}
+
+fn default_deck() -> String {
+    DEFAULT_DECK.into()
+}
+
+fn default_duration() -> i32 {
+    DEFAULT_DURATION
+}
+
+fn start_of(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).unwrap()
+}
+
+/// Which of a day's timings on a deck stands for the day in charts and
+/// summaries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DayPolicy {
+    /// The highest correct rate, the fewest incorrect breaking ties.
+    Best,
+    First,
+    /// As when a second timing replaced the first.
+    #[default]
+    Last,
+}
+
+impl DayPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DayPolicy::Best => "best",
+            DayPolicy::First => "first",
+            DayPolicy::Last => "last",
+        }
+    }
+
+    /// Keeps one timing per student, deck and day out of scores, in the
+    /// order they came.
+    pub fn pick(&self, scores: Vec<SafmedScore>) -> Vec<SafmedScore> {
+        let mut picked: Vec<SafmedScore> = Vec::new();
+        let mut days: HashMap<(String, String, NaiveDate), usize> = HashMap::new();
+        for score in scores {
+            let day = (score.id.clone(), score.deck_id.clone(), score.date);
+            let i = match days.get(&day) {
+                Some(i) => *i,
+                None => {
+                    days.insert(day, picked.len());
+                    picked.push(score);
+                    continue;
+                }
+            };
+            let kept = &picked[i];
+            let better = match self {
+                DayPolicy::First => score.seq < kept.seq,
+                DayPolicy::Last => score.seq > kept.seq,
+                DayPolicy::Best => {
+                    let rates =
+                        |s: &SafmedScore| (s.correct_per_minute(), -s.incorrect_per_minute());
+                    rates(&score) > rates(kept)
+                }
+            };
+            if better {
+                picked[i] = score;
+            }
+        }
+        picked
+    }
+}
+
+impl TryFrom<&str> for DayPolicy {
+    type Error = Error;
+    fn try_from(s: &str) -> Result<Self> {
+        match s {
+            "best" => Ok(DayPolicy::Best),
+            "first" => Ok(DayPolicy::First),
+            "last" => Ok(DayPolicy::Last),
+            _ => Err(Error::ValueError(format!(
+                "{s} should be best, first or last"
+            ))),
+        }
+    }
+}
+
+impl Entity for SafmedScore {
+    const TABLE: &'static str = "safmed";
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "correct",
+        "incorrect",
+        "date",
+        "deck_id",
+        "duration",
+        "seq",
+        "timed_at",
+    ];
+    const KEY: &'static [&'static str] = &["id", "deck_id", "date", "seq"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.id.clone().into(),
+            self.correct.into(),
+            self.incorrect.into(),
+            self.date.into(),
+            self.deck_id.clone().into(),
+            self.duration.into(),
+            self.seq.into(),
+            self.timed_at.into(),
+        ]
+    }
 }
 
 impl TryFrom<Record> for SafmedScore {
     type Error = Error;
     fn try_from(rec: Record) -> Result<Self> {
-        let id = match rec.get("id") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing id".to_string())),
-        };
-        let correct = match rec.get("correct") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing correct".to_string())),
-        };
-        let incorrect = match rec.get("incorrect") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing incorrect".to_string())),
-        };
-        let date = match rec.get("date") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing date".to_string())),
-        };
+        let date: NaiveDate = column(&rec, "date")?;
         Ok(SafmedScore {
-            id,
-            correct,
-            incorrect,
+            id: column(&rec, "id")?,
+            correct: column(&rec, "correct")?,
+            incorrect: column(&rec, "incorrect")?,
             date,
+            // rows selected without them are one minute timings on the
+            // general deck, the first of their day
+            deck_id: match rec.contains_key("deck_id") {
+                true => column(&rec, "deck_id")?,
+                false => default_deck(),
+            },
+            duration: match rec.contains_key("duration") {
+                true => column(&rec, "duration")?,
+                false => default_duration(),
+            },
+            seq: match rec.contains_key("seq") {
+                true => column(&rec, "seq")?,
+                false => 1,
+            },
+            timed_at: match rec.contains_key("timed_at") {
+                true => column(&rec, "timed_at")?,
+                false => start_of(date),
+            },
         })
     }
 }
 
+/// How a student has done across all of their SAFMEDS timings on a deck,
+/// counting one a day as picked by a DayPolicy. Rates are answers per minute.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafmedSummary {
+    pub id: String,
+    pub deck_id: String,
+    pub timings: i32,
+    pub best_correct_rate: f64,
+    pub average_correct_rate: f64,
+    pub average_incorrect_rate: f64,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+}
+
+impl SafmedSummary {
+    /// Combines the summaries of two sets of timings by the same student on
+    /// the same deck.
+    pub fn merge(self, other: SafmedSummary) -> SafmedSummary {
+        let timings = self.timings + other.timings;
+        let average =
+            |a: f64, b: f64| (a * self.timings as f64 + b * other.timings as f64) / timings as f64;
+        SafmedSummary {
+            timings,
+            best_correct_rate: self.best_correct_rate.max(other.best_correct_rate),
+            average_correct_rate: average(self.average_correct_rate, other.average_correct_rate),
+            average_incorrect_rate: average(
+                self.average_incorrect_rate,
+                other.average_incorrect_rate,
+            ),
+            first_date: self.first_date.min(other.first_date),
+            last_date: self.last_date.max(other.last_date),
+            ..self
+        }
+    }
+}
+
+// Records are aggregated over timings of the same duration, so their counts
+// scale to rates.
+impl TryFrom<Record> for SafmedSummary {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        let duration: i32 = match rec.contains_key("duration") {
+            true => column(&rec, "duration")?,
+            false => default_duration(),
+        };
+        Ok(SafmedSummary {
+            id: column(&rec, "id")?,
+            deck_id: column(&rec, "deck_id")?,
+            timings: column(&rec, "timings")?,
+            best_correct_rate: per_minute(column::<i32>(&rec, "best_correct")? as f64, duration),
+            average_correct_rate: per_minute(column(&rec, "average_correct")?, duration),
+            average_incorrect_rate: per_minute(column(&rec, "average_incorrect")?, duration),
+            first_date: column(&rec, "first_date")?,
+            last_date: column(&rec, "last_date")?,
+        })
+    }
+}
+
+impl From<&SafmedScore> for SafmedSummary {
+    fn from(score: &SafmedScore) -> Self {
+        SafmedSummary {
+            id: score.id.clone(),
+            deck_id: score.deck_id.clone(),
+            timings: 1,
+            best_correct_rate: score.correct_per_minute(),
+            average_correct_rate: score.correct_per_minute(),
+            average_incorrect_rate: score.incorrect_per_minute(),
+            first_date: score.date,
+            last_date: score.date,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_score {
     use super::*;
@@ -85,6 +308,10 @@ mod test_score {
                     correct: 10.into(),
                     incorrect: 5.into(),
                     date: date_from_str("2021-01-01").unwrap(),
+                    deck_id: DEFAULT_DECK.into(),
+                    duration: 60,
+                    seq: 1,
+                    timed_at: datetime_from_str("2021-01-01 00:00:00").unwrap(),
                 }),
             ),
             (
@@ -104,4 +331,47 @@ mod test_score {
             };
         }
     }
+
+    #[test]
+    fn test_per_minute() {
+        let score = SafmedScore {
+            duration: 30,
+            ..SafmedScore::new("st1", 15, 2, "2021-01-01").unwrap()
+        };
+        assert_eq!(30.0, score.correct_per_minute());
+        assert_eq!(4.0, score.incorrect_per_minute());
+        let score = SafmedScore {
+            duration: 120,
+            ..score
+        };
+        assert_eq!(7.5, score.correct_per_minute());
+    }
+
+    #[test]
+    fn test_day_policy() {
+        let timing = |seq, correct, incorrect, date| SafmedScore {
+            seq,
+            ..SafmedScore::new("st1", correct, incorrect, date).unwrap()
+        };
+        let scores = vec![
+            timing(1, 40, 5, "2021-01-01"),
+            timing(2, 50, 9, "2021-01-01"),
+            timing(3, 50, 2, "2021-01-01"),
+            timing(4, 30, 1, "2021-01-01"),
+            timing(1, 20, 1, "2021-01-02"),
+        ];
+        let tests = [
+            (DayPolicy::First, 1),
+            (DayPolicy::Last, 4),
+            (DayPolicy::Best, 3),
+        ];
+        for (policy, seq) in tests {
+            let picked = policy.pick(scores.clone());
+            assert_eq!(2, picked.len());
+            assert_eq!(seq, picked[0].seq);
+            assert_eq!(scores[4], picked[1]);
+        }
+        assert_eq!(Ok(DayPolicy::Best), DayPolicy::try_from("best"));
+        assert!(DayPolicy::try_from("worst").is_err());
+    }
 }
@@ -1,7 +1,7 @@
-use crate::database::Record;
+use crate::database::{column, Entity, Record, Value};
 use crate::errors::{Error, Result};
 use crate::useful::*;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -11,6 +11,29 @@ pub struct Student {
     pub first_names: String,
     pub last_name: String,
     pub date_of_birth: NaiveDate,
+    /// When the student was archived, None while they're active.
+    #[serde(default)]
+    pub archived_at: Option<NaiveDateTime>,
+    /// An identifier from outside scorg, e.g. the school's unique pupil number.
+    #[serde(default)]
+    pub upn: Option<String>,
+    /// Year group or class, e.g. "7B".
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub start_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub leave_date: Option<NaiveDate>,
+    /// Whether the student is taking part at the moment. Unlike archiving this
+    /// doesn't hide them.
+    #[serde(default = "yes")]
+    pub active: bool,
+}
+
+fn yes() -> bool {
+    true
 }
 
 impl Student {
@@ -20,38 +43,82 @@ impl Student {
             first_names: first.to_string(),
             last_name: last.to_string(),
             date_of_birth: date_from_str(dob)?,
+            archived_at: None,
+            upn: None,
+            class: None,
+            notes: String::new(),
+            start_date: None,
+            leave_date: None,
+            active: true,
         })
     }
 }
 
+impl Entity for Student {
+    const TABLE: &'static str = "student";
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "first_names",
+        "last_name",
+        "date_of_birth",
+        "archived_at",
+        "upn",
+        "class",
+        "notes",
+        "start_date",
+        "leave_date",
+        "active",
+    ];
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.id.clone().into(),
+            self.first_names.clone().into(),
+            self.last_name.clone().into(),
+            self.date_of_birth.into(),
+            self.archived_at.into(),
+            self.upn.clone().into(),
+            self.class.clone().into(),
+            self.notes.clone().into(),
+            self.start_date.into(),
+            self.leave_date.into(),
+            self.active.into(),
+        ]
+    }
+}
+
 impl TryFrom<Record> for Student {
     type Error = Error;
     fn try_from(rec: Record) -> Result<Self> {
-        let id = match rec.get("id") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing id".to_string())),
-        };
-        let first_names = match rec.get("first_names") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing first_names".to_string())),
-        };
-        let last_name = match rec.get("last_name") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing last_name".to_string())),
-        };
-        let date_of_birth = match rec.get("date_of_birth") {
-            Some(v) => v.try_into()?,
-            None => return Err(Error::ValueError("Missing date_of_birth".to_string())),
-        };
         Ok(Student {
-            id,
-            first_names,
-            last_name,
-            date_of_birth,
+            id: column(&rec, "id")?,
+            first_names: column(&rec, "first_names")?,
+            last_name: column(&rec, "last_name")?,
+            date_of_birth: column(&rec, "date_of_birth")?,
+            archived_at: optional(&rec, "archived_at", None)?,
+            upn: optional(&rec, "upn", None)?,
+            class: optional(&rec, "class", None)?,
+            notes: optional(&rec, "notes", String::new())?,
+            start_date: optional(&rec, "start_date", None)?,
+            leave_date: optional(&rec, "leave_date", None)?,
+            active: optional(&rec, "active", true)?,
         })
     }
 }
 
+// older callers only select the names and date of birth, which is fine as the
+// rest have defaults
+fn optional<'a, T>(rec: &'a Record, name: &str, default: T) -> Result<T>
+where
+    &'a Value: TryInto<T, Error = Error>,
+{
+    match rec.contains_key(name) {
+        true => column::<T>(rec, name),
+        false => Ok(default),
+    }
+}
+
 #[cfg(test)]
 mod test_student {
     use super::*;
@@ -76,9 +143,45 @@ mod test_student {
                 ]),
                 Ok(Student {
                     id: "st1".into(),
-                    first_names: "first".into(),
-                    last_name: "last".into(),
-                    date_of_birth: date_from_str("1990-01-23").unwrap(),
+                    ..Student::new("first", "last", "1990-01-23").unwrap()
+                }),
+            ),
+            (
+                Record::from([
+                    ("id".into(), "st1".into()),
+                    ("first_names".into(), "first".into()),
+                    ("last_name".into(), "last".into()),
+                    ("date_of_birth".into(), "1990-01-23".into()),
+                    ("archived_at".into(), "2023-07-20 15:30:00".into()),
+                ]),
+                Ok(Student {
+                    id: "st1".into(),
+                    archived_at: Some(datetime_from_str("2023-07-20 15:30:00").unwrap()),
+                    ..Student::new("first", "last", "1990-01-23").unwrap()
+                }),
+            ),
+            (
+                Record::from([
+                    ("id".into(), "st1".into()),
+                    ("first_names".into(), "first".into()),
+                    ("last_name".into(), "last".into()),
+                    ("date_of_birth".into(), "1990-01-23".into()),
+                    ("upn".into(), "A123456789012".into()),
+                    ("class".into(), "7B".into()),
+                    ("notes".into(), "needs glasses".into()),
+                    ("start_date".into(), "2021-09-01".into()),
+                    ("leave_date".into(), Value::Null),
+                    ("active".into(), Value::from(false)),
+                ]),
+                Ok(Student {
+                    id: "st1".into(),
+                    upn: Some("A123456789012".into()),
+                    class: Some("7B".into()),
+                    notes: "needs glasses".into(),
+                    start_date: Some(date_from_str("2021-09-01").unwrap()),
+                    leave_date: None,
+                    active: false,
+                    ..Student::new("first", "last", "1990-01-23").unwrap()
                 }),
             ),
             (
@@ -102,15 +205,19 @@ mod test_student {
     fn test_serde() {
         let student = Student {
             id: "st1".into(),
-            first_names: "Ben".into(),
-            last_name: "Jones".into(),
-            date_of_birth: date_from_str("1990-01-23").unwrap(),
+            ..Student::new("Ben", "Jones", "1990-01-23").unwrap()
         };
         let json_student = serde_json::to_string(&student).expect("failed to serialize");
-        assert_eq!(json_student, "{\"id\":\"st1\",\"first_names\":\"Ben\",\"last_name\":\"Jones\",\"date_of_birth\":\"1990-01-23\"}".to_owned());
+        assert_eq!(json_student, "{\"id\":\"st1\",\"first_names\":\"Ben\",\"last_name\":\"Jones\",\"date_of_birth\":\"1990-01-23\",\"archived_at\":null,\"upn\":null,\"class\":null,\"notes\":\"\",\"start_date\":null,\"leave_date\":null,\"active\":true}".to_owned());
         assert_eq!(
             student,
             serde_json::from_str(&json_student).expect("failed to deserialize")
         );
+        // older versions of the app only send the names and date of birth
+        let edit = "{\"id\":\"st1\",\"first_names\":\"Ben\",\"last_name\":\"Jones\",\"date_of_birth\":\"1990-01-23\"}";
+        assert_eq!(
+            student,
+            serde_json::from_str(edit).expect("failed to deserialize")
+        );
     }
 }
@@ -0,0 +1,168 @@
+use crate::database::{column, Entity, Record, Value};
+use crate::errors::{Error, Result};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, SaltString};
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can do anything, including managing users.
+    Admin,
+    /// Can see and change the students assigned to them.
+    Teacher,
+    /// Can see the students assigned to them but not change anything.
+    Viewer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Teacher => "teacher",
+            Role::Viewer => "viewer",
+        }
+    }
+}
+
+impl TryFrom<&str> for Role {
+    type Error = Error;
+    fn try_from(s: &str) -> Result<Self> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "teacher" => Ok(Role::Teacher),
+            "viewer" => Ok(Role::Viewer),
+            _ => Err(Error::ValueError(format!("{s} is not a role"))),
+        }
+    }
+}
+
+/// Someone who logs in to the app.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    /// An argon2 hash in PHC format, which carries its own salt. It never
+    /// leaves the backend.
+    #[serde(skip)]
+    pub password_hash: String,
+    pub role: Role,
+}
+
+impl User {
+    pub fn new(username: &str, password: &str, role: Role) -> Result<Self> {
+        Ok(User {
+            id: Uuid::new_v4().to_string(),
+            username: username.to_string(),
+            password_hash: hash_password(password)?,
+            role,
+        })
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        match PasswordHash::new(&self.password_hash) {
+            Ok(hash) => Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(e) => Err(Error::ValueError(format!("couldn't hash password: {e}"))),
+    }
+}
+
+impl Entity for User {
+    const TABLE: &'static str = "users";
+    const COLUMNS: &'static [&'static str] = &["id", "username", "password_hash", "role"];
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![
+            self.id.clone().into(),
+            self.username.clone().into(),
+            self.password_hash.clone().into(),
+            self.role.as_str().into(),
+        ]
+    }
+}
+
+impl TryFrom<Record> for User {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        let role: String = column(&rec, "role")?;
+        Ok(User {
+            id: column(&rec, "id")?,
+            username: column(&rec, "username")?,
+            password_hash: column(&rec, "password_hash")?,
+            role: Role::try_from(role.as_str())?,
+        })
+    }
+}
+
+/// A student being looked after by a user, which lets teachers and viewers
+/// see them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assignment {
+    pub student_id: String,
+    pub user_id: String,
+}
+
+impl Entity for Assignment {
+    const TABLE: &'static str = "student_user";
+    const COLUMNS: &'static [&'static str] = &["student_id", "user_id"];
+    const KEY: &'static [&'static str] = &["student_id", "user_id"];
+
+    fn to_values(&self) -> Vec<Value> {
+        vec![self.student_id.clone().into(), self.user_id.clone().into()]
+    }
+}
+
+impl TryFrom<Record> for Assignment {
+    type Error = Error;
+    fn try_from(rec: Record) -> Result<Self> {
+        Ok(Assignment {
+            student_id: column(&rec, "student_id")?,
+            user_id: column(&rec, "user_id")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_user {
+    use super::*;
+
+    #[test]
+    fn test_passwords() {
+        let user = User::new("mrs.jones", "correct horse", Role::Teacher).unwrap();
+        assert!(user.password_hash.starts_with("$argon2"));
+        assert!(!user.password_hash.contains("correct horse"));
+        assert!(user.check_password("correct horse"));
+        assert!(!user.check_password("Correct horse"));
+        // the same password is salted differently each time
+        assert_ne!(hash_password("pw").unwrap(), hash_password("pw").unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let user = User::new("admin", "secret", Role::Admin).unwrap();
+        let rec: Record = User::columns().into_iter().zip(user.to_values()).collect();
+        assert_eq!(Ok(user.clone()), User::try_from(rec.clone()));
+        let mut bad = rec;
+        bad.insert("role".into(), "headteacher".into());
+        assert_eq!(
+            Err(Error::ValueError("headteacher is not a role".into())),
+            User::try_from(bad)
+        );
+        // the hash isn't sent to the app
+        let json = serde_json::to_string(&user).unwrap();
+        assert!(!json.contains("argon2"));
+        assert!(json.contains(r#""role":"admin""#));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+
+use crate::models::Student;
+use crate::useful::date_to_str;
+
+/// How closely a query word has to match one of a student's words to count.
+const MIN_SIMILARITY: f64 = 0.6;
+
+/// The share of a query word's trigrams sqlite's search index has to hold for
+/// the word to count as matched. It's lower than MIN_SIMILARITY as a typo
+/// spoils up to three trigrams.
+pub(crate) const MIN_TRIGRAMS: f64 = 0.4;
+
+/// Ranks students by how well they match text, best first, leaving out those
+/// that don't match at all. Every word of text is compared against every word
+/// of the student's names, date of birth, upn, class and notes, ignoring case
+/// and accents and allowing for small typos. This is only used where there's
+/// no sqlite search index, see [`index_text`].
+pub fn rank(students: Vec<Student>, text: &str) -> Vec<Student> {
+    let query = words(text);
+    if query.is_empty() {
+        return vec![];
+    }
+    let mut scored: Vec<(f64, Student)> = students
+        .into_iter()
+        .filter_map(|student| {
+            let searched = words(&searchable(&student));
+            let mut total = 0.0;
+            for q in &query {
+                let best = searched
+                    .iter()
+                    .map(|w| similarity(q, w))
+                    .fold(0.0, f64::max);
+                if best < MIN_SIMILARITY {
+                    return None;
+                }
+                total += best;
+            }
+            Some((total / query.len() as f64, student))
+        })
+        .collect();
+    // ties stay in the order they came in
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().map(|(_, student)| student).collect()
+}
+
+fn searchable(student: &Student) -> String {
+    format!(
+        "{} {} {} {} {} {}",
+        student.first_names,
+        student.last_name,
+        date_to_str(student.date_of_birth),
+        student.upn.as_deref().unwrap_or_default(),
+        student.class.as_deref().unwrap_or_default(),
+        student.notes
+    )
+}
+
+/// What sqlite's search index keeps for s: its words folded as they are for
+/// queries, with a space either side of each so the trigrams at the start and
+/// end of a word can be told apart from the rest.
+pub(crate) fn index_text(s: &str) -> String {
+    format!(" {} ", words(s).join(" "))
+}
+
+/// The trigrams to look for in the search index, one set per word of text.
+pub(crate) fn index_trigrams(text: &str) -> Vec<Vec<String>> {
+    words(text)
+        .iter()
+        .map(|word| {
+            let padded: Vec<char> = format!(" {word} ").chars().collect();
+            let mut trigrams: Vec<String> = padded.windows(3).map(String::from_iter).collect();
+            trigrams.sort();
+            trigrams.dedup();
+            trigrams
+        })
+        .collect()
+}
+
+/// The words of s, lower case and without accents.
+fn words(s: &str) -> Vec<String> {
+    fold(s).split_whitespace().map(String::from).collect()
+}
+
+fn fold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => folded.push('a'),
+            'ç' | 'ć' | 'č' => folded.push('c'),
+            'ď' | 'đ' => folded.push('d'),
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => folded.push('e'),
+            'ğ' => folded.push('g'),
+            'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => folded.push('i'),
+            'ł' => folded.push('l'),
+            'ñ' | 'ń' | 'ň' => folded.push('n'),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => folded.push('o'),
+            'ř' => folded.push('r'),
+            'ś' | 'ş' | 'š' => folded.push('s'),
+            'ť' | 'ţ' => folded.push('t'),
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => folded.push('u'),
+            'ý' | 'ÿ' => folded.push('y'),
+            'ź' | 'ż' | 'ž' => folded.push('z'),
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            // apostrophes join O'Brien up rather than splitting it
+            '\'' | '’' => (),
+            c if c.is_alphanumeric() => folded.push(c),
+            _ => folded.push(' '),
+        }
+    }
+    folded
+}
+
+/// 1 for the same word, a little less for the start of the word, otherwise
+/// whichever is better of trigram overlap and edit distance.
+fn similarity(query: &str, word: &str) -> f64 {
+    if query == word {
+        return 1.0;
+    }
+    if word.starts_with(query) {
+        return 0.9;
+    }
+    let longest = query.chars().count().max(word.chars().count()) as f64;
+    let edits = 1.0 - edit_distance(query, word) as f64 / longest;
+    edits.max(trigram_similarity(query, word))
+}
+
+fn trigrams(word: &str) -> HashSet<Vec<char>> {
+    let padded: Vec<char> = format!("  {word} ").chars().collect();
+    padded.windows(3).map(|w| w.to_vec()).collect()
+}
+
+fn trigram_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let shared = a.intersection(&b).count() as f64;
+    shared / (a.len() + b.len()) as f64 * 2.0
+}
+
+// Levenshtein distance, counting swapping two neighbouring letters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn student(first: &str, last: &str) -> Student {
+        Student::new(first, last, "2010-05-17").unwrap()
+    }
+
+    fn names(students: Vec<Student>) -> Vec<String> {
+        students
+            .into_iter()
+            .map(|s| format!("{} {}", s.first_names, s.last_name))
+            .collect()
+    }
+
+    fn pupils() -> Vec<Student> {
+        vec![
+            student("Ben", "Jones"),
+            student("Zoë", "Brontë"),
+            student("Siobhan", "O'Brien"),
+            student("Benjamin", "Smith"),
+            student("Anna", "Smyth"),
+        ]
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!("zoe bronte", fold("Zoë Brontë"));
+        assert_eq!("obrien", fold("O'Brien"));
+        assert_eq!("strasse mary ann", fold("STRASSE mary-ann"));
+        assert_eq!("strasse", fold("Straße"));
+    }
+
+    #[test]
+    fn test_index_trigrams() {
+        assert_eq!(" zoe bronte obrien ", index_text("Zoë  Brontë, O'Brien"));
+        assert_eq!(
+            vec![vec![" be", "ben", "en "], vec![" a "]],
+            index_trigrams("Ben a")
+        );
+        // repeats are only looked for once
+        assert_eq!(vec![vec![" aa", "aa ", "aaa"]], index_trigrams("aaaa"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("jones", "jones"));
+        assert_eq!(1, edit_distance("jonse", "jones"));
+        assert_eq!(1, edit_distance("smith", "smyth"));
+        assert_eq!(3, edit_distance("", "ben"));
+    }
+
+    #[test]
+    fn test_rank() {
+        assert_eq!(vec!["Ben Jones"], names(rank(pupils(), "jones")));
+        // accents and case don't matter
+        assert_eq!(vec!["Zoë Brontë"], names(rank(pupils(), "ZOE bronte")));
+        assert_eq!(vec!["Siobhan O'Brien"], names(rank(pupils(), "obrien")));
+        // small typos still match
+        assert_eq!(vec!["Ben Jones"], names(rank(pupils(), "Ben Jnoes")));
+        // the exact name comes before the one it starts
+        assert_eq!(
+            vec!["Ben Jones", "Benjamin Smith"],
+            names(rank(pupils(), "ben"))
+        );
+        assert_eq!(
+            vec!["Benjamin Smith", "Anna Smyth"],
+            names(rank(pupils(), "smith"))
+        );
+        assert_eq!(5, rank(pupils(), "2010").len());
+        let mut upn = pupils();
+        upn[2].upn = Some("A123456789012".into());
+        upn[2].class = Some("7B".into());
+        assert_eq!(
+            vec!["Siobhan O'Brien"],
+            names(rank(upn.clone(), "a123456789012"))
+        );
+        assert_eq!(vec!["Siobhan O'Brien"], names(rank(upn, "7b")));
+        assert!(rank(pupils(), "xavier").is_empty());
+        assert!(rank(pupils(), "  ").is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::database::{Dao, Order, QueryOptions, Repository, Symbol, Where};
+use crate::errors::Result;
+use crate::models::AuditEntry;
+use crate::services::permissions::{Permissions, Session};
+
+/// Reads and writes the audit table. The services record every change they
+/// make through here, inside the same transaction as the change itself.
+pub(crate) struct AuditLog {
+    entries: Repository<AuditEntry>,
+    permissions: Permissions,
+}
+
+impl AuditLog {
+    pub fn new(dao: Arc<dyn Dao>) -> Self {
+        Self {
+            entries: Repository::new(Arc::clone(&dao)),
+            permissions: Permissions::new(dao),
+        }
+    }
+
+    /// Marks the changes recorded from now on as made by the session's user.
+    pub fn set_session(&mut self, session: Arc<Session>) {
+        self.permissions.set_session(session);
+    }
+
+    pub fn record(&self, entries: &[AuditEntry]) -> Result<usize> {
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        log::debug!("auditing {} changes", entries.len());
+        let user_id = self.permissions.user()?.map(|user| user.id);
+        let entries: Vec<AuditEntry> = entries
+            .iter()
+            .map(|entry| AuditEntry {
+                user_id: user_id.clone(),
+                ..entry.clone()
+            })
+            .collect();
+        self.entries.insert(&entries)
+    }
+
+    /// Every change to the student with id, oldest first, optionally only
+    /// those to one table.
+    pub fn history(&self, id: &str, table: Option<&str>) -> Result<Vec<AuditEntry>> {
+        let mut wheres = vec![Where::new("student_id", Symbol::EQ, id.into())];
+        if let Some(table) = table {
+            wheres.push(Where::new("table_name", Symbol::EQ, table.into()));
+        }
+        let oldest_first = QueryOptions::new().order_by("changed_at", Order::Asc);
+        self.entries.list(&wheres, &oldest_first)
+    }
+}
@@ -0,0 +1,324 @@
+use std::sync::Arc;
+
+use crate::constant::DEFAULT_DECK;
+use crate::database::{Dao, Entity, Order, QueryOptions, Repository, Symbol, Where};
+use crate::errors::{Error, Result};
+use crate::models::{AuditEntry, Card, CardOutcome, Deck, SafmedScore};
+use crate::services::audit::AuditLog;
+use crate::services::permissions::{Permissions, Session};
+use crate::services::score::outcome_audit_entry;
+
+pub struct DeckService {
+    dao: Arc<dyn Dao>,
+    decks: Repository<Deck>,
+    cards: Repository<Card>,
+    outcomes: Repository<CardOutcome>,
+    scores: Repository<SafmedScore>,
+    audit: AuditLog,
+    permissions: Permissions,
+}
+
+impl DeckService {
+    pub fn new(dao: Arc<dyn Dao>) -> Self {
+        log::debug!("created new DeckService");
+        Self {
+            decks: Repository::new(Arc::clone(&dao)),
+            cards: Repository::new(Arc::clone(&dao)),
+            outcomes: Repository::new(Arc::clone(&dao)),
+            scores: Repository::new(Arc::clone(&dao)),
+            audit: AuditLog::new(Arc::clone(&dao)),
+            permissions: Permissions::new(Arc::clone(&dao)),
+            dao,
+        }
+    }
+
+    /// Everyone can see the decks, but viewers can't change them and only
+    /// admins can delete them.
+    pub fn with_session(mut self, session: Arc<Session>) -> Self {
+        self.permissions.set_session(Arc::clone(&session));
+        self.audit.set_session(session);
+        self
+    }
+
+    pub fn all(&self) -> Result<Vec<Deck>> {
+        log::debug!("getting all decks");
+        let by_name = QueryOptions::new().order_by("name", Order::Asc);
+        self.decks.list(&vec![], &by_name)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Deck> {
+        match self.decks.get(&[id.into()])? {
+            Some(deck) => Ok(deck),
+            None => Err(Error::NotFound(format!("deck {id}"))),
+        }
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Result<Deck> {
+        let wheres = vec![Where::new("name", Symbol::EQ, name.into())];
+        match self.decks.list(&wheres, &QueryOptions::new())?.pop() {
+            Some(deck) => Ok(deck),
+            None => Err(Error::NotFound(format!("deck {name}"))),
+        }
+    }
+
+    pub fn add_deck(&self, deck: &Deck) -> Result<usize> {
+        log::debug!("adding deck {deck:?}");
+        self.permissions.can_edit()?;
+        self.decks.insert(std::slice::from_ref(deck))
+    }
+
+    pub fn update_deck(&self, deck: &Deck) -> Result<usize> {
+        log::debug!("updating deck {}", deck.id);
+        self.permissions.can_edit()?;
+        let wheres = vec![Where::new("id", Symbol::EQ, deck.id.clone().into())];
+        self.dao
+            .update(&Deck::columns(), Deck::TABLE, deck.to_values(), &wheres)
+    }
+
+    /// Deletes a deck nobody has been timed on. The general deck is always
+    /// kept as scores fall back to it. Only admins can, as its cards take
+    /// every student's outcomes on them along with them.
+    pub fn delete_deck(&self, id: &str) -> Result<usize> {
+        log::debug!("deleting deck {id}");
+        self.permissions.is_admin()?;
+        if id == DEFAULT_DECK {
+            return Err(Error::ValidationError(
+                "the general deck can't be deleted".into(),
+            ));
+        }
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            let wheres = vec![Where::new("deck_id", Symbol::EQ, id.into())];
+            let one = QueryOptions::new().limit(1);
+            if !self.scores.list(&wheres, &one)?.is_empty() {
+                return Err(Error::ValidationError(
+                    "the deck has scores, delete them first".into(),
+                ));
+            }
+            self.delete_outcomes(&wheres)?;
+            self.cards.delete(&wheres)?;
+            deleted = self
+                .decks
+                .delete(&vec![Where::new("id", Symbol::EQ, id.into())])?;
+            Ok(())
+        })?;
+        Ok(deleted)
+    }
+
+    /// The deck's cards, by their fronts.
+    pub fn cards(&self, deck_id: &str) -> Result<Vec<Card>> {
+        log::debug!("getting cards for deck {deck_id}");
+        let wheres = vec![Where::new("deck_id", Symbol::EQ, deck_id.into())];
+        let by_front = QueryOptions::new().order_by("front", Order::Asc);
+        self.cards.list(&wheres, &by_front)
+    }
+
+    pub fn get_card(&self, id: &str) -> Result<Card> {
+        match self.cards.get(&[id.into()])? {
+            Some(card) => Ok(card),
+            None => Err(Error::NotFound(format!("card {id}"))),
+        }
+    }
+
+    pub fn add_cards(&self, cards: &[Card]) -> Result<usize> {
+        log::debug!("adding {} cards", cards.len());
+        self.permissions.can_edit()?;
+        let mut added = 0;
+        self.dao.transaction(&mut |_| {
+            for card in cards {
+                self.get(&card.deck_id)?;
+            }
+            added = self.cards.insert(cards)?;
+            Ok(())
+        })?;
+        Ok(added)
+    }
+
+    pub fn update_card(&self, card: &Card) -> Result<usize> {
+        log::debug!("updating card {}", card.id);
+        self.permissions.can_edit()?;
+        self.get(&card.deck_id)?;
+        let wheres = vec![Where::new("id", Symbol::EQ, card.id.clone().into())];
+        self.dao
+            .update(&Card::columns(), Card::TABLE, card.to_values(), &wheres)
+    }
+
+    /// Deletes the card along with how everyone did on it, so only admins can.
+    pub fn delete_card(&self, id: &str) -> Result<usize> {
+        log::debug!("deleting card {id}");
+        self.permissions.is_admin()?;
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            self.delete_outcomes(&vec![Where::new("card_id", Symbol::EQ, id.into())])?;
+            deleted = self
+                .cards
+                .delete(&vec![Where::new("id", Symbol::EQ, id.into())])?;
+            Ok(())
+        })?;
+        Ok(deleted)
+    }
+
+    // sqlite would take the outcomes with their cards, they're deleted here
+    // so that each one is audited
+    fn delete_outcomes(&self, wheres: &Vec<Where>) -> Result<usize> {
+        let old = self.outcomes.list(wheres, &QueryOptions::new())?;
+        let deleted = self.outcomes.delete(wheres)?;
+        let entries = old
+            .iter()
+            .map(|outcome| outcome_audit_entry(Some(outcome), None))
+            .collect::<Result<Vec<AuditEntry>>>()?;
+        self.audit.record(&entries)?;
+        Ok(deleted)
+    }
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{MemoryDao, SqliteDao};
+    use crate::models::{Operation, Role, Student};
+    use crate::services::{SafmedScoreService, StudentService, UserService};
+    use matches::assert_matches;
+
+    fn daos() -> Vec<Arc<dyn Dao>> {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        vec![sqlite, Arc::new(MemoryDao::new())]
+    }
+
+    #[test]
+    fn test_decks() {
+        for dao in daos() {
+            let ds = DeckService::new(dao);
+            let tables = Deck::new("Times tables", "maths", 60, "").unwrap();
+            ds.add_deck(&tables).unwrap();
+            // there's always the general deck
+            assert_eq!(Ok(vec![Deck::general(), tables.clone()]), ds.all());
+            assert!(matches!(
+                ds.add_deck(&Deck::new("Times tables", "", 0, "").unwrap()),
+                Err(Error::Duplicate { .. })
+            ));
+            let more = Deck {
+                card_count: 144,
+                ..tables.clone()
+            };
+            assert_eq!(Ok(1), ds.update_deck(&more));
+            assert_eq!(Ok(more), ds.get_by_name("Times tables"));
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "the general deck can't be deleted".into()
+                )),
+                ds.delete_deck(DEFAULT_DECK)
+            );
+            assert_eq!(Ok(1), ds.delete_deck(&tables.id));
+            assert_eq!(
+                Err(Error::NotFound(format!("deck {}", tables.id))),
+                ds.get(&tables.id)
+            );
+        }
+    }
+
+    #[test]
+    fn test_delete_deck_with_scores() {
+        for dao in daos() {
+            let ds = DeckService::new(Arc::clone(&dao));
+            let phonics = Deck::new("Phonics", "reading", 40, "").unwrap();
+            ds.add_deck(&phonics).unwrap();
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            let score = SafmedScore {
+                deck_id: phonics.id.clone(),
+                ..SafmedScore::new(&ben.id, 30, 2, "2022-01-01").unwrap()
+            };
+            let scores = SafmedScoreService::new(Arc::clone(&dao));
+            scores.add_score(&score).unwrap();
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "the deck has scores, delete them first".into()
+                )),
+                ds.delete_deck(&phonics.id)
+            );
+            scores.delete_scores(&ben.id).unwrap();
+            assert_eq!(Ok(1), ds.delete_deck(&phonics.id));
+        }
+    }
+
+    #[test]
+    fn test_cards() {
+        for dao in daos() {
+            let ds = DeckService::new(dao);
+            let tables = Deck::new("Times tables", "maths", 2, "").unwrap();
+            ds.add_deck(&tables).unwrap();
+            let seven = Card::new(&tables.id, "7 x 8", "56", &["7x"]).unwrap();
+            let six = Card::new(&tables.id, "6 x 8", "48", &[]).unwrap();
+            assert_eq!(Ok(2), ds.add_cards(&[seven.clone(), six.clone()]));
+            assert_eq!(Ok(vec![six.clone(), seven.clone()]), ds.cards(&tables.id));
+            assert!(matches!(
+                ds.add_cards(&[Card::new(&tables.id, "7 x 8", "", &[]).unwrap()]),
+                Err(Error::Duplicate { .. })
+            ));
+            assert_eq!(
+                Err(Error::NotFound("deck nope".into())),
+                ds.add_cards(&[Card::new("nope", "1 x 1", "1", &[]).unwrap()])
+            );
+            let fixed = Card {
+                back: "fifty six".into(),
+                ..seven.clone()
+            };
+            assert_eq!(Ok(1), ds.update_card(&fixed));
+            assert_eq!(Ok(fixed), ds.get_card(&seven.id));
+            assert_eq!(Ok(1), ds.delete_card(&six.id));
+            assert_eq!(1, ds.cards(&tables.id).unwrap().len());
+            // the cards go with their deck
+            assert_eq!(Ok(1), ds.delete_deck(&tables.id));
+            assert!(matches!(ds.get_card(&seven.id), Err(Error::NotFound(_))));
+        }
+    }
+
+    #[test]
+    fn test_only_admins_delete() {
+        for dao in daos() {
+            let session = Session::new();
+            let users = UserService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+            let admin = users.add_user("admin", "pw", Role::Admin).unwrap();
+            users.login("admin", "pw").unwrap();
+            users.add_user("teacher", "pw", Role::Teacher).unwrap();
+            users.login("teacher", "pw").unwrap();
+            let ds = DeckService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+            let tables = Deck::new("Times tables", "maths", 2, "").unwrap();
+            ds.add_deck(&tables).unwrap();
+            let seven = Card::new(&tables.id, "7 x 8", "56", &[]).unwrap();
+            ds.add_cards(std::slice::from_ref(&seven)).unwrap();
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            let students = StudentService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+            students.add_student(&ben).unwrap();
+            let score = SafmedScore {
+                deck_id: tables.id.clone(),
+                ..SafmedScore::new(&ben.id, 1, 0, "2022-01-01").unwrap()
+            };
+            SafmedScoreService::new(Arc::clone(&dao))
+                .add_score_with_cards(&score, &[(seven.id.clone(), true)])
+                .unwrap();
+            // the card has Ben's outcome on it
+            assert_matches!(ds.delete_card(&seven.id), Err(Error::PermissionDenied(_)));
+            assert_matches!(ds.delete_deck(&tables.id), Err(Error::PermissionDenied(_)));
+            users.login("admin", "pw").unwrap();
+            assert_eq!(Ok(1), ds.delete_card(&seven.id));
+            let deleted: Vec<AuditEntry> = students
+                .history(&ben.id)
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.table_name == CardOutcome::TABLE)
+                .collect();
+            assert_eq!(2, deleted.len());
+            assert_eq!(Operation::Delete, deleted[1].operation);
+            assert_eq!(Some(admin.id), deleted[1].user_id);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use std::sync::Arc;
+
+use crate::database::{Dao, Entity, Order, QueryOptions, Repository, Symbol, Value, Where};
+use crate::errors::{Error, Result};
+use crate::models::{AuditEntry, DayPolicy, Group, MemberScore, Membership, Student};
+use crate::services::audit::AuditLog;
+use crate::services::permissions::{Permissions, Session};
+use crate::services::{SafmedScoreService, StudentService};
+use crate::useful::date_to_str;
+use chrono::NaiveDate;
+
+pub struct GroupService {
+    dao: Arc<dyn Dao>,
+    groups: Repository<Group>,
+    memberships: Repository<Membership>,
+    students: StudentService,
+    scores: SafmedScoreService,
+    audit: AuditLog,
+    permissions: Permissions,
+}
+
+impl GroupService {
+    pub fn new(dao: Arc<dyn Dao>) -> Self {
+        log::debug!("created new GroupService");
+        Self {
+            groups: Repository::new(Arc::clone(&dao)),
+            memberships: Repository::new(Arc::clone(&dao)),
+            students: StudentService::new(Arc::clone(&dao)),
+            scores: SafmedScoreService::new(Arc::clone(&dao)),
+            audit: AuditLog::new(Arc::clone(&dao)),
+            permissions: Permissions::new(Arc::clone(&dao)),
+            dao,
+        }
+    }
+
+    /// Stops viewers changing groups, and only shows members and scores of the
+    /// students assigned to the session's user.
+    pub fn with_session(mut self, session: Arc<Session>) -> Self {
+        self.permissions.set_session(Arc::clone(&session));
+        self.audit.set_session(Arc::clone(&session));
+        self.students = self.students.with_session(Arc::clone(&session));
+        self.scores = self.scores.with_session(session);
+        self
+    }
+
+    /// Picks which of a day's timings the members' latest scores come from.
+    pub fn with_policy(mut self, policy: DayPolicy) -> Self {
+        self.scores = self.scores.with_policy(policy);
+        self
+    }
+
+    pub fn all(&self) -> Result<Vec<Group>> {
+        log::debug!("getting all groups");
+        let by_name = QueryOptions::new().order_by("name", Order::Asc);
+        self.groups.list(&vec![], &by_name)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Group> {
+        match self.groups.get(&[id.into()])? {
+            Some(group) => Ok(group),
+            None => Err(Error::NotFound(format!("group {id}"))),
+        }
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Result<Group> {
+        let wheres = vec![Where::new("name", Symbol::EQ, name.into())];
+        match self.groups.list(&wheres, &QueryOptions::new())?.pop() {
+            Some(group) => Ok(group),
+            None => Err(Error::NotFound(format!("group {name}"))),
+        }
+    }
+
+    pub fn add_group(&self, group: &Group) -> Result<usize> {
+        log::debug!("adding group {group:?}");
+        self.permissions.can_edit()?;
+        self.groups.insert(std::slice::from_ref(group))
+    }
+
+    pub fn update_group(&self, group: &Group) -> Result<usize> {
+        log::debug!("updating group {}", group.id);
+        self.permissions.can_edit()?;
+        let wheres = vec![Where::new("id", Symbol::EQ, group.id.clone().into())];
+        self.dao
+            .update(&Group::columns(), Group::TABLE, group.to_values(), &wheres)
+    }
+
+    /// Deletes the group and its history, the students stay as they are. The
+    /// user has to be able to change every student who's ever been in it.
+    pub fn delete_group(&self, id: &str) -> Result<usize> {
+        log::debug!("deleting group {id}");
+        self.permissions.can_edit()?;
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            let wheres = vec![Where::new("group_id", Symbol::EQ, id.into())];
+            let old = self.memberships.list(&wheres, &QueryOptions::new())?;
+            let mut entries = Vec::new();
+            for membership in &old {
+                self.permissions.can_change(&membership.student_id)?;
+                entries.push(audit_entry(Some(membership), None)?);
+            }
+            self.memberships.delete(&wheres)?;
+            self.audit.record(&entries)?;
+            deleted = self
+                .groups
+                .delete(&vec![Where::new("id", Symbol::EQ, id.into())])?;
+            Ok(())
+        })?;
+        Ok(deleted)
+    }
+
+    /// Puts the student in the group from joined_on, as long as they aren't
+    /// in it already.
+    pub fn add_member(
+        &self,
+        group_id: &str,
+        student_id: &str,
+        joined_on: NaiveDate,
+    ) -> Result<usize> {
+        log::debug!("adding student {student_id} to group {group_id}");
+        self.permissions.can_change(student_id)?;
+        let mut added = 0;
+        self.dao.transaction(&mut |_| {
+            // both have to exist, which sqlite checks but memory doesn't
+            self.get(group_id)?;
+            self.students.get(student_id)?;
+            if !self.current(group_id, student_id)?.is_empty() {
+                return Err(Error::ValidationError(
+                    "the student is already in the group".into(),
+                ));
+            }
+            let membership = Membership {
+                group_id: group_id.into(),
+                student_id: student_id.into(),
+                joined_on,
+                left_on: None,
+            };
+            added = self.memberships.insert(std::slice::from_ref(&membership))?;
+            self.audit
+                .record(&[audit_entry(None, Some(&membership))?])?;
+            Ok(())
+        })?;
+        Ok(added)
+    }
+
+    /// Ends the student's membership of the group on left_on, giving 0 if they
+    /// weren't in it.
+    pub fn remove_member(
+        &self,
+        group_id: &str,
+        student_id: &str,
+        left_on: NaiveDate,
+    ) -> Result<usize> {
+        log::debug!("removing student {student_id} from group {group_id}");
+        self.permissions.can_change(student_id)?;
+        let mut wheres = membership_wheres(group_id, student_id);
+        wheres.push(Where::is_null("left_on"));
+        let mut removed = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.memberships.list(&wheres, &QueryOptions::new())?;
+            if old.iter().any(|membership| left_on < membership.joined_on) {
+                return Err(Error::ValidationError(
+                    "the student can't leave the group before they joined it".into(),
+                ));
+            }
+            removed = self.dao.update(
+                &vec!["left_on".into()],
+                Membership::TABLE,
+                vec![left_on.into()],
+                &wheres,
+            )?;
+            let entries = old
+                .iter()
+                .map(|membership| {
+                    let new = Membership {
+                        left_on: Some(left_on),
+                        ..membership.clone()
+                    };
+                    audit_entry(Some(membership), Some(&new))
+                })
+                .collect::<Result<Vec<AuditEntry>>>()?;
+            self.audit.record(&entries)?;
+            Ok(())
+        })?;
+        Ok(removed)
+    }
+
+    /// The students in the group now, in name order.
+    pub fn members(&self, group_id: &str) -> Result<Vec<Student>> {
+        let wheres = vec![
+            Where::new("group_id", Symbol::EQ, group_id.into()),
+            Where::is_null("left_on"),
+        ];
+        let ids: Vec<String> = self
+            .memberships
+            .list(&wheres, &QueryOptions::new())?
+            .into_iter()
+            .map(|m| m.student_id)
+            .collect();
+        self.students.get_many(&ids)
+    }
+
+    /// Every time any student the user can see joined or left the group,
+    /// oldest first.
+    pub fn history(&self, group_id: &str) -> Result<Vec<Membership>> {
+        let mut wheres = vec![Where::new("group_id", Symbol::EQ, group_id.into())];
+        self.permissions.restrict(&mut wheres, "student_id")?;
+        let by_date = QueryOptions::new().order_by("joined_on", Order::Asc);
+        self.memberships.list(&wheres, &by_date)
+    }
+
+    /// The groups the student is in now.
+    pub fn groups_for(&self, student_id: &str) -> Result<Vec<Group>> {
+        self.permissions.can_see(student_id)?;
+        let wheres = vec![
+            Where::new("student_id", Symbol::EQ, student_id.into()),
+            Where::is_null("left_on"),
+        ];
+        let ids: Vec<String> = self
+            .memberships
+            .list(&wheres, &QueryOptions::new())?
+            .into_iter()
+            .map(|m| m.group_id)
+            .collect();
+        let wheres = vec![Where::new("id", Symbol::IN, Value::from(ids))];
+        let by_name = QueryOptions::new().order_by("name", Order::Asc);
+        self.groups.list(&wheres, &by_name)
+    }
+
+    /// Each member of the group with their most recent score.
+    pub fn latest_scores(&self, group_id: &str) -> Result<Vec<MemberScore>> {
+        let members = self.members(group_id)?;
+        let ids: Vec<String> = members.iter().map(|s| s.id.clone()).collect();
+        let mut latest = self.scores.latest_scores(&ids)?;
+        Ok(members
+            .into_iter()
+            .map(|student| MemberScore {
+                latest: latest.remove(&student.id),
+                student,
+            })
+            .collect())
+    }
+
+    fn current(&self, group_id: &str, student_id: &str) -> Result<Vec<Membership>> {
+        let mut wheres = membership_wheres(group_id, student_id);
+        wheres.push(Where::is_null("left_on"));
+        self.memberships.list(&wheres, &QueryOptions::new())
+    }
+}
+
+// memberships are audited under their student, so they show up in the
+// student's history
+fn audit_entry(old: Option<&Membership>, new: Option<&Membership>) -> Result<AuditEntry> {
+    let (id, key) = match old.or(new) {
+        Some(membership) => (
+            membership.student_id.as_str(),
+            format!(
+                "{} {} {}",
+                membership.group_id,
+                membership.student_id,
+                date_to_str(membership.joined_on)
+            ),
+        ),
+        None => ("", String::new()),
+    };
+    AuditEntry::new(Membership::TABLE, &key, id, old, new)
+}
+
+fn membership_wheres(group_id: &str, student_id: &str) -> Vec<Where> {
+    vec![
+        Where::new("group_id", Symbol::EQ, group_id.into()),
+        Where::new("student_id", Symbol::EQ, student_id.into()),
+    ]
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{MemoryDao, SqliteDao};
+    use crate::models::{Operation, Role, SafmedScore};
+    use crate::services::UserService;
+    use crate::useful::date_from_str;
+    use matches::assert_matches;
+
+    fn daos() -> Vec<Arc<dyn Dao>> {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        vec![sqlite, Arc::new(MemoryDao::new())]
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        date_from_str(s).unwrap()
+    }
+
+    // a group with Ben and Sam in it and Gemma left out
+    fn setup(dao: &Arc<dyn Dao>) -> (GroupService, Group, Vec<Student>) {
+        let gs = GroupService::new(Arc::clone(dao));
+        let students = vec![
+            Student::new("Ben", "Jones", "1990-01-23").unwrap(),
+            Student::new("Sam", "Smith", "2001-04-02").unwrap(),
+            Student::new("Gemma", "Forbes", "1988-09-30").unwrap(),
+        ];
+        StudentService::new(Arc::clone(dao))
+            .add_students(&students)
+            .unwrap();
+        let group = Group::new("Year 4 maths", "");
+        gs.add_group(&group).unwrap();
+        for student in &students[..2] {
+            gs.add_member(&group.id, &student.id, date("2021-09-01"))
+                .unwrap();
+        }
+        (gs, group, students)
+    }
+
+    fn names(students: Vec<Student>) -> Vec<String> {
+        students.into_iter().map(|s| s.first_names).collect()
+    }
+
+    #[test]
+    fn test_groups() {
+        for dao in daos() {
+            let gs = GroupService::new(dao);
+            let maths = Group::new("Year 4 maths", "");
+            let reading = Group::new("Reading", "catch up");
+            gs.add_group(&maths).unwrap();
+            gs.add_group(&reading).unwrap();
+            assert_eq!(Ok(vec![reading.clone(), maths.clone()]), gs.all());
+            assert!(matches!(
+                gs.add_group(&Group::new("Reading", "")),
+                Err(Error::Duplicate { .. })
+            ));
+            let renamed = Group {
+                name: "Year 5 maths".into(),
+                ..maths.clone()
+            };
+            assert_eq!(Ok(1), gs.update_group(&renamed));
+            assert_eq!(Ok(renamed), gs.get_by_name("Year 5 maths"));
+            assert_eq!(Ok(1), gs.delete_group(&maths.id));
+            assert_eq!(
+                Err(Error::NotFound(format!("group {}", maths.id))),
+                gs.get(&maths.id)
+            );
+        }
+    }
+
+    #[test]
+    fn test_membership() {
+        for dao in daos() {
+            let (gs, group, students) = setup(&dao);
+            assert_eq!(vec!["Ben", "Sam"], names(gs.members(&group.id).unwrap()));
+            assert_eq!(Ok(vec![group.clone()]), gs.groups_for(&students[0].id));
+            assert!(gs.groups_for(&students[2].id).unwrap().is_empty());
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "the student is already in the group".into()
+                )),
+                gs.add_member(&group.id, &students[0].id, date("2021-10-01"))
+            );
+            assert!(matches!(
+                gs.add_member(&group.id, "nobody", date("2021-10-01")),
+                Err(Error::NotFound(_))
+            ));
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "the student can't leave the group before they joined it".into()
+                )),
+                gs.remove_member(&group.id, &students[0].id, date("2021-08-31"))
+            );
+            // Ben leaves and comes back
+            assert_eq!(
+                Ok(1),
+                gs.remove_member(&group.id, &students[0].id, date("2021-12-17"))
+            );
+            assert_eq!(
+                Ok(0),
+                gs.remove_member(&group.id, &students[0].id, date("2021-12-18"))
+            );
+            assert_eq!(vec!["Sam"], names(gs.members(&group.id).unwrap()));
+            gs.add_member(&group.id, &students[0].id, date("2022-01-04"))
+                .unwrap();
+            let history = gs.history(&group.id).unwrap();
+            assert_eq!(3, history.len());
+            let left: Vec<&Membership> = history.iter().filter(|m| m.left_on.is_some()).collect();
+            assert_eq!(1, left.len());
+            assert_eq!(students[0].id, left[0].student_id);
+            assert_eq!(Some(date("2021-12-17")), left[0].left_on);
+            assert_eq!(date("2022-01-04"), history[2].joined_on);
+            // deleting the group doesn't touch the students
+            gs.delete_group(&group.id).unwrap();
+            assert!(gs.history(&group.id).unwrap().is_empty());
+            let ss = StudentService::new(dao);
+            assert_eq!(3, ss.all().unwrap().len());
+            // but every change to their memberships is in their history
+            let operations: Vec<Operation> = ss
+                .history(&students[0].id)
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.table_name == Membership::TABLE)
+                .map(|e| e.operation)
+                .collect();
+            assert_eq!(
+                vec![
+                    Operation::Insert,
+                    Operation::Update,
+                    Operation::Insert,
+                    Operation::Delete,
+                    Operation::Delete
+                ],
+                operations
+            );
+        }
+    }
+
+    #[test]
+    fn test_membership_permissions() {
+        for dao in daos() {
+            let (_, group, students) = setup(&dao);
+            let session = Session::new();
+            let users = UserService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+            users.add_user("admin", "pw", Role::Admin).unwrap();
+            users.login("admin", "pw").unwrap();
+            let teacher = users.add_user("teacher", "pw", Role::Teacher).unwrap();
+            users.assign(&students[1].id, &teacher.id).unwrap();
+            users.login("teacher", "pw").unwrap();
+            let gs = GroupService::new(Arc::clone(&dao)).with_session(session);
+            assert_matches!(
+                gs.remove_member(&group.id, &students[0].id, date("2021-12-17")),
+                Err(Error::PermissionDenied(_))
+            );
+            assert_eq!(
+                Ok(1),
+                gs.remove_member(&group.id, &students[1].id, date("2021-12-17"))
+            );
+            // Ben's in the group too, and he isn't the teacher's
+            assert_matches!(gs.delete_group(&group.id), Err(Error::PermissionDenied(_)));
+            // and only Sam's comings and goings are the teacher's to see
+            let history = gs.history(&group.id).unwrap();
+            assert_eq!(1, history.len());
+            assert_eq!(students[1].id, history[0].student_id);
+        }
+    }
+
+    #[test]
+    fn test_latest_scores() {
+        for dao in daos() {
+            let (gs, group, students) = setup(&dao);
+            let scores = SafmedScoreService::new(Arc::clone(&dao));
+            scores
+                .add_scores(&[
+                    SafmedScore::new(&students[0].id, 40, 5, "2021-10-01").unwrap(),
+                    SafmedScore::new(&students[0].id, 45, 3, "2021-10-08").unwrap(),
+                    SafmedScore::new(&students[2].id, 50, 1, "2021-10-08").unwrap(),
+                ])
+                .unwrap();
+            let latest = gs.latest_scores(&group.id).unwrap();
+            assert_eq!(2, latest.len());
+            assert_eq!(students[0], latest[0].student);
+            assert_eq!(Some(45), latest[0].latest.as_ref().map(|s| s.correct));
+            // Sam hasn't done a timing yet
+            assert_eq!(students[1], latest[1].student);
+            assert_eq!(None, latest[1].latest);
+        }
+    }
+}
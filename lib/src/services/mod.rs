@@ -1,5 +1,14 @@
+mod audit;
+pub mod deck;
+pub mod group;
+mod permissions;
 pub mod score;
 pub mod student;
+pub mod user;
 
+pub use deck::DeckService;
+pub use group::GroupService;
+pub use permissions::Session;
 pub use score::SafmedScoreService;
 pub use student::StudentService;
+pub use user::UserService;
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use crate::database::{Dao, QueryOptions, Repository, Symbol, Value, Where};
+use crate::errors::{Error, Result};
+use crate::models::{Assignment, Role, User};
+use parking_lot::RwLock;
+
+/// Who is logged in. The services share it, so logging in or out applies to
+/// all of them at once.
+#[derive(Debug, Default)]
+pub struct Session {
+    user: RwLock<Option<User>>,
+}
+
+impl Session {
+    pub fn new() -> Arc<Session> {
+        Arc::new(Session::default())
+    }
+
+    pub fn user(&self) -> Option<User> {
+        self.user.read().clone()
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.user.read().is_some()
+    }
+
+    // only UserService logs people in, once it's checked their password
+    pub(crate) fn set_user(&self, user: Option<User>) {
+        *self.user.write() = user;
+    }
+}
+
+/// Checks what the session's user may do with students and their scores.
+/// Services without a session, as in the cli, aren't restricted at all.
+pub(crate) struct Permissions {
+    session: Option<Arc<Session>>,
+    assignments: Repository<Assignment>,
+}
+
+impl Permissions {
+    pub fn new(dao: Arc<dyn Dao>) -> Self {
+        Permissions {
+            session: None,
+            assignments: Repository::new(dao),
+        }
+    }
+
+    pub fn set_session(&mut self, session: Arc<Session>) {
+        self.session = Some(session);
+    }
+
+    /// The user to check against, None when nothing is checked.
+    pub fn user(&self) -> Result<Option<User>> {
+        match &self.session {
+            None => Ok(None),
+            Some(session) => match session.user() {
+                Some(user) => Ok(Some(user)),
+                None => Err(Error::PermissionDenied("log in first".into())),
+            },
+        }
+    }
+
+    /// Narrows wheres down to the students the user can see, field being the
+    /// column holding the student's id.
+    pub fn restrict(&self, wheres: &mut Vec<Where>, field: &str) -> Result<()> {
+        if let Some(ids) = self.visible()? {
+            wheres.push(Where::new(field, Symbol::IN, Value::from(ids)));
+        }
+        Ok(())
+    }
+
+    pub fn can_see(&self, student_id: &str) -> Result<()> {
+        match self.visible()? {
+            Some(ids) if !ids.iter().any(|id| id == student_id) => Err(Error::PermissionDenied(
+                format!("student {student_id} isn't assigned to you"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn can_change(&self, student_id: &str) -> Result<()> {
+        self.can_edit()?;
+        self.can_see(student_id)
+    }
+
+    /// Everyone but viewers can make changes.
+    pub fn can_edit(&self) -> Result<()> {
+        match self.user()? {
+            Some(user) if user.role == Role::Viewer => Err(Error::PermissionDenied(
+                "viewers can't change anything".into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_admin(&self) -> Result<()> {
+        match self.user()? {
+            Some(user) if user.role != Role::Admin => {
+                Err(Error::PermissionDenied("only admins can do that".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the user can add students, giving the teacher they need to be
+    /// assigned to so they can see them afterwards.
+    pub fn can_add(&self) -> Result<Option<String>> {
+        self.can_edit()?;
+        match self.user()? {
+            Some(user) if user.role == Role::Teacher => Ok(Some(user.id)),
+            _ => Ok(None),
+        }
+    }
+
+    // the ids of the students the user can see, None if they can see everyone
+    fn visible(&self) -> Result<Option<Vec<String>>> {
+        let user = match self.user()? {
+            Some(user) if user.role != Role::Admin => user,
+            _ => return Ok(None),
+        };
+        let wheres = vec![Where::new("user_id", Symbol::EQ, user.id.into())];
+        let assigned = self.assignments.list(&wheres, &QueryOptions::new())?;
+        Ok(Some(assigned.into_iter().map(|a| a.student_id).collect()))
+    }
+}
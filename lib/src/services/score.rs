@@ -1,223 +1,775 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use crate::constant::{SCORE_FIELDS, SCORE_SCHEMA};
-use crate::database::{Dao, Symbol, Where};
-use crate::errors::Result;
-use crate::models::SafmedScore;
+use crate::constant::MAX_PARAMS;
+use crate::database::column;
+use crate::database::{
+    Aggregate, Dao, Entity, Function, Order, QueryOptions, Repository, Symbol, Where,
+};
+use crate::errors::{Error, Result};
+use crate::models::{
+    AuditEntry, Card, CardOutcome, DayPolicy, Deck, MissedCard, SafmedScore, SafmedSummary,
+};
+use crate::services::audit::AuditLog;
+use crate::services::permissions::{Permissions, Session};
 use crate::useful::date_to_str;
+use chrono::NaiveDate;
 
 pub struct SafmedScoreService {
     dao: Arc<dyn Dao>,
+    scores: Repository<SafmedScore>,
+    decks: Repository<Deck>,
+    cards: Repository<Card>,
+    outcomes: Repository<CardOutcome>,
+    audit: AuditLog,
+    permissions: Permissions,
+    policy: DayPolicy,
 }
 
 impl SafmedScoreService {
     pub fn new(dao: Arc<dyn Dao>) -> Self {
-        Self { dao }
+        let scores = Repository::new(Arc::clone(&dao));
+        let decks = Repository::new(Arc::clone(&dao));
+        let cards = Repository::new(Arc::clone(&dao));
+        let outcomes = Repository::new(Arc::clone(&dao));
+        let audit = AuditLog::new(Arc::clone(&dao));
+        let permissions = Permissions::new(Arc::clone(&dao));
+        Self {
+            dao,
+            scores,
+            decks,
+            cards,
+            outcomes,
+            audit,
+            permissions,
+            policy: DayPolicy::default(),
+        }
     }
 
-    pub fn init(&self) -> Result<()> {
-        log::debug!("initialising...");
-        let sqls = [SCORE_SCHEMA];
-        for sql in sqls {
-            log::debug!("executing {sql}");
-            self.dao.execute(sql)?;
-        }
-        Ok(())
+    /// Only lets the session's user see and change the scores of the students
+    /// assigned to them, unless they're an admin.
+    pub fn with_session(mut self, session: Arc<Session>) -> Self {
+        self.permissions.set_session(Arc::clone(&session));
+        self.audit.set_session(session);
+        self
+    }
+
+    /// Picks which of a day's timings the scores handed out for charts and
+    /// summaries come from, the last unless set.
+    pub fn with_policy(mut self, policy: DayPolicy) -> Self {
+        self.policy = policy;
+        self
     }
 
+    /// Adds score as the next timing of its day on its deck, or if it has a
+    /// seq, in place of that timing.
     pub fn add_score(&self, score: &SafmedScore) -> Result<usize> {
         log::debug!("adding score {score:?}");
-        match self.dao.insert(
-            &score_fields(),
-            "safmed",
-            vec![
-                score.id.to_owned().into(),
-                score.correct.into(),
-                score.incorrect.into(),
-                score.date.to_owned().into(),
-            ],
-            true,
-        ) {
-            Ok(num) => Ok(num),
-            Err(e) => Err(e),
-        }
+        self.add_scores(std::slice::from_ref(score))
     }
 
+    /// Changes one of the day's timings, the one with score's seq.
     pub fn update_score(&self, score: &SafmedScore) -> Result<usize> {
         log::debug!("updating score {score:?}");
-        let added = self.dao.update(
-            &vec!["correct".into(), "incorrect".into()],
-            "safmed",
-            vec![score.correct.into(), score.incorrect.into()],
-            &vec![
-                Where::new("id", Symbol::EQ, score.id.to_owned().into()),
-                Where::new("date", Symbol::EQ, date_to_str(score.date).into()),
-            ],
-        )?;
-        Ok(added)
+        self.permissions.can_change(&score.id)?;
+        check_duration(std::slice::from_ref(score))?;
+        if score.seq < 1 {
+            return Err(Error::ValidationError(
+                "say which of the day's timings to change".into(),
+            ));
+        }
+        let wheres = by_timing(score, "id");
+        let mut updated = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.select_scores(&wheres)?;
+            updated = self.dao.update(
+                &vec![
+                    "correct".into(),
+                    "incorrect".into(),
+                    "duration".into(),
+                    "timed_at".into(),
+                ],
+                SafmedScore::TABLE,
+                vec![
+                    score.correct.into(),
+                    score.incorrect.into(),
+                    score.duration.into(),
+                    score.timed_at.into(),
+                ],
+                &wheres,
+            )?;
+            for old in &old {
+                self.audit.record(&[audit_entry(Some(old), Some(score))?])?;
+            }
+            Ok(())
+        })?;
+        Ok(updated)
     }
 
-    pub fn add_scores(&self, scores: &Vec<SafmedScore>) -> Result<usize> {
+    /// Adds scores, each as the next timing of its day on its deck unless it
+    /// has a seq, when it replaces that timing. Replaced scores are kept in
+    /// the audit log.
+    pub fn add_scores(&self, scores: &[SafmedScore]) -> Result<usize> {
+        Ok(self.insert_scores(scores)?.len())
+    }
+
+    // adds scores, giving them back numbered
+    fn insert_scores(&self, scores: &[SafmedScore]) -> Result<Vec<SafmedScore>> {
         log::debug!("adding scores {scores:?}");
-        // get vec of all the args to pass to insert
-        let mut args = Vec::new();
-        // get the latest score for each id
-        let mut latest: HashMap<String, SafmedScore> = HashMap::new();
+        check_duration(scores)?;
+        let mut ids: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
         for score in scores {
-            args.push(score.id.to_owned().into());
-            args.push(score.correct.into());
-            args.push(score.incorrect.into());
-            args.push(score.date.to_owned().into());
-            match latest.get(&score.id.to_owned()) {
-                Some(s) => {
-                    if s.date < score.date {
-                        latest.insert(score.id.to_owned(), score.clone());
-                    }
+            if seen.insert(&score.id) {
+                ids.push(score.id.clone());
+            }
+        }
+        for id in &ids {
+            self.permissions.can_change(id)?;
+        }
+        let decks: HashSet<&String> = scores.iter().map(|score| &score.deck_id).collect();
+        let mut numbered = Vec::new();
+        self.dao.transaction(&mut |_| {
+            // sqlite checks the decks exist but memory doesn't
+            for deck in &decks {
+                if self.decks.get(&[deck.as_str().into()])?.is_none() {
+                    return Err(Error::NotFound(format!("deck {deck}")));
                 }
-                None => {
-                    latest.insert(score.id.to_owned(), score.clone());
+            }
+            // whatever is there for each timing before this insert, and the
+            // last seq of each day
+            let mut existing: HashMap<(String, String, NaiveDate, i32), SafmedScore> =
+                HashMap::new();
+            let mut last: HashMap<(String, String, NaiveDate), i32> = HashMap::new();
+            for chunk in ids.chunks(MAX_PARAMS) {
+                let wheres = vec![Where::new("id", Symbol::IN, chunk.to_vec().into())];
+                for score in self.select_scores(&wheres)? {
+                    let seq = last.entry(day_key(&score)).or_default();
+                    *seq = score.seq.max(*seq);
+                    existing.insert(score_key(&score), score);
                 }
-            };
+            }
+            numbered.clear();
+            let mut entries = Vec::new();
+            for score in scores {
+                let seq = last.entry(day_key(score)).or_default();
+                let score = match score.seq {
+                    0 => SafmedScore {
+                        seq: *seq + 1,
+                        ..score.clone()
+                    },
+                    _ => score.clone(),
+                };
+                *seq = score.seq.max(*seq);
+                let old = existing.insert(score_key(&score), score.clone());
+                entries.push(audit_entry(old.as_ref(), Some(&score))?);
+                numbered.push(score);
+            }
+            self.scores.upsert(&numbered)?;
+            self.audit.record(&entries)?;
+            Ok(())
+        })?;
+        Ok(numbered)
+    }
+
+    /// One of the student's timings, the seq'th on the deck that day.
+    pub fn get_timing(
+        &self,
+        id: &str,
+        deck_id: &str,
+        date: NaiveDate,
+        seq: i32,
+    ) -> Result<SafmedScore> {
+        self.permissions.can_see(id)?;
+        let wheres = vec![
+            Where::new("id", Symbol::EQ, id.into()),
+            Where::new("deck_id", Symbol::EQ, deck_id.into()),
+            Where::new("date", Symbol::EQ, date_to_str(date).into()),
+            Where::new("seq", Symbol::EQ, seq.into()),
+        ];
+        match self.select_scores(&wheres)?.pop() {
+            Some(score) => Ok(score),
+            None => Err(Error::NotFound(format!(
+                "timing {seq} on {}",
+                date_to_str(date)
+            ))),
         }
-        let added = self.dao.insert(&score_fields(), "safmed", args, true)?;
+    }
+
+    /// Every one of the student's timings, oldest first, however many they
+    /// had in a day.
+    pub fn timings(&self, id: &str) -> Result<Vec<SafmedScore>> {
+        self.permissions.can_see(id)?;
+        self.select_scores(&[Where::new("id", Symbol::EQ, id.into())])
+    }
+
+    /// Deletes a single timing along with its card outcomes, leaving the rest
+    /// of the day's timings numbered as they were.
+    pub fn delete_timing(&self, score: &SafmedScore) -> Result<usize> {
+        log::debug!("deleting timing {score:?}");
+        self.permissions.can_change(&score.id)?;
+        let wheres = by_timing(score, "id");
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.select_scores(&wheres)?;
+            let outcomes = by_timing(score, "student_id");
+            let old_outcomes = self.outcomes.list(&outcomes, &QueryOptions::new())?;
+            self.outcomes.delete(&outcomes)?;
+            deleted = self.scores.delete(&wheres)?;
+            for old in &old {
+                self.audit.record(&[audit_entry(Some(old), None)?])?;
+            }
+            let entries = old_outcomes
+                .iter()
+                .map(|outcome| outcome_audit_entry(Some(outcome), None))
+                .collect::<Result<Vec<AuditEntry>>>()?;
+            self.audit.record(&entries)?;
+            Ok(())
+        })?;
+        Ok(deleted)
+    }
+
+    /// Adds score along with whether the student got each of the cards, given
+    /// by id, right. Any outcomes already kept for the timing are replaced.
+    pub fn add_score_with_cards(
+        &self,
+        score: &SafmedScore,
+        cards: &[(String, bool)],
+    ) -> Result<usize> {
+        log::debug!("adding score {score:?} with {} cards", cards.len());
+        let right = cards.iter().filter(|(_, correct)| *correct).count();
+        if right > score.correct as usize || cards.len() - right > score.incorrect as usize {
+            return Err(Error::ValidationError(
+                "more cards than the score has answers".into(),
+            ));
+        }
+        let mut added = 0;
+        self.dao.transaction(&mut |_| {
+            let score = &self.insert_scores(std::slice::from_ref(score))?[0];
+            added = 1;
+            for (card_id, _) in cards {
+                match self.cards.get(&[card_id.as_str().into()])? {
+                    Some(card) if card.deck_id == score.deck_id => (),
+                    Some(_) => {
+                        return Err(Error::ValidationError(format!(
+                            "card {card_id} isn't in deck {}",
+                            score.deck_id
+                        )))
+                    }
+                    None => return Err(Error::NotFound(format!("card {card_id}"))),
+                }
+            }
+            let timing = by_timing(score, "student_id");
+            let mut old: HashMap<String, CardOutcome> = self
+                .outcomes
+                .list(&timing, &QueryOptions::new())?
+                .into_iter()
+                .map(|outcome| (outcome.card_id.clone(), outcome))
+                .collect();
+            self.outcomes.delete(&timing)?;
+            let outcomes: Vec<CardOutcome> = cards
+                .iter()
+                .map(|(card_id, correct)| CardOutcome {
+                    student_id: score.id.clone(),
+                    deck_id: score.deck_id.clone(),
+                    date: score.date,
+                    seq: score.seq,
+                    card_id: card_id.clone(),
+                    correct: *correct,
+                })
+                .collect();
+            self.outcomes.insert(&outcomes)?;
+            let mut entries = outcomes
+                .iter()
+                .map(|outcome| {
+                    outcome_audit_entry(old.remove(&outcome.card_id).as_ref(), Some(outcome))
+                })
+                .collect::<Result<Vec<AuditEntry>>>()?;
+            // the cards that aren't in the timing any more
+            for outcome in old.values() {
+                entries.push(outcome_audit_entry(Some(outcome), None)?);
+            }
+            self.audit.record(&entries)?;
+            Ok(())
+        })?;
         Ok(added)
     }
 
+    /// How the student did on each card in the timing.
+    pub fn card_outcomes(&self, score: &SafmedScore) -> Result<Vec<CardOutcome>> {
+        self.permissions.can_see(&score.id)?;
+        self.outcomes
+            .list(&by_timing(score, "student_id"), &QueryOptions::new())
+    }
+
+    /// The cards the student got wrong in timings between from and to, most
+    /// missed first, at most limit of them. Every timing counts, whatever the
+    /// day policy.
+    pub fn missed_cards(
+        &self,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+        limit: usize,
+    ) -> Result<Vec<MissedCard>> {
+        log::debug!("getting cards missed by {id} from {from} to {to}");
+        self.permissions.can_see(id)?;
+        let records = self.dao.aggregate(
+            CardOutcome::TABLE,
+            &vec![
+                Aggregate::count("seen"),
+                Aggregate::new(Function::Sum, "correct", "right"),
+            ],
+            &vec!["card_id".into()],
+            &vec![
+                Where::new("student_id", Symbol::EQ, id.into()),
+                Where::between("date", from.into(), to.into()),
+            ],
+            &vec![],
+        )?;
+        let mut counts: HashMap<String, (i32, i32)> = HashMap::new();
+        for rec in &records {
+            let card_id = column::<String>(rec, "card_id")?;
+            let seen = column::<i32>(rec, "seen")?;
+            let right = column::<i32>(rec, "right")?;
+            if seen > right {
+                counts.insert(card_id, (seen - right, seen));
+            }
+        }
+        let ids: Vec<String> = counts.keys().cloned().collect();
+        let mut missed = Vec::new();
+        for chunk in ids.chunks(MAX_PARAMS) {
+            let wheres = vec![Where::new("id", Symbol::IN, chunk.to_vec().into())];
+            for card in self.cards.list(&wheres, &QueryOptions::new())? {
+                let (times, seen) = counts[&card.id];
+                missed.push(MissedCard {
+                    card,
+                    missed: times,
+                    seen,
+                });
+            }
+        }
+        // ties go to the card missed the bigger share of the time
+        missed.sort_by(|a, b| {
+            (b.missed, a.seen, &a.card.front).cmp(&(a.missed, b.seen, &b.card.front))
+        });
+        missed.truncate(limit);
+        Ok(missed)
+    }
+
+    fn select_scores(&self, wheres: &[Where]) -> Result<Vec<SafmedScore>> {
+        let mut wheres = wheres.to_vec();
+        self.permissions.restrict(&mut wheres, "id")?;
+        self.scores.list(&wheres, &by_date())
+    }
+
+    // the timing standing for each day, as the policy picks
+    fn picked_scores(&self, wheres: &[Where]) -> Result<Vec<SafmedScore>> {
+        Ok(self.policy.pick(self.select_scores(wheres)?))
+    }
+
+    /// The student's scores, one a day on each deck.
     pub fn get_safmed_scores(&self, id: &str) -> Result<Vec<SafmedScore>> {
-        let records = self.dao.select(
-            &score_fields(),
-            "safmed",
-            &vec![Where::new("id", Symbol::EQ, id.into())],
-        );
-        let mut score_vec = Vec::new();
-        for score_record in records? {
-            let score = match SafmedScore::try_from(score_record) {
-                Ok(score) => score,
-                Err(e) => return Err(e),
-            };
-            score_vec.push(score);
+        self.permissions.can_see(id)?;
+        self.picked_scores(&[Where::new("id", Symbol::EQ, id.into())])
+    }
+
+    /// The student's scores on one deck, oldest first.
+    pub fn get_deck_scores(&self, id: &str, deck_id: &str) -> Result<Vec<SafmedScore>> {
+        self.permissions.can_see(id)?;
+        self.picked_scores(&[
+            Where::new("id", Symbol::EQ, id.into()),
+            Where::new("deck_id", Symbol::EQ, deck_id.into()),
+        ])
+    }
+
+    pub fn get_safmed_scores_between(
+        &self,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<SafmedScore>> {
+        self.permissions.can_see(id)?;
+        self.picked_scores(&[
+            Where::new("id", Symbol::EQ, id.into()),
+            Where::between("date", from.into(), to.into()),
+        ])
+    }
+
+    pub fn scores_for_students(&self, ids: &[String]) -> Result<HashMap<String, Vec<SafmedScore>>> {
+        let mut scores: HashMap<String, Vec<SafmedScore>> = HashMap::new();
+        // each student's scores all come back in the same chunk
+        for chunk in ids.chunks(MAX_PARAMS) {
+            let wheres = vec![Where::new("id", Symbol::IN, chunk.to_vec().into())];
+            for score in self.picked_scores(&wheres)? {
+                scores.entry(score.id.clone()).or_default().push(score);
+            }
+        }
+        Ok(scores)
+    }
+
+    /// The most recent score of each student in ids that has any.
+    pub fn latest_scores(&self, ids: &[String]) -> Result<HashMap<String, SafmedScore>> {
+        let scores = self.scores_for_students(ids)?;
+        Ok(scores
+            .into_iter()
+            .filter_map(|(id, mut scores)| scores.pop().map(|latest| (id, latest)))
+            .collect())
+    }
+
+    /// How each student has done on each deck they've been timed on, in
+    /// answers per minute.
+    pub fn summary_per_student(&self) -> Result<Vec<SafmedSummary>> {
+        log::debug!("summarising scores per student and deck");
+        let function = match self.policy {
+            DayPolicy::First => Function::Min,
+            DayPolicy::Last => Function::Max,
+            // the best timing is worked out from rates, which the Dao can't
+            // compare, so the day's timings are picked here instead
+            DayPolicy::Best => {
+                let picked = self.picked_scores(&[])?;
+                return Ok(merge_summaries(picked.iter().map(SafmedSummary::from)));
+            }
+        };
+        let mut wheres = vec![];
+        self.permissions.restrict(&mut wheres, "id")?;
+        let days = self.dao.aggregate(
+            SafmedScore::TABLE,
+            &vec![Aggregate::new(function, "seq", "seq")],
+            &vec!["id".into(), "deck_id".into(), "date".into()],
+            &wheres,
+            &vec![],
+        )?;
+        let timings = days
+            .iter()
+            .map(|day| {
+                let wheres = ["id", "deck_id", "date", "seq"]
+                    .iter()
+                    .map(|&field| match day.get(field) {
+                        Some(v) => Ok(Where::new(field, Symbol::EQ, v.clone())),
+                        None => Err(Error::ValueError(format!("Missing {field}"))),
+                    })
+                    .collect::<Result<Vec<Where>>>()?;
+                Ok(Where::and(wheres))
+            })
+            .collect::<Result<Vec<Where>>>()?;
+        let mut summaries = Vec::new();
+        // each timing takes four parameters
+        for chunk in timings.chunks(MAX_PARAMS / 4) {
+            // counts only scale to rates over timings of the same length
+            let records = self.dao.aggregate(
+                SafmedScore::TABLE,
+                &summary_aggregates(),
+                &vec!["id".into(), "deck_id".into(), "duration".into()],
+                &vec![Where::or(chunk.to_vec())],
+                &vec![],
+            )?;
+            for rec in records {
+                summaries.push(SafmedSummary::try_from(rec)?);
+            }
         }
-        Ok(score_vec)
+        Ok(merge_summaries(summaries))
     }
 
     pub fn delete_scores(&self, id: &str) -> Result<usize> {
-        self.dao
-            .delete("safmed", &vec![Where::new("id", Symbol::EQ, id.into())])
+        self.permissions.can_change(id)?;
+        let wheres = vec![Where::new("id", Symbol::EQ, id.into())];
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.select_scores(&wheres)?;
+            let outcomes = vec![Where::new("student_id", Symbol::EQ, id.into())];
+            let old_outcomes = self.outcomes.list(&outcomes, &QueryOptions::new())?;
+            self.outcomes.delete(&outcomes)?;
+            deleted = self.scores.delete(&wheres)?;
+            let mut entries = old
+                .iter()
+                .map(|score| audit_entry(Some(score), None))
+                .collect::<Result<Vec<AuditEntry>>>()?;
+            for outcome in &old_outcomes {
+                entries.push(outcome_audit_entry(Some(outcome), None)?);
+            }
+            self.audit.record(&entries)?;
+            Ok(())
+        })?;
+        Ok(deleted)
+    }
+
+    /// Every change made to the student's scores, oldest first.
+    pub fn history(&self, id: &str) -> Result<Vec<AuditEntry>> {
+        self.permissions.can_see(id)?;
+        self.audit.history(id, Some(SafmedScore::TABLE))
+    }
+}
+
+// the wheres picking out score's timing, field being the column holding the
+// student's id
+fn by_timing(score: &SafmedScore, field: &str) -> Vec<Where> {
+    vec![
+        Where::new(field, Symbol::EQ, score.id.to_owned().into()),
+        Where::new("deck_id", Symbol::EQ, score.deck_id.to_owned().into()),
+        Where::new("date", Symbol::EQ, date_to_str(score.date).into()),
+        Where::new("seq", Symbol::EQ, score.seq.into()),
+    ]
+}
+
+fn check_duration(scores: &[SafmedScore]) -> Result<()> {
+    match scores.iter().any(|score| score.duration <= 0) {
+        true => Err(Error::ValidationError(
+            "timings have to last longer than 0 seconds".into(),
+        )),
+        false => Ok(()),
     }
 }
 
-fn score_fields() -> Vec<String> {
-    SCORE_FIELDS
-        .to_vec()
-        .iter()
-        .map(|x| x.to_string())
-        .collect()
+fn day_key(score: &SafmedScore) -> (String, String, NaiveDate) {
+    (score.id.clone(), score.deck_id.clone(), score.date)
+}
+
+fn score_key(score: &SafmedScore) -> (String, String, NaiveDate, i32) {
+    (
+        score.id.clone(),
+        score.deck_id.clone(),
+        score.date,
+        score.seq,
+    )
+}
+
+fn audit_entry(old: Option<&SafmedScore>, new: Option<&SafmedScore>) -> Result<AuditEntry> {
+    let (id, key) = match old.or(new) {
+        Some(score) => (
+            score.id.as_str(),
+            format!(
+                "{} {} {} {}",
+                score.id,
+                score.deck_id,
+                date_to_str(score.date),
+                score.seq
+            ),
+        ),
+        None => ("", String::new()),
+    };
+    AuditEntry::new(SafmedScore::TABLE, &key, id, old, new)
+}
+
+// outcomes are audited under their student, keyed by their timing and card
+pub(crate) fn outcome_audit_entry(
+    old: Option<&CardOutcome>,
+    new: Option<&CardOutcome>,
+) -> Result<AuditEntry> {
+    let (id, key) = match old.or(new) {
+        Some(outcome) => (
+            outcome.student_id.as_str(),
+            format!(
+                "{} {} {} {} {}",
+                outcome.student_id,
+                outcome.deck_id,
+                date_to_str(outcome.date),
+                outcome.seq,
+                outcome.card_id
+            ),
+        ),
+        None => ("", String::new()),
+    };
+    AuditEntry::new(CardOutcome::TABLE, &key, id, old, new)
+}
+
+// one summary for each student and deck, in the order they first came
+fn merge_summaries(summaries: impl IntoIterator<Item = SafmedSummary>) -> Vec<SafmedSummary> {
+    let mut merged: Vec<SafmedSummary> = Vec::new();
+    let mut found: HashMap<(String, String), usize> = HashMap::new();
+    for summary in summaries {
+        match found.get(&(summary.id.clone(), summary.deck_id.clone())) {
+            Some(&i) => merged[i] = merged[i].clone().merge(summary),
+            None => {
+                found.insert((summary.id.clone(), summary.deck_id.clone()), merged.len());
+                merged.push(summary);
+            }
+        }
+    }
+    merged
+}
+
+fn summary_aggregates() -> Vec<Aggregate> {
+    vec![
+        Aggregate::count("timings"),
+        Aggregate::new(Function::Max, "correct", "best_correct"),
+        Aggregate::new(Function::Avg, "correct", "average_correct"),
+        Aggregate::new(Function::Avg, "incorrect", "average_incorrect"),
+        Aggregate::new(Function::Min, "date", "first_date"),
+        Aggregate::new(Function::Max, "date", "last_date"),
+    ]
+}
+
+// scores are always handed out oldest first
+fn by_date() -> QueryOptions {
+    QueryOptions::new()
+        .order_by("date", Order::Asc)
+        .order_by("seq", Order::Asc)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        database::{dao::MockDao, Record},
-        useful::date_from_str,
+        constant::DEFAULT_DECK,
+        database::{dao::MockDao, MemoryDao, Record, SqliteDao},
+        errors::Error,
+        models::{Operation, Role, Student},
+        services::{DeckService, StudentService, UserService},
+        useful::{date_from_str, datetime_from_str},
     };
+    use matches::assert_matches;
 
     use super::*;
 
     #[test]
     fn test_add_score() {
-        let mut dao = MockDao::new();
-        let score = SafmedScore::new("st1".into(), 99, 11, "2022-01-01".into()).unwrap();
-        dao.expect_insert()
-            .withf(move |f, t, args, rep| {
-                *f == score_fields()
-                    && t == "safmed"
-                    && *args == vec!["st1".into(), 99.into(), 11.into(), "2022-01-01".into()]
-                    && *rep == true
-            })
-            .times(1)
-            .returning(move |_, _, _, _| Ok(1));
-        let ss = SafmedScoreService::new(Arc::new(dao));
+        let ss = SafmedScoreService::new(Arc::new(MemoryDao::new()));
+        let score = SafmedScore::new("st1", 99, 11, "2022-01-01").unwrap();
         assert_eq!(Ok(1), ss.add_score(&score));
+        let first = SafmedScore { seq: 1, ..score };
+        assert_eq!(Ok(vec![first.clone()]), ss.get_safmed_scores("st1"));
+        // a second timing on the same day is kept as the day's next
+        let retake = SafmedScore::new("st1", 80, 2, "2022-01-01").unwrap();
+        assert_eq!(Ok(1), ss.add_score(&retake));
+        let second = SafmedScore { seq: 2, ..retake };
+        assert_eq!(Ok(vec![second.clone()]), ss.get_safmed_scores("st1"));
+        assert_eq!(Ok(vec![first.clone(), second]), ss.timings("st1"));
+        // unless it says which timing it replaces
+        let fixed = SafmedScore {
+            correct: 98,
+            ..first.clone()
+        };
+        assert_eq!(Ok(1), ss.add_score(&fixed));
+        let history = ss.history("st1").unwrap();
+        assert_eq!(3, history.len());
+        assert_eq!(Operation::Insert, history[1].operation);
+        assert_eq!(Operation::Update, history[2].operation);
+        assert_eq!("st1 general 2022-01-01 1", history[2].row_key);
+        assert_eq!(
+            Some(serde_json::to_string(&first).unwrap()),
+            history[2].old_value
+        );
     }
 
     #[test]
     fn test_add_scores() {
-        let mut dao = MockDao::new();
+        let ss = SafmedScoreService::new(Arc::new(MemoryDao::new()));
         let scores = vec![
-            SafmedScore::new("st1".into(), 99, 11, "2022-01-01".into()).unwrap(),
-            SafmedScore::new("st1".into(), 87, 8, "2022-01-02".into()).unwrap(),
+            SafmedScore::new("st1", 99, 11, "2022-01-01").unwrap(),
+            SafmedScore::new("st1", 87, 8, "2022-01-02").unwrap(),
+            SafmedScore::new("st2", 99, 11, "2022-01-01").unwrap(),
+            SafmedScore::new("st1", 90, 1, "2022-01-02").unwrap(),
         ];
-        dao.expect_insert()
-            .withf(move |f, t, args, rep| {
-                *f == score_fields()
-                    && t == "safmed"
-                    && *args
-                        == vec![
-                            "st1".into(),
-                            99.into(),
-                            11.into(),
-                            "2022-01-01".into(),
-                            "st1".into(),
-                            87.into(),
-                            8.into(),
-                            "2022-01-02".into(),
-                        ]
-                    && *rep == true
-            })
-            .times(1)
-            .returning(move |_, _, _, _| Ok(2));
-        let ss = SafmedScoreService::new(Arc::new(dao));
-        assert_eq!(Ok(2), ss.add_scores(&scores));
+        assert_eq!(Ok(4), ss.add_scores(&scores));
+        let seqs: Vec<(NaiveDate, i32)> = ss
+            .timings("st1")
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.date, s.seq))
+            .collect();
+        assert_eq!(
+            vec![
+                (scores[0].date, 1),
+                (scores[1].date, 1),
+                (scores[3].date, 2)
+            ],
+            seqs
+        );
+        let operations: Vec<Operation> = ss
+            .history("st1")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.operation)
+            .collect();
+        assert_eq!(vec![Operation::Insert; 3], operations);
     }
 
     #[test]
-    fn test_add_scores_for_multiple_students() {
-        let mut dao = MockDao::new();
-        let scores = vec![
-            SafmedScore::new("st1".into(), 99, 11, "2022-01-01".into()).unwrap(),
-            SafmedScore::new("st1".into(), 87, 8, "2022-01-02".into()).unwrap(),
-            SafmedScore::new("st2".into(), 99, 11, "2022-01-01".into()).unwrap(),
-            SafmedScore::new("st3".into(), 87, 8, "2022-01-02".into()).unwrap(),
-        ];
-        dao.expect_insert()
-            .withf(move |fields, table, args, rep| {
-                *fields == score_fields()
-                    && table == "safmed"
-                    && *args
-                        == vec![
-                            "st1".into(),
-                            99.into(),
-                            11.into(),
-                            "2022-01-01".into(),
-                            "st1".into(),
-                            87.into(),
-                            8.into(),
-                            "2022-01-02".into(),
-                            "st2".into(),
-                            99.into(),
-                            11.into(),
-                            "2022-01-01".into(),
-                            "st3".into(),
-                            87.into(),
-                            8.into(),
-                            "2022-01-02".into(),
-                        ]
-                    && *rep == true
-            })
-            .times(1)
-            .returning(move |_, _, _, _| Ok(4));
-        let ss = SafmedScoreService::new(Arc::new(dao));
-        assert_eq!(Ok(4), ss.add_scores(&scores));
+    fn test_update_and_delete_scores() {
+        let ss = SafmedScoreService::new(Arc::new(MemoryDao::new()));
+        ss.add_score(&SafmedScore::new("st1", 99, 11, "2022-01-01").unwrap())
+            .unwrap();
+        let update = SafmedScore::new("st1", 98, 12, "2022-01-01").unwrap();
+        assert_eq!(
+            Err(Error::ValidationError(
+                "say which of the day's timings to change".into()
+            )),
+            ss.update_score(&update)
+        );
+        let update = SafmedScore { seq: 1, ..update };
+        assert_eq!(Ok(1), ss.update_score(&update));
+        assert_eq!(Ok(1), ss.delete_scores("st1"));
+        let history = ss.history("st1").unwrap();
+        assert_eq!(3, history.len());
+        assert_eq!(Operation::Update, history[1].operation);
+        assert_eq!(Operation::Delete, history[2].operation);
+        assert_eq!(
+            Some(serde_json::to_string(&update).unwrap()),
+            history[2].old_value
+        );
+    }
+
+    #[test]
+    fn test_timings() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            let ss = SafmedScoreService::new(Arc::clone(&dao));
+            for (correct, incorrect) in [(40, 5), (50, 2), (45, 1)] {
+                ss.add_score(&SafmedScore::new(&ben.id, correct, incorrect, "2022-01-01").unwrap())
+                    .unwrap();
+            }
+            let date = date_from_str("2022-01-01").unwrap();
+            let second = ss.get_timing(&ben.id, DEFAULT_DECK, date, 2).unwrap();
+            assert_eq!(50, second.correct);
+            // each policy picks its own timing for the day
+            for (policy, correct) in [
+                (DayPolicy::First, 40),
+                (DayPolicy::Last, 45),
+                (DayPolicy::Best, 50),
+            ] {
+                let ss = SafmedScoreService::new(Arc::clone(&dao)).with_policy(policy);
+                let scores = ss.get_safmed_scores(&ben.id).unwrap();
+                assert_eq!(
+                    vec![correct],
+                    scores.iter().map(|s| s.correct).collect::<Vec<i32>>()
+                );
+                let summary = ss.summary_per_student().unwrap().pop().unwrap();
+                assert_eq!(1, summary.timings);
+                assert_eq!(correct as f64, summary.best_correct_rate);
+            }
+            let edited = SafmedScore {
+                correct: 55,
+                timed_at: datetime_from_str("2022-01-01 09:30:00").unwrap(),
+                ..second.clone()
+            };
+            assert_eq!(Ok(1), ss.update_score(&edited));
+            assert_eq!(Ok(edited), ss.get_timing(&ben.id, DEFAULT_DECK, date, 2));
+            assert_eq!(Ok(1), ss.delete_timing(&second));
+            let seqs: Vec<i32> = ss.timings(&ben.id).unwrap().iter().map(|s| s.seq).collect();
+            assert_eq!(vec![1, 3], seqs);
+            assert_eq!(
+                Err(Error::NotFound("timing 2 on 2022-01-01".into())),
+                ss.get_timing(&ben.id, DEFAULT_DECK, date, 2)
+            );
+            // the next timing still goes on the end
+            ss.add_score(&SafmedScore::new(&ben.id, 30, 3, "2022-01-01").unwrap())
+                .unwrap();
+            assert_eq!(4, ss.timings(&ben.id).unwrap()[2].seq);
+        }
     }
 
     #[test]
     fn test_get_safmed_scores() {
         let mut dao = MockDao::new();
         dao.expect_select()
-            .withf(move |_, table, _| table == "safmed")
+            .withf(move |_, table, _, options| table == "safmed" && *options == by_date())
             .times(1)
-            .returning(move |_, _, _| {
+            .returning(move |_, _, _, _| {
                 Ok(vec![
                     Record::from([
                         ("id".into(), "st1".into()),
@@ -247,22 +799,464 @@ mod tests {
                     id: "st1".into(),
                     correct: 89.into(),
                     incorrect: 19.into(),
-                    date: date_from_str("2021-01-01").unwrap()
+                    date: date_from_str("2021-01-01").unwrap(),
+                    deck_id: "general".into(),
+                    duration: 60,
+                    seq: 1,
+                    timed_at: datetime_from_str("2021-01-01 00:00:00").unwrap(),
                 },
                 SafmedScore {
                     id: "st1".into(),
                     correct: 89.into(),
                     incorrect: 19.into(),
-                    date: date_from_str("2021-01-02").unwrap()
+                    date: date_from_str("2021-01-02").unwrap(),
+                    deck_id: "general".into(),
+                    duration: 60,
+                    seq: 1,
+                    timed_at: datetime_from_str("2021-01-02 00:00:00").unwrap(),
                 },
                 SafmedScore {
                     id: "st1".into(),
                     correct: 89.into(),
                     incorrect: 19.into(),
-                    date: date_from_str("2021-01-03").unwrap()
+                    date: date_from_str("2021-01-03").unwrap(),
+                    deck_id: "general".into(),
+                    duration: 60,
+                    seq: 1,
+                    timed_at: datetime_from_str("2021-01-03 00:00:00").unwrap(),
                 },
             ])
         );
     }
-}
 
+    #[test]
+    fn test_get_safmed_scores_between() {
+        let mut dao = MockDao::new();
+        dao.expect_select()
+            .withf(move |f, table, wheres, options| {
+                *f == SafmedScore::columns()
+                    && table == "safmed"
+                    && *wheres
+                        == vec![
+                            Where::new("id", Symbol::EQ, "st1".into()),
+                            Where::between(
+                                "date",
+                                date_from_str("2021-01-01").unwrap().into(),
+                                date_from_str("2021-01-31").unwrap().into(),
+                            ),
+                        ]
+                    && *options == by_date()
+            })
+            .times(1)
+            .returning(move |_, _, _, _| {
+                Ok(vec![Record::from([
+                    ("id".into(), "st1".into()),
+                    ("correct".into(), 89.into()),
+                    ("incorrect".into(), 19.into()),
+                    ("date".into(), "2021-01-02".into()),
+                ])])
+            });
+        let ss = SafmedScoreService::new(Arc::new(dao));
+        assert_eq!(
+            ss.get_safmed_scores_between(
+                "st1",
+                date_from_str("2021-01-01").unwrap(),
+                date_from_str("2021-01-31").unwrap()
+            ),
+            Ok(vec![SafmedScore {
+                seq: 1,
+                ..SafmedScore::new("st1", 89, 19, "2021-01-02").unwrap()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_scores_for_students() {
+        let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        dao.init().unwrap();
+        let students = vec![
+            Student::new("Ben", "Jones", "1990-01-23").unwrap(),
+            Student::new("Gemma", "Forbes", "1988-09-30").unwrap(),
+            Student::new("Sam", "Smith", "2001-04-02").unwrap(),
+        ];
+        StudentService::new(Arc::clone(&dao))
+            .add_students(&students)
+            .unwrap();
+        let (ben, gemma, sam) = (&students[0].id, &students[1].id, &students[2].id);
+        let ss = SafmedScoreService::new(Arc::clone(&dao));
+        ss.add_scores(&[
+            SafmedScore::new(ben, 99, 11, "2022-01-01").unwrap(),
+            SafmedScore::new(ben, 87, 8, "2022-01-02").unwrap(),
+            SafmedScore::new(gemma, 50, 2, "2022-01-01").unwrap(),
+            SafmedScore::new(sam, 20, 4, "2022-01-01").unwrap(),
+        ])
+        .unwrap();
+        let actual = ss
+            .scores_for_students(&[ben.clone(), gemma.clone()])
+            .unwrap();
+        assert_eq!(2, actual.len());
+        assert_eq!(2, actual[ben].len());
+        assert_eq!(
+            vec![SafmedScore {
+                seq: 1,
+                ..SafmedScore::new(gemma, 50, 2, "2022-01-01").unwrap()
+            }],
+            actual[gemma]
+        );
+        assert!(!actual.contains_key(sam));
+        // more ids than sqlite takes parameters in one go
+        let mut ids: Vec<String> = (0..MAX_PARAMS).map(|i| format!("nobody{i}")).collect();
+        ids.push(sam.clone());
+        let actual = ss.scores_for_students(&ids).unwrap();
+        assert_eq!(vec![sam], actual.keys().collect::<Vec<&String>>());
+    }
+
+    #[test]
+    fn test_summary_per_student() {
+        let mut dao = MockDao::new();
+        let group_by =
+            |last: &str| -> Vec<String> { vec!["id".into(), "deck_id".into(), last.into()] };
+        dao.expect_aggregate()
+            .withf(move |table, aggregates, group_by_, wheres, having| {
+                table == "safmed"
+                    && *aggregates == vec![Aggregate::new(Function::Max, "seq", "seq")]
+                    && *group_by_ == group_by("date")
+                    && wheres.is_empty()
+                    && having.is_empty()
+            })
+            .times(1)
+            .returning(move |_, _, _, _, _| {
+                let day = |seq: i32, date: &str| {
+                    Record::from([
+                        ("id".into(), "st1".into()),
+                        ("deck_id".into(), "general".into()),
+                        ("date".into(), date.into()),
+                        ("seq".into(), seq.into()),
+                    ])
+                };
+                Ok(vec![day(2, "2022-01-01"), day(1, "2022-01-02")])
+            });
+        dao.expect_aggregate()
+            .withf(move |table, aggregates, group_by_, wheres, having| {
+                let day = |seq: i32, date: &str| {
+                    Where::and(vec![
+                        Where::new("id", Symbol::EQ, "st1".into()),
+                        Where::new("deck_id", Symbol::EQ, "general".into()),
+                        Where::new("date", Symbol::EQ, date.into()),
+                        Where::new("seq", Symbol::EQ, seq.into()),
+                    ])
+                };
+                table == "safmed"
+                    && *aggregates == summary_aggregates()
+                    && *group_by_ == group_by("duration")
+                    && *wheres == vec![Where::or(vec![day(2, "2022-01-01"), day(1, "2022-01-02")])]
+                    && having.is_empty()
+            })
+            .times(1)
+            .returning(move |_, _, _, _, _| {
+                Ok(vec![Record::from([
+                    ("id".into(), "st1".into()),
+                    ("deck_id".into(), "general".into()),
+                    ("duration".into(), 60.into()),
+                    ("timings".into(), 2.into()),
+                    ("best_correct".into(), 99.into()),
+                    ("average_correct".into(), 93.0.into()),
+                    ("average_incorrect".into(), 9.5.into()),
+                    ("first_date".into(), "2022-01-01".into()),
+                    ("last_date".into(), "2022-01-02".into()),
+                ])])
+            });
+        let ss = SafmedScoreService::new(Arc::new(dao));
+        assert_eq!(
+            Ok(vec![SafmedSummary {
+                id: "st1".into(),
+                deck_id: "general".into(),
+                timings: 2,
+                best_correct_rate: 99.0,
+                average_correct_rate: 93.0,
+                average_incorrect_rate: 9.5,
+                first_date: date_from_str("2022-01-01").unwrap(),
+                last_date: date_from_str("2022-01-02").unwrap(),
+            }]),
+            ss.summary_per_student()
+        );
+    }
+
+    #[test]
+    fn test_summary_per_student_in_db() {
+        let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        dao.init().unwrap();
+        let students = vec![
+            Student::new("Ben", "Jones", "1990-01-23").unwrap(),
+            Student::new("Gemma", "Forbes", "1988-09-30").unwrap(),
+        ];
+        StudentService::new(Arc::clone(&dao))
+            .add_students(&students)
+            .unwrap();
+        let (ben, gemma) = (&students[0].id, &students[1].id);
+        let ss = SafmedScoreService::new(Arc::clone(&dao));
+        ss.add_scores(&[
+            SafmedScore::new(ben, 99, 11, "2022-01-01").unwrap(),
+            SafmedScore::new(ben, 87, 8, "2022-01-03").unwrap(),
+            SafmedScore::new(gemma, 50, 2, "2022-01-02").unwrap(),
+        ])
+        .unwrap();
+        let mut summaries = ss.summary_per_student().unwrap();
+        summaries.sort_by_key(|s| std::cmp::Reverse(s.timings));
+        assert_eq!(2, summaries.len());
+        assert_eq!(
+            SafmedSummary {
+                id: ben.clone(),
+                deck_id: "general".into(),
+                timings: 2,
+                best_correct_rate: 99.0,
+                average_correct_rate: 93.0,
+                average_incorrect_rate: 9.5,
+                first_date: date_from_str("2022-01-01").unwrap(),
+                last_date: date_from_str("2022-01-03").unwrap(),
+            },
+            summaries[0]
+        );
+        assert_eq!(1, summaries[1].timings);
+    }
+
+    #[test]
+    fn test_summary_uses_rates() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            let ss = SafmedScoreService::new(Arc::clone(&dao));
+            let timed = |correct, incorrect, duration, date| SafmedScore {
+                duration,
+                ..SafmedScore::new(&ben.id, correct, incorrect, date).unwrap()
+            };
+            // 40 and 30 a minute, then 20 a minute over two minutes
+            ss.add_scores(&[
+                timed(20, 2, 30, "2022-01-01"),
+                timed(15, 1, 30, "2022-01-02"),
+                timed(40, 6, 120, "2022-01-03"),
+            ])
+            .unwrap();
+            let summary = ss.summary_per_student().unwrap().pop().unwrap();
+            assert_eq!(3, summary.timings);
+            assert_eq!(40.0, summary.best_correct_rate);
+            assert_eq!(30.0, summary.average_correct_rate);
+            assert_eq!(3.0, summary.average_incorrect_rate);
+            assert_eq!(date_from_str("2022-01-03").unwrap(), summary.last_date);
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "timings have to last longer than 0 seconds".into()
+                )),
+                ss.add_score(&timed(20, 2, 0, "2022-01-04"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_decks() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            let phonics = Deck::new("Phonics", "reading", 40, "").unwrap();
+            Repository::new(Arc::clone(&dao))
+                .insert(std::slice::from_ref(&phonics))
+                .unwrap();
+            let ss = SafmedScoreService::new(Arc::clone(&dao));
+            let general = SafmedScore::new(&ben.id, 50, 2, "2022-01-01").unwrap();
+            let on_phonics = SafmedScore {
+                deck_id: phonics.id.clone(),
+                ..SafmedScore::new(&ben.id, 30, 5, "2022-01-01").unwrap()
+            };
+            // a score on each deck on the same day
+            assert_eq!(Ok(2), ss.add_scores(&[general.clone(), on_phonics.clone()]));
+            assert_eq!(
+                Ok(vec![SafmedScore {
+                    seq: 1,
+                    ..on_phonics.clone()
+                }]),
+                ss.get_deck_scores(&ben.id, &phonics.id)
+            );
+            assert_eq!(2, ss.get_safmed_scores(&ben.id).unwrap().len());
+            let mut summaries = ss.summary_per_student().unwrap();
+            summaries.sort_by(|a, b| a.best_correct_rate.total_cmp(&b.best_correct_rate));
+            let decks: Vec<&str> = summaries.iter().map(|s| s.deck_id.as_str()).collect();
+            assert_eq!(vec![phonics.id.as_str(), DEFAULT_DECK], decks);
+            assert_eq!(
+                Err(Error::NotFound("deck nope".into())),
+                ss.add_score(&SafmedScore {
+                    deck_id: "nope".into(),
+                    ..general
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_card_outcomes() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            let ds = DeckService::new(Arc::clone(&dao));
+            let tables = Deck::new("Times tables", "maths", 2, "").unwrap();
+            ds.add_deck(&tables).unwrap();
+            let seven = Card::new(&tables.id, "7 x 8", "56", &[]).unwrap();
+            let general = Card::new(DEFAULT_DECK, "a", "", &[]).unwrap();
+            ds.add_cards(&[seven.clone(), general.clone()]).unwrap();
+            let ss = SafmedScoreService::new(Arc::clone(&dao));
+            let score = SafmedScore {
+                deck_id: tables.id.clone(),
+                ..SafmedScore::new(&ben.id, 1, 1, "2022-01-01").unwrap()
+            };
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "more cards than the score has answers".into()
+                )),
+                ss.add_score_with_cards(
+                    &score,
+                    &[(seven.id.clone(), true), (general.id.clone(), true)]
+                )
+            );
+            assert_eq!(
+                Err(Error::ValidationError(format!(
+                    "card {} isn't in deck {}",
+                    general.id, tables.id
+                ))),
+                ss.add_score_with_cards(&score, &[(general.id.clone(), false)])
+            );
+            // nothing is kept when the cards are wrong
+            assert_eq!(Ok(vec![]), ss.get_safmed_scores(&ben.id));
+            assert_eq!(
+                Ok(1),
+                ss.add_score_with_cards(&score, &[(seven.id.clone(), false)])
+            );
+            // a retake has outcomes of its own
+            assert_eq!(
+                Ok(1),
+                ss.add_score_with_cards(&score, &[(seven.id.clone(), true)])
+            );
+            let first = SafmedScore { seq: 1, ..score };
+            let retake = SafmedScore {
+                seq: 2,
+                ..first.clone()
+            };
+            assert!(!ss.card_outcomes(&first).unwrap()[0].correct);
+            assert!(ss.card_outcomes(&retake).unwrap()[0].correct);
+            // and replacing a timing replaces its outcomes
+            assert_eq!(
+                Ok(1),
+                ss.add_score_with_cards(&first, &[(seven.id.clone(), true)])
+            );
+            let outcomes = ss.card_outcomes(&first).unwrap();
+            assert_eq!(1, outcomes.len());
+            assert!(outcomes[0].correct);
+            assert_eq!(Ok(1), ss.delete_timing(&retake));
+            assert_eq!(Ok(vec![]), ss.card_outcomes(&retake));
+            ss.delete_scores(&ben.id).unwrap();
+            assert_eq!(Ok(vec![]), ss.card_outcomes(&first));
+            // every outcome that came and went is in Ben's history
+            let operations: Vec<Operation> = StudentService::new(Arc::clone(&dao))
+                .history(&ben.id)
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.table_name == CardOutcome::TABLE)
+                .map(|e| e.operation)
+                .collect();
+            assert_eq!(
+                vec![
+                    Operation::Insert,
+                    Operation::Insert,
+                    Operation::Update,
+                    Operation::Delete,
+                    Operation::Delete
+                ],
+                operations
+            );
+        }
+    }
+
+    #[test]
+    fn test_missed_cards() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            let ds = DeckService::new(Arc::clone(&dao));
+            let cards: Vec<Card> = ["a", "b", "c", "d"]
+                .iter()
+                .map(|front| Card::new(DEFAULT_DECK, front, "", &[]).unwrap())
+                .collect();
+            ds.add_cards(&cards).unwrap();
+            let ss = SafmedScoreService::new(Arc::clone(&dao));
+            // a is missed twice, b once in two goes, c once in one, d never
+            let timings = [
+                ("2022-01-01", vec![(0, false), (1, false), (3, true)]),
+                ("2022-01-02", vec![(0, false), (1, true), (2, false)]),
+                ("2022-01-03", vec![(0, true), (3, true)]),
+                ("2022-02-01", vec![(3, false)]),
+            ];
+            for (date, outcomes) in timings {
+                let outcomes: Vec<(String, bool)> = outcomes
+                    .into_iter()
+                    .map(|(i, correct)| (cards[i].id.clone(), correct))
+                    .collect();
+                let score = SafmedScore::new(&ben.id, 3, 3, date).unwrap();
+                ss.add_score_with_cards(&score, &outcomes).unwrap();
+            }
+            let from = date_from_str("2022-01-01").unwrap();
+            let to = date_from_str("2022-01-31").unwrap();
+            let missed = ss.missed_cards(&ben.id, from, to, 10).unwrap();
+            let fronts: Vec<(&str, i32, i32)> = missed
+                .iter()
+                .map(|m| (m.card.front.as_str(), m.missed, m.seen))
+                .collect();
+            assert_eq!(vec![("a", 2, 3), ("c", 1, 1), ("b", 1, 2)], fronts);
+            assert_eq!(1, ss.missed_cards(&ben.id, from, to, 1).unwrap().len());
+            assert_eq!(Ok(vec![]), ss.missed_cards("nobody", from, to, 10));
+        }
+    }
+
+    #[test]
+    fn test_permissions() {
+        let dao: Arc<dyn Dao> = Arc::new(MemoryDao::new());
+        let session = Session::new();
+        let users = UserService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+        users.add_user("admin", "pw", Role::Admin).unwrap();
+        let ss = SafmedScoreService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+        let mine = SafmedScore::new("st1", 40, 5, "2022-01-01").unwrap();
+        let theirs = SafmedScore::new("st2", 50, 1, "2022-01-01").unwrap();
+        assert_eq!(
+            Err(Error::PermissionDenied("log in first".into())),
+            ss.add_score(&mine)
+        );
+        users.login("admin", "pw").unwrap();
+        let teacher = users.add_user("teacher", "pw", Role::Teacher).unwrap();
+        users.assign("st1", &teacher.id).unwrap();
+        ss.add_scores(&[mine.clone(), theirs.clone()]).unwrap();
+        users.login("teacher", "pw").unwrap();
+        assert_matches!(ss.add_score(&theirs), Err(Error::PermissionDenied(_)));
+        assert_matches!(ss.get_safmed_scores("st2"), Err(Error::PermissionDenied(_)));
+        assert_matches!(ss.delete_scores("st2"), Err(Error::PermissionDenied(_)));
+        let both = ss
+            .scores_for_students(&["st1".into(), "st2".into()])
+            .unwrap();
+        assert_eq!(vec!["st1"], both.keys().collect::<Vec<&String>>());
+        let summary = ss.summary_per_student().unwrap();
+        assert_eq!(1, summary.len());
+        assert_eq!(Ok(1), ss.update_score(&SafmedScore { seq: 1, ..mine }));
+    }
+}
@@ -1,145 +1,300 @@
-use crate::constant::*;
-use crate::database::{Dao, Symbol, Value, Where};
+use crate::constant::MAX_PARAMS;
+use crate::database::{Dao, Entity, Order, QueryOptions, Repository, Symbol, Value, Where};
 use crate::errors::{Error, Result};
-use crate::models::Student;
-use crate::useful::date_to_str;
+use crate::models::{Assignment, AuditEntry, Student};
+use crate::services::audit::AuditLog;
+use crate::services::permissions::{Permissions, Session};
+use crate::useful::curr_datetime;
+use chrono::NaiveDateTime;
 use std::sync::Arc;
 
 pub struct StudentService {
     dao: Arc<dyn Dao>,
+    students: Repository<Student>,
+    audit: AuditLog,
+    permissions: Permissions,
 }
 
 impl StudentService {
     pub fn new(dao: Arc<dyn Dao>) -> Self {
         log::debug!("created new StudentService");
-        Self { dao }
+        let students = Repository::new(Arc::clone(&dao));
+        let audit = AuditLog::new(Arc::clone(&dao));
+        let permissions = Permissions::new(Arc::clone(&dao));
+        Self {
+            dao,
+            students,
+            audit,
+            permissions,
+        }
     }
 
-    pub fn init(&self) -> Result<()> {
-        log::debug!("initialising...");
-        let sqls = [STUDENT_SCHEMA];
-        for sql in sqls {
-            log::debug!("executing {sql}");
-            self.dao.execute(sql)?;
-        }
-        Ok(())
-    }
-
-    fn select_students(&self, wheres: &Vec<Where>) -> Result<Vec<Student>> {
-        // get all the scores first, in a hashmap of id -> vec of scores with that id
-        // unless the wheres contain a Where that looks for an id, in which case we might as well only look for those scores
-        // get the relevant students based on the wheres
-        let students = self.dao.select(&student_fields(), "student", wheres);
-        let mut student_vec = Vec::new();
-        for student_record in students? {
-            let student = match Student::try_from(student_record) {
-                Ok(student) => student,
-                Err(e) => return Err(e),
-            };
-            student_vec.push(student);
-        }
-        log::debug!("got {:#?}", &student_vec);
-        Ok(student_vec)
+    /// Only lets the session's user see and change the students assigned to
+    /// them, unless they're an admin.
+    pub fn with_session(mut self, session: Arc<Session>) -> Self {
+        self.permissions.set_session(Arc::clone(&session));
+        self.audit.set_session(session);
+        self
     }
 
+    fn select_students(&self, wheres: &[Where], options: &QueryOptions) -> Result<Vec<Student>> {
+        let mut wheres = wheres.to_vec();
+        self.permissions.restrict(&mut wheres, "id")?;
+        let students = self.students.list(&wheres, options)?;
+        log::debug!("got {:#?}", &students);
+        Ok(students)
+    }
+
+    /// Every student that hasn't been archived.
     pub fn all(&self) -> Result<Vec<Student>> {
         log::debug!("getting all students");
-        self.select_students(&vec![])
+        self.select_students(&[active()], &by_name())
+    }
+
+    pub fn archived(&self) -> Result<Vec<Student>> {
+        log::debug!("getting archived students");
+        self.select_students(&[!active()], &by_name())
+    }
+
+    /// A sorted page of the students that haven't been archived, in name order
+    /// unless the options say otherwise.
+    pub fn list(&self, options: &QueryOptions) -> Result<Vec<Student>> {
+        log::debug!("listing students with {options:?}");
+        let mut options = options.clone();
+        if options.order_by.is_empty() {
+            options.order_by = by_name().order_by;
+        }
+        for (field, _) in &options.order_by {
+            if !Student::COLUMNS.contains(&field.as_str()) {
+                return Err(Error::ValidationError(format!(
+                    "can't sort students by {field}"
+                )));
+            }
+        }
+        self.select_students(&[active()], &options)
+    }
+
+    /// The students that haven't been archived whose names, date of birth, upn,
+    /// class or notes match text, best match first, ignoring case and accents
+    /// and allowing for small typos.
+    pub fn search(&self, text: &str) -> Result<Vec<Student>> {
+        log::debug!("searching students for {text:?}");
+        let mut wheres = vec![active()];
+        self.permissions.restrict(&mut wheres, "id")?;
+        self.students.search(text, &wheres)
     }
 
     pub fn get(&self, id: &str) -> Result<Student> {
         log::debug!("getting student with id {id}");
-        let wheres = vec![Where::new("id", Symbol::EQ, Value::from(id))];
-        let mut result = self.select_students(&wheres)?;
-        match result.len() {
-            1 => Ok(result.remove(0)),
-            _ => Err(Error::NoStudent),
+        self.permissions.can_see(id)?;
+        match self.students.get(&[id.into()])? {
+            Some(student) => Ok(student),
+            None => Err(Error::NotFound(format!("student {id}"))),
         }
     }
 
+    pub fn get_many(&self, ids: &[String]) -> Result<Vec<Student>> {
+        log::debug!("getting students with ids {ids:?}");
+        let mut students = Vec::new();
+        for chunk in ids.chunks(MAX_PARAMS) {
+            let wheres = vec![Where::new("id", Symbol::IN, Value::from(chunk.to_vec()))];
+            students.extend(self.select_students(&wheres, &by_name())?);
+        }
+        // each chunk comes back in order, but not the chunks between them
+        students
+            .sort_by(|a, b| (&a.last_name, &a.first_names).cmp(&(&b.last_name, &b.first_names)));
+        Ok(students)
+    }
+
+    /// The id of the student called first last. It's looked up among every
+    /// student, so one the user can't see is PermissionDenied rather than
+    /// NotFound and nobody tries to add them again.
     pub fn get_id_for_name(&self, first: &str, last: &str) -> Result<String> {
         log::debug!("getting id for student with name {first} {last}");
         let wheres = vec![
             Where::new("first_names", Symbol::EQ, Value::from(first)),
             Where::new("last_name", Symbol::EQ, Value::from(last)),
         ];
-        let mut result = self
-            .dao
-            .select(&vec!["id".to_owned()], "student", &wheres)?;
+        let mut result = self.dao.select(
+            &vec!["id".to_owned()],
+            Student::TABLE,
+            &wheres,
+            &QueryOptions::new(),
+        )?;
         let id_hm = match result.len() {
             1 => result.remove(0),
-            _ => return Err(Error::NoStudent),
+            _ => return Err(Error::NotFound(format!("student {first} {last}"))),
         };
-        match id_hm.get("id") {
+        let id = match id_hm.get("id") {
             Some(val) => match val {
-                Value::Text(t) => Ok(t.clone()),
-                _ => Err(Error::ValueError(
-                    "something went very wrong here...".into(),
-                )),
+                Value::Text(t) => t.clone(),
+                _ => {
+                    return Err(Error::ValueError(
+                        "something went very wrong here...".into(),
+                    ))
+                }
             },
-            None => Err(Error::ValueError(
-                "something went very wrong here...".into(),
-            )),
-        }
+            None => {
+                return Err(Error::ValueError(
+                    "something went very wrong here...".into(),
+                ))
+            }
+        };
+        self.permissions.can_see(&id)?;
+        Ok(id)
     }
 
     pub fn add_student(&self, student: &Student) -> Result<usize> {
-        log::debug!("adding student {student:?}");
-        self.dao.insert(
-            &student_fields(),
-            "student",
-            vec![
-                student.id.clone().into(),
-                student.first_names.clone().into(),
-                student.last_name.clone().into(),
-                student.date_of_birth.clone().into(),
-            ],
-            false,
-        )
+        self.add_students(&vec![student.clone()])
     }
 
     pub fn add_students(&self, students: &Vec<Student>) -> Result<usize> {
         log::debug!("adding students {students:#?}");
-        let mut args: Vec<Value> = vec![];
-        for student in students {
-            args.push(student.id.to_owned().into());
-            args.push(student.first_names.clone().into());
-            args.push(student.last_name.clone().into());
-            args.push(student.date_of_birth.to_owned().into());
-        }
-        let added = self.dao.insert(&student_fields(), "student", args, false)?;
+        // teachers are given the students they add so they can see them
+        let teacher = self.permissions.can_add()?;
+        let entries = students
+            .iter()
+            .map(|student| audit_entry(None, Some(student)))
+            .collect::<Result<Vec<AuditEntry>>>()?;
+        let mut added = 0;
+        self.dao.transaction(&mut |_| {
+            added = self.students.insert(students)?;
+            if let Some(user_id) = &teacher {
+                let assignments: Vec<Assignment> = students
+                    .iter()
+                    .map(|student| Assignment {
+                        student_id: student.id.clone(),
+                        user_id: user_id.clone(),
+                    })
+                    .collect();
+                Repository::new(Arc::clone(&self.dao)).insert(&assignments)?;
+            }
+            self.audit.record(&entries)?;
+            Ok(())
+        })?;
         Ok(added)
     }
 
-    pub fn delete_student(&self, id: &str) -> Result<usize> {
-        log::debug!("deleting student with id {id}");
+    /// Hides the student from all() and list() without losing anything.
+    pub fn archive(&self, id: &str) -> Result<usize> {
+        log::debug!("archiving student with id {id}");
+        self.set_archived(id, Some(curr_datetime()))
+    }
+
+    pub fn unarchive(&self, id: &str) -> Result<usize> {
+        log::debug!("unarchiving student with id {id}");
+        self.set_archived(id, None)
+    }
+
+    fn set_archived(&self, id: &str, archived_at: Option<NaiveDateTime>) -> Result<usize> {
+        self.permissions.can_change(id)?;
+        let wheres = vec![
+            Where::new("id", Symbol::EQ, Value::from(id)),
+            match archived_at {
+                Some(_) => active(),
+                None => !active(),
+            },
+        ];
+        let mut updated = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.select_students(&wheres, &QueryOptions::new())?;
+            updated = self.dao.update(
+                &vec!["archived_at".into()],
+                Student::TABLE,
+                vec![archived_at.into()],
+                &wheres,
+            )?;
+            for student in &old {
+                let new = Student {
+                    archived_at,
+                    ..student.clone()
+                };
+                self.audit
+                    .record(&[audit_entry(Some(student), Some(&new))?])?;
+            }
+            Ok(())
+        })?;
+        Ok(updated)
+    }
+
+    /// Deletes an archived student for good. In sqlite their scores go with
+    /// them, callers delete the scores first with
+    /// SafmedScoreService::delete_scores only so that it's audited. MemoryDao
+    /// doesn't cascade, so there they'd be left behind otherwise.
+    pub fn purge_student(&self, id: &str) -> Result<usize> {
+        log::debug!("purging student with id {id}");
+        self.permissions.can_change(id)?;
         let w = vec![Where::new("id", Symbol::EQ, Value::from(id))];
-        let deleted = self.dao.delete("student", &w)?;
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.select_students(&w, &QueryOptions::new())?;
+            if old.iter().any(|s| s.archived_at.is_none()) {
+                return Err(Error::ValidationError(
+                    "students have to be archived before they can be purged".into(),
+                ));
+            }
+            deleted = self.students.delete(&w)?;
+            for student in &old {
+                self.audit.record(&[audit_entry(Some(student), None)?])?;
+            }
+            Ok(())
+        })?;
         Ok(deleted)
     }
 
     pub fn update_student(&self, update: &Student) -> Result<usize> {
         log::debug!("updating student with id {}", update.id);
+        self.permissions.can_change(&update.id)?;
         let wheres = vec![Where::new("id", Symbol::EQ, Value::from(update.id.clone()))];
-        let args = vec![
-            update.id.clone().into(),
-            update.first_names.clone().into(),
-            update.last_name.clone().into(),
-            date_to_str(update.date_of_birth).into(),
-        ];
-        let updated = self
-            .dao
-            .update(&student_fields(), "student", args, &wheres)?;
+        // archiving has its own methods so archived_at is left alone
+        let (fields, args): (Vec<String>, Vec<Value>) = Student::columns()
+            .into_iter()
+            .zip(update.to_values())
+            .filter(|(column, _)| column != "archived_at")
+            .unzip();
+        let mut updated = 0;
+        self.dao.transaction(&mut |_| {
+            let old = self.select_students(&wheres, &QueryOptions::new())?;
+            updated = self
+                .dao
+                .update(&fields, Student::TABLE, args.clone(), &wheres)?;
+            for student in &old {
+                let new = Student {
+                    archived_at: student.archived_at,
+                    ..update.clone()
+                };
+                self.audit
+                    .record(&[audit_entry(Some(student), Some(&new))?])?;
+            }
+            Ok(())
+        })?;
         Ok(updated)
     }
+
+    /// Every change made to the student and their scores, oldest first.
+    pub fn history(&self, id: &str) -> Result<Vec<AuditEntry>> {
+        log::debug!("getting history for student with id {id}");
+        self.permissions.can_see(id)?;
+        self.audit.history(id, None)
+    }
+}
+
+fn audit_entry(old: Option<&Student>, new: Option<&Student>) -> Result<AuditEntry> {
+    let id = match old.or(new) {
+        Some(student) => &student.id,
+        None => "",
+    };
+    AuditEntry::new(Student::TABLE, id, id, old, new)
 }
 
-fn student_fields() -> Vec<String> {
-    STUDENT_FIELDS
-        .to_vec()
-        .iter()
-        .map(|x| x.to_string())
-        .collect()
+fn active() -> Where {
+    Where::is_null("archived_at")
+}
+
+fn by_name() -> QueryOptions {
+    QueryOptions::new()
+        .order_by("last_name", Order::Asc)
+        .order_by("first_names", Order::Asc)
 }
 
 // #################
@@ -149,9 +304,12 @@ fn student_fields() -> Vec<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::{dao::MockDao, Record, Symbol, Value, Where};
-    use crate::models::Student;
+    use crate::database::{dao::MockDao, MemoryDao, Record, SqliteDao, Symbol, Value, Where};
+    use crate::errors::Source;
+    use crate::models::{Operation, Role, Student};
+    use crate::services::UserService;
     use crate::useful::*;
+    use matches::assert_matches;
 
     type ServiceTest = Vec<(Vec<Record>, Vec<Student>)>;
 
@@ -177,24 +335,25 @@ mod tests {
             vec![
                 Student {
                     id: "st1".into(),
-                    first_names: "Ben".into(),
-                    last_name: "Jones".into(),
-                    date_of_birth: date_from_str("1990-01-23").unwrap(),
+                    ..Student::new("Ben", "Jones", "1990-01-23").unwrap()
                 },
                 Student {
                     id: "st2".into(),
-                    first_names: "Gemma Victoria".into(),
-                    last_name: "Mercer-Forbes".into(),
-                    date_of_birth: date_from_str("1988-09-30").unwrap(),
+                    ..Student::new("Gemma Victoria", "Mercer-Forbes", "1988-09-30").unwrap()
                 },
             ],
         )];
         for (students, expected) in tests {
             let mut dao = MockDao::new();
             dao.expect_select()
-                .withf(|f, t, w| *f == student_fields() && t == "student" && *w == vec![])
+                .withf(|f, t, w, o| {
+                    *f == Student::columns()
+                        && t == "student"
+                        && *w == vec![active()]
+                        && *o == by_name()
+                })
                 .times(1)
-                .returning(move |_, _, _| Ok(students.clone()));
+                .returning(move |_, _, _, _| Ok(students.clone()));
             let dao: Arc<dyn Dao> = Arc::new(dao);
             let ss = StudentService::new(Arc::clone(&dao));
             let actual = ss.all().unwrap();
@@ -214,21 +373,19 @@ mod tests {
             .into()],
             vec![Student {
                 id: "st1".into(),
-                first_names: "Ben".into(),
-                last_name: "Jones".into(),
-                date_of_birth: date_from_str("1990-01-23").unwrap(),
+                ..Student::new("Ben", "Jones", "1990-01-23").unwrap()
             }],
         )];
         for (students, expected) in tests {
             let mut dao = MockDao::new();
             dao.expect_select()
-                .withf(|f, t, w| {
-                    *f == student_fields()
+                .withf(|f, t, w, _| {
+                    *f == Student::columns()
                         && t == "student"
                         && *w == vec![Where::new("id", Symbol::EQ, Value::from("st1"))]
                 })
                 .times(1)
-                .returning(move |_, _, _| Ok(students.clone()));
+                .returning(move |_, _, _, _| Ok(students.clone()));
             let ss = StudentService::new(Arc::new(dao));
             let actual = ss.get("st1").unwrap();
             assert_eq!(expected[0], actual);
@@ -248,7 +405,7 @@ mod tests {
         for (students, expected) in tests {
             let mut dao = MockDao::new();
             dao.expect_select()
-                .withf(|f, t, w| {
+                .withf(|f, t, w, _| {
                     *f == vec!["id".to_owned()]
                         && t == "student"
                         && *w
@@ -258,117 +415,343 @@ mod tests {
                             ]
                 })
                 .times(1)
-                .returning(move |_, _, _| Ok(students.clone()));
+                .returning(move |_, _, _, _| Ok(students.clone()));
             let ss = StudentService::new(Arc::new(dao));
             let actual = ss.get_id_for_name("Ben", "Jones").unwrap();
             assert_eq!(expected, actual);
         }
     }
 
+    fn memory_service() -> StudentService {
+        StudentService::new(Arc::new(MemoryDao::new()))
+    }
+
+    fn ben() -> Student {
+        Student {
+            id: "st1".into(),
+            ..Student::new("Ben", "Jones", "1990-01-23").unwrap()
+        }
+    }
+
     #[test]
     fn test_add_student() {
-        let mut dao = MockDao::new();
-        let student = Student {
-            id: "st1".into(),
-            first_names: "Ben".into(),
-            last_name: "Jones".into(),
-            date_of_birth: date_from_str("1990-01-23").unwrap(),
-        };
-        dao.expect_insert()
-            .withf(move |f, t, args, _| {
-                *f == student_fields()
-                    && t == "student"
-                    && *args
-                        == vec![
-                            "st1".into(),
-                            "Ben".into(),
-                            "Jones".into(),
-                            "1990-01-23".into(),
-                        ]
-            })
-            .times(1)
-            .returning(move |_, _, _, _| Ok(1));
-        let ss = StudentService::new(Arc::new(dao));
-        assert_eq!(Ok(1), ss.add_student(&student));
+        let ss = memory_service();
+        assert_eq!(Ok(1), ss.add_student(&ben()));
+        assert_eq!(Ok(ben()), ss.get("st1"));
+        assert!(ss.add_student(&ben()).is_err());
+        let history = ss.history("st1").unwrap();
+        assert_eq!(1, history.len());
+        assert_eq!(Operation::Insert, history[0].operation);
+        assert_eq!(
+            Some(serde_json::to_string(&ben()).unwrap()),
+            history[0].new_value
+        );
+        // nobody's logged in
+        assert_eq!(None, history[0].user_id);
     }
 
     #[test]
     fn test_add_students() {
-        let mut dao = MockDao::new();
-        let students = vec![
-            Student {
-                id: "st1".into(),
-                first_names: "Ben".into(),
-                last_name: "Jones".into(),
-                date_of_birth: date_from_str("1990-01-23").unwrap(),
-            },
-            Student {
+        let ss = memory_service();
+        let gemma = Student {
+            id: "st2".into(),
+            ..Student::new("Gemma", "Forbes", "1988-09-30").unwrap()
+        };
+        assert_eq!(Ok(2), ss.add_students(&vec![ben(), gemma.clone()]));
+        assert_eq!(Ok(vec![gemma, ben()]), ss.all());
+        assert_eq!(1, ss.history("st2").unwrap().len());
+    }
+
+    #[test]
+    fn test_add_duplicate_name() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ss = StudentService::new(dao);
+            ss.add_student(&ben()).unwrap();
+            let twin = Student {
                 id: "st2".into(),
-                first_names: "Gemma".into(),
-                last_name: "Forbes".into(),
-                date_of_birth: date_from_str("1988-09-30").unwrap(),
-            },
-        ];
-        dao.expect_insert()
-            .withf(move |f, t, args, _| {
-                *f == student_fields()
-                    && t == "student"
-                    && *args
-                        == vec![
-                            "st1".into(),
-                            "Ben".into(),
-                            "Jones".into(),
-                            "1990-01-23".into(),
-                            "st2".into(),
-                            "Gemma".into(),
-                            "Forbes".into(),
-                            "1988-09-30".into(),
-                        ]
-            })
-            .times(1)
-            .returning(move |_, _, _, _| Ok(2));
-        let ss = StudentService::new(Arc::new(dao));
-        assert_eq!(Ok(2), ss.add_students(&students));
+                ..ben()
+            };
+            assert_eq!(
+                Err(Error::Duplicate {
+                    table: "student".into(),
+                    columns: vec!["first_names".into(), "last_name".into()],
+                    source: Source::none(),
+                }),
+                ss.add_student(&twin)
+            );
+        }
     }
 
     #[test]
-    fn test_delete_student() {
-        let mut dao = MockDao::new();
-        dao.expect_delete()
-            .withf(move |table, wheres| {
-                table == "student" && *wheres == vec![Where::new("id", Symbol::EQ, "st1".into())]
-            })
-            .times(1)
-            .returning(move |_, _| Ok(1));
-        let ss = StudentService::new(Arc::new(dao));
-        assert_eq!(Ok(1), ss.delete_student("st1"));
+    fn test_archive() {
+        let ss = memory_service();
+        ss.add_student(&ben()).unwrap();
+        assert_eq!(Ok(1), ss.archive("st1"));
+        assert_eq!(Ok(vec![]), ss.all());
+        assert_eq!(Ok(vec![]), ss.list(&QueryOptions::new()));
+        let archived = ss.archived().unwrap();
+        assert_eq!(1, archived.len());
+        assert!(archived[0].archived_at.is_some());
+        // archiving twice doesn't move the date
+        assert_eq!(Ok(0), ss.archive("st1"));
+        // still there for anything that asks for them directly
+        assert_eq!(archived[0], ss.get("st1").unwrap());
+        assert_eq!(Ok(1), ss.unarchive("st1"));
+        assert_eq!(Ok(vec![ben()]), ss.all());
+        assert_eq!(Ok(vec![]), ss.archived());
+        let history = ss.history("st1").unwrap();
+        assert_eq!(3, history.len());
+        assert_eq!(Operation::Update, history[2].operation);
+    }
+
+    #[test]
+    fn test_purge_student() {
+        let ss = memory_service();
+        ss.add_student(&ben()).unwrap();
+        assert_matches!(ss.purge_student("st1"), Err(Error::ValidationError(_)));
+        assert_eq!(Ok(ben()), ss.get("st1"));
+        ss.archive("st1").unwrap();
+        assert_eq!(Ok(1), ss.purge_student("st1"));
+        assert_eq!(Err(Error::NotFound("student st1".into())), ss.get("st1"));
+        assert_eq!(Ok(0), ss.purge_student("st1"));
+        let history = ss.history("st1").unwrap();
+        assert_eq!(3, history.len());
+        assert_eq!(Operation::Delete, history[2].operation);
+        assert_eq!(history[1].new_value, history[2].old_value);
+        assert_eq!(None, history[2].new_value);
+    }
+
+    #[test]
+    fn test_update_leaves_archive_alone() {
+        let ss = memory_service();
+        ss.add_student(&ben()).unwrap();
+        ss.archive("st1").unwrap();
+        ss.update_student(&Student {
+            first_names: "Benjamin".into(),
+            ..ben()
+        })
+        .unwrap();
+        let student = ss.get("st1").unwrap();
+        assert_eq!("Benjamin", student.first_names);
+        assert!(student.archived_at.is_some());
     }
 
     #[test]
     fn test_update_student() {
+        let ss = memory_service();
+        ss.add_student(&ben()).unwrap();
         let update = Student {
-            id: "st1".into(),
-            first_names: "Ben".into(),
-            last_name: "Jones".into(),
-            date_of_birth: date_from_str("1990-01-23").unwrap(),
+            first_names: "Benjamin".into(),
+            ..ben()
         };
-        let mut dao = MockDao::new();
-        dao.expect_update()
-            .withf(move |fields, table, args, wheres| {
-                *fields == student_fields()
-                    && table == "student"
-                    && *args
-                        == vec![
-                            "st1".into(),
-                            "Ben".into(),
-                            "Jones".into(),
-                            date_to_str(update.date_of_birth).into(),
-                        ]
-                    && *wheres == vec![Where::new("id", Symbol::EQ, "st1".into())]
-            })
-            .times(1)
-            .returning(move |_, _, _, _| Ok(1));
-        let ss = StudentService::new(Arc::new(dao));
         assert_eq!(Ok(1), ss.update_student(&update));
+        assert_eq!(Ok(update.clone()), ss.get("st1"));
+        let history = ss.history("st1").unwrap();
+        assert_eq!(Operation::Update, history[1].operation);
+        assert_eq!(
+            Some(serde_json::to_string(&ben()).unwrap()),
+            history[1].old_value
+        );
+        // nothing to update so nothing to audit
+        let missing = Student {
+            id: "st9".into(),
+            ..ben()
+        };
+        assert_eq!(Ok(0), ss.update_student(&missing));
+        assert!(ss.history("st9").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_many() {
+        let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        dao.init().unwrap();
+        let ss = StudentService::new(Arc::clone(&dao));
+        let students = vec![
+            Student::new("Ben", "Jones", "1990-01-23").unwrap(),
+            Student::new("Gemma", "Forbes", "1988-09-30").unwrap(),
+            Student::new("Sam", "Smith", "2001-04-02").unwrap(),
+        ];
+        ss.add_students(&students).unwrap();
+        let ids = vec![
+            students[0].id.clone(),
+            students[2].id.clone(),
+            "nope".into(),
+        ];
+        let actual = ss.get_many(&ids).unwrap();
+        assert_eq!(vec![students[0].clone(), students[2].clone()], actual);
+        assert_eq!(Ok(vec![]), ss.get_many(&[]));
+        // more ids than sqlite takes parameters at once
+        let mut ids: Vec<String> = (0..2 * MAX_PARAMS).map(|i| format!("st{i}")).collect();
+        ids.push(students[1].id.clone());
+        ids.push(students[0].id.clone());
+        let actual = ss.get_many(&ids).unwrap();
+        assert_eq!(vec![students[1].clone(), students[0].clone()], actual);
+    }
+
+    #[test]
+    fn test_list() {
+        let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        dao.init().unwrap();
+        let ss = StudentService::new(Arc::clone(&dao));
+        let students = vec![
+            Student::new("Sam", "Smith", "2001-04-02").unwrap(),
+            Student::new("Ben", "Jones", "1990-01-23").unwrap(),
+            Student::new("Gemma", "Forbes", "1988-09-30").unwrap(),
+            Student::new("Alex", "Smith", "1995-07-12").unwrap(),
+        ];
+        ss.add_students(&students).unwrap();
+        let names = |students: Vec<Student>| -> Vec<String> {
+            students.into_iter().map(|s| s.first_names).collect()
+        };
+        assert_eq!(
+            vec!["Gemma", "Ben", "Alex", "Sam"],
+            names(ss.list(&QueryOptions::new()).unwrap())
+        );
+        assert_eq!(
+            vec!["Ben", "Alex"],
+            names(ss.list(&QueryOptions::new().limit(2).offset(1)).unwrap())
+        );
+        let by_dob = QueryOptions::new().order_by("date_of_birth", Order::Desc);
+        assert_eq!(
+            vec!["Sam", "Alex", "Ben", "Gemma"],
+            names(ss.list(&by_dob).unwrap())
+        );
+        let bad = QueryOptions::new().order_by("id; DROP TABLE student", Order::Asc);
+        assert_eq!(
+            Err(Error::ValidationError(
+                "can't sort students by id; DROP TABLE student".into()
+            )),
+            ss.list(&bad)
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ss = StudentService::new(Arc::clone(&dao));
+            let students = vec![
+                Student::new("Zoë", "Smith", "2001-04-02").unwrap(),
+                Student {
+                    notes: "extra time in exams".into(),
+                    ..Student::new("Ben", "Jones", "1990-01-23").unwrap()
+                },
+                Student::new("Alex", "Smyth", "1995-07-12").unwrap(),
+                Student::new("Samuel", "Smithson", "2003-11-30").unwrap(),
+            ];
+            ss.add_students(&students).unwrap();
+            ss.archive(&students[2].id).unwrap();
+            let names = |students: Vec<Student>| -> Vec<String> {
+                students.into_iter().map(|s| s.first_names).collect()
+            };
+            assert_eq!(vec!["Zoë"], names(ss.search("ZOE smiht").unwrap()));
+            // archived students aren't searched
+            assert_eq!(vec!["Zoë"], names(ss.search("smyth").unwrap()));
+            assert!(ss.search("gemma").unwrap().is_empty());
+            assert!(ss.search(" ").unwrap().is_empty());
+            // the closest match comes first
+            assert_eq!(vec!["Zoë", "Samuel"], names(ss.search("smith").unwrap()));
+            assert_eq!(vec!["Ben"], names(ss.search("exams").unwrap()));
+            assert_eq!(vec!["Samuel"], names(ss.search("2003-11-30").unwrap()));
+            // edits are searched as they are now
+            let ben = Student {
+                notes: String::new(),
+                ..students[1].clone()
+            };
+            ss.update_student(&ben).unwrap();
+            assert!(ss.search("exams").unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_student_details() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let ss = StudentService::new(dao);
+            let ben = Student {
+                upn: Some("A123456789012".into()),
+                class: Some("7B".into()),
+                notes: "extra time".into(),
+                start_date: Some(date_from_str("2021-09-01").unwrap()),
+                ..ben()
+            };
+            ss.add_student(&ben).unwrap();
+            assert_eq!(Ok(ben.clone()), ss.get("st1"));
+            let left = Student {
+                leave_date: Some(date_from_str("2022-07-20").unwrap()),
+                active: false,
+                ..ben.clone()
+            };
+            ss.update_student(&left).unwrap();
+            assert_eq!(Ok(left), ss.get("st1"));
+            // upns are unique but students don't need one
+            let sam = Student::new("Sam", "Smith", "2001-04-02").unwrap();
+            let gemma = Student::new("Gemma", "Forbes", "1988-09-30").unwrap();
+            assert_eq!(Ok(2), ss.add_students(&vec![sam.clone(), gemma]));
+            assert_eq!(
+                Err(Error::Duplicate {
+                    table: "student".into(),
+                    columns: vec!["upn".into()],
+                    source: Source::none(),
+                }),
+                ss.update_student(&Student {
+                    upn: ben.upn.clone(),
+                    ..sam
+                })
+            );
+        }
+    }
+
+    fn ids(students: Vec<Student>) -> Vec<String> {
+        students.into_iter().map(|s| s.id).collect()
+    }
+
+    #[test]
+    fn test_permissions() {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        for dao in [sqlite, Arc::new(MemoryDao::new()) as Arc<dyn Dao>] {
+            let session = Session::new();
+            let users = UserService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+            users.add_user("admin", "pw", Role::Admin).unwrap();
+            let ss = StudentService::new(Arc::clone(&dao)).with_session(Arc::clone(&session));
+            assert_eq!(
+                Err(Error::PermissionDenied("log in first".into())),
+                ss.all()
+            );
+            users.login("admin", "pw").unwrap();
+            let viewer = users.add_user("viewer", "pw", Role::Viewer).unwrap();
+            let teacher = users.add_user("teacher", "pw", Role::Teacher).unwrap();
+            ss.add_student(&ben()).unwrap();
+            users.assign("st1", &viewer.id).unwrap();
+            // teachers see the students they add but nobody else's
+            users.login("teacher", "pw").unwrap();
+            let sam = Student::new("Sam", "Smith", "2001-04-02").unwrap();
+            ss.add_student(&sam).unwrap();
+            assert_eq!(vec![sam.id.clone()], ids(ss.all().unwrap()));
+            assert_eq!(Some(teacher.id), ss.history(&sam.id).unwrap()[0].user_id);
+            assert_matches!(ss.get("st1"), Err(Error::PermissionDenied(_)));
+            assert_eq!(Ok(vec![]), ss.search("ben jones"));
+            assert_eq!(vec![sam.id.clone()], ids(ss.search("sam").unwrap()));
+            assert_matches!(ss.archive("st1"), Err(Error::PermissionDenied(_)));
+            assert_matches!(
+                ss.get_id_for_name("Ben", "Jones"),
+                Err(Error::PermissionDenied(_))
+            );
+            assert_eq!(Ok(1), ss.archive(&sam.id));
+            // viewers can look but not touch
+            users.login("viewer", "pw").unwrap();
+            assert_eq!(Ok(vec![ben()]), ss.all());
+            assert_matches!(ss.update_student(&ben()), Err(Error::PermissionDenied(_)));
+            assert_matches!(ss.add_student(&sam), Err(Error::PermissionDenied(_)));
+            // and admins see everyone
+            users.login("admin", "pw").unwrap();
+            assert_eq!(Ok(vec![ben()]), ss.all());
+            assert_eq!(vec![sam.id], ids(ss.archived().unwrap()));
+        }
     }
 }
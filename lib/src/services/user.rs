@@ -0,0 +1,296 @@
+use std::sync::Arc;
+
+use crate::database::{Dao, Entity, Order, QueryOptions, Repository, Symbol, Value, Where};
+use crate::errors::{Error, Result};
+use crate::models::{user::hash_password, Assignment, Role, User};
+use crate::services::permissions::{Permissions, Session};
+
+pub struct UserService {
+    dao: Arc<dyn Dao>,
+    users: Repository<User>,
+    assignments: Repository<Assignment>,
+    session: Option<Arc<Session>>,
+    permissions: Permissions,
+}
+
+impl UserService {
+    pub fn new(dao: Arc<dyn Dao>) -> Self {
+        log::debug!("created new UserService");
+        Self {
+            users: Repository::new(Arc::clone(&dao)),
+            assignments: Repository::new(Arc::clone(&dao)),
+            session: None,
+            permissions: Permissions::new(Arc::clone(&dao)),
+            dao,
+        }
+    }
+
+    /// Logs in to session and checks the logged in user is allowed to manage
+    /// users. Without one, as in the cli, anything goes.
+    pub fn with_session(mut self, session: Arc<Session>) -> Self {
+        self.permissions.set_session(Arc::clone(&session));
+        self.session = Some(session);
+        self
+    }
+
+    /// Checks the password, logging the user in to the session if it's right.
+    pub fn login(&self, username: &str, password: &str) -> Result<User> {
+        log::debug!("logging in {username}");
+        let user = match self.find(username)? {
+            Some(user) if user.check_password(password) => user,
+            _ => {
+                log::info!("failed login for {username}");
+                return Err(Error::LoginFailed);
+            }
+        };
+        if let Some(session) = &self.session {
+            session.set_user(Some(user.clone()));
+        }
+        Ok(user)
+    }
+
+    pub fn logout(&self) {
+        if let Some(session) = &self.session {
+            session.set_user(None);
+        }
+    }
+
+    /// Whether anyone can log in yet. Until they can the first user, who has
+    /// to be an admin, can be added without logging in.
+    pub fn has_users(&self) -> Result<bool> {
+        let options = QueryOptions::new().limit(1);
+        Ok(!self.users.list(&vec![], &options)?.is_empty())
+    }
+
+    pub fn all(&self) -> Result<Vec<User>> {
+        self.permissions.is_admin()?;
+        let by_name = QueryOptions::new().order_by("username", Order::Asc);
+        self.users.list(&vec![], &by_name)
+    }
+
+    pub fn get_by_name(&self, username: &str) -> Result<User> {
+        self.permissions.is_admin()?;
+        match self.find(username)? {
+            Some(user) => Ok(user),
+            None => Err(Error::NotFound(format!("user {username}"))),
+        }
+    }
+
+    pub fn add_user(&self, username: &str, password: &str, role: Role) -> Result<User> {
+        log::debug!("adding user {username} as {}", role.as_str());
+        if self.has_users()? {
+            self.permissions.is_admin()?;
+        } else if role != Role::Admin {
+            return Err(Error::ValidationError(
+                "the first user has to be an admin".into(),
+            ));
+        }
+        if username.trim().is_empty() || password.is_empty() {
+            return Err(Error::ValidationError(
+                "users need a username and password".into(),
+            ));
+        }
+        let user = User::new(username.trim(), password, role)?;
+        self.users.insert(std::slice::from_ref(&user))?;
+        Ok(user)
+    }
+
+    /// Admins can change anyone's password, everyone else only their own.
+    pub fn set_password(&self, id: &str, password: &str) -> Result<usize> {
+        match self.permissions.user()? {
+            Some(user) if user.id == id => (),
+            _ => self.permissions.is_admin()?,
+        }
+        if password.is_empty() {
+            return Err(Error::ValidationError("passwords can't be empty".into()));
+        }
+        self.dao.update(
+            &vec!["password_hash".into()],
+            User::TABLE,
+            vec![hash_password(password)?.into()],
+            &by_id(id),
+        )
+    }
+
+    pub fn set_role(&self, id: &str, role: Role) -> Result<usize> {
+        self.permissions.is_admin()?;
+        let mut updated = 0;
+        self.dao.transaction(&mut |_| {
+            updated = self.dao.update(
+                &vec!["role".into()],
+                User::TABLE,
+                vec![role.as_str().into()],
+                &by_id(id),
+            )?;
+            self.keep_an_admin()
+        })?;
+        Ok(updated)
+    }
+
+    pub fn delete_user(&self, id: &str) -> Result<usize> {
+        self.permissions.is_admin()?;
+        let mut deleted = 0;
+        self.dao.transaction(&mut |_| {
+            self.assignments
+                .delete(&vec![Where::new("user_id", Symbol::EQ, id.into())])?;
+            deleted = self.users.delete(&by_id(id))?;
+            self.keep_an_admin()
+        })?;
+        Ok(deleted)
+    }
+
+    /// Lets the user see, and unless they're a viewer change, the student.
+    pub fn assign(&self, student_id: &str, user_id: &str) -> Result<usize> {
+        self.permissions.is_admin()?;
+        let assignment = Assignment {
+            student_id: student_id.into(),
+            user_id: user_id.into(),
+        };
+        self.assignments.upsert(&[assignment])
+    }
+
+    pub fn unassign(&self, student_id: &str, user_id: &str) -> Result<usize> {
+        self.permissions.is_admin()?;
+        self.assignments.delete(&vec![
+            Where::new("student_id", Symbol::EQ, student_id.into()),
+            Where::new("user_id", Symbol::EQ, user_id.into()),
+        ])
+    }
+
+    /// The ids of the students assigned to the user.
+    pub fn assigned(&self, user_id: &str) -> Result<Vec<String>> {
+        match self.permissions.user()? {
+            Some(user) if user.id == user_id => (),
+            _ => self.permissions.is_admin()?,
+        }
+        let wheres = vec![Where::new("user_id", Symbol::EQ, user_id.into())];
+        let assigned = self.assignments.list(&wheres, &QueryOptions::new())?;
+        Ok(assigned.into_iter().map(|a| a.student_id).collect())
+    }
+
+    fn find(&self, username: &str) -> Result<Option<User>> {
+        let wheres = vec![Where::new("username", Symbol::EQ, username.into())];
+        Ok(self.users.list(&wheres, &QueryOptions::new())?.pop())
+    }
+
+    // nobody could manage users again without one
+    fn keep_an_admin(&self) -> Result<()> {
+        let wheres = vec![Where::new("role", Symbol::EQ, Role::Admin.as_str().into())];
+        match self
+            .users
+            .list(&wheres, &QueryOptions::new().limit(1))?
+            .is_empty()
+        {
+            true => Err(Error::ValidationError(
+                "there has to be at least one admin".into(),
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+fn by_id(id: &str) -> Vec<Where> {
+    vec![Where::new("id", Symbol::EQ, Value::from(id))]
+}
+
+// #################
+// ##### TESTS #####
+// #################
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{MemoryDao, SqliteDao};
+    use crate::models::Student;
+    use crate::services::StudentService;
+
+    fn daos() -> Vec<Arc<dyn Dao>> {
+        let sqlite: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").unwrap());
+        sqlite.init().unwrap();
+        vec![sqlite, Arc::new(MemoryDao::new())]
+    }
+
+    #[test]
+    fn test_first_user_is_an_admin() {
+        for dao in daos() {
+            let us = UserService::new(dao).with_session(Session::new());
+            assert_eq!(Ok(false), us.has_users());
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "the first user has to be an admin".into()
+                )),
+                us.add_user("mrs.jones", "pw", Role::Teacher)
+            );
+            us.add_user("admin", "pw", Role::Admin).unwrap();
+            // from now on only an admin can add users
+            assert!(matches!(
+                us.add_user("mrs.jones", "pw", Role::Teacher),
+                Err(Error::PermissionDenied(_))
+            ));
+            us.login("admin", "pw").unwrap();
+            us.add_user("mrs.jones", "pw", Role::Teacher).unwrap();
+            assert!(matches!(
+                us.add_user("mrs.jones", "pw", Role::Viewer),
+                Err(Error::Duplicate { .. })
+            ));
+            let names: Vec<String> = us.all().unwrap().into_iter().map(|u| u.username).collect();
+            assert_eq!(vec!["admin", "mrs.jones"], names);
+        }
+    }
+
+    #[test]
+    fn test_login() {
+        for dao in daos() {
+            let session = Session::new();
+            let us = UserService::new(dao).with_session(Arc::clone(&session));
+            let admin = us.add_user("admin", "secret", Role::Admin).unwrap();
+            assert_eq!(Err(Error::LoginFailed), us.login("admin", "wrong"));
+            assert_eq!(Err(Error::LoginFailed), us.login("nobody", "secret"));
+            assert!(!session.is_logged_in());
+            assert_eq!(Ok(admin.clone()), us.login("admin", "secret"));
+            assert_eq!(Some(admin.clone()), session.user());
+            us.set_password(&admin.id, "changed").unwrap();
+            us.logout();
+            assert!(!session.is_logged_in());
+            assert_eq!(Err(Error::LoginFailed), us.login("admin", "secret"));
+            assert!(us.login("admin", "changed").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_only_admins_manage_users() {
+        for dao in daos() {
+            let us = UserService::new(Arc::clone(&dao)).with_session(Session::new());
+            let admin = us.add_user("admin", "pw", Role::Admin).unwrap();
+            us.login("admin", "pw").unwrap();
+            let teacher = us.add_user("mrs.jones", "pw", Role::Teacher).unwrap();
+            let ben = Student::new("Ben", "Jones", "1990-01-23").unwrap();
+            StudentService::new(Arc::clone(&dao))
+                .add_student(&ben)
+                .unwrap();
+            assert_eq!(Ok(1), us.assign(&ben.id, &teacher.id));
+            // there's always an admin left
+            assert_eq!(
+                Err(Error::ValidationError(
+                    "there has to be at least one admin".into()
+                )),
+                us.set_role(&admin.id, Role::Viewer)
+            );
+            us.login("mrs.jones", "pw").unwrap();
+            assert_eq!(Ok(vec![ben.id.clone()]), us.assigned(&teacher.id));
+            assert!(matches!(us.all(), Err(Error::PermissionDenied(_))));
+            assert!(matches!(
+                us.assign(&ben.id, &teacher.id),
+                Err(Error::PermissionDenied(_))
+            ));
+            assert!(matches!(
+                us.set_password(&admin.id, "mine now"),
+                Err(Error::PermissionDenied(_))
+            ));
+            assert_eq!(Ok(1), us.set_password(&teacher.id, "new"));
+            us.login("admin", "pw").unwrap();
+            assert_eq!(Ok(1), us.delete_user(&teacher.id));
+            assert!(us.assigned(&teacher.id).unwrap().is_empty());
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use super::constant::*;
 use crate::database::Dao;
 use crate::errors::{Error, Result};
-use chrono::{Duration, Local, NaiveDate};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, Timelike};
 use regex::Regex;
 use std::fs::{create_dir, read_dir, write};
+use std::sync::OnceLock;
 use std::thread::sleep;
 
 pub fn date_to_str(d: NaiveDate) -> String {
@@ -14,6 +15,11 @@ pub fn curr_date() -> NaiveDate {
     Local::now().date_naive()
 }
 
+pub fn curr_datetime() -> NaiveDateTime {
+    // the database only keeps whole seconds
+    Local::now().naive_local().with_nanosecond(0).unwrap()
+}
+
 pub fn curr_date_str() -> String {
     date_to_str(curr_date())
 }
@@ -27,6 +33,19 @@ pub fn date_from_str(s: &str) -> Result<NaiveDate> {
     }
 }
 
+pub fn datetime_to_str(dt: NaiveDateTime) -> String {
+    dt.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+pub fn datetime_from_str(s: &str) -> Result<NaiveDateTime> {
+    match NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        Ok(dt) => Ok(dt),
+        Err(_) => Err(Error::BadDateConversion(format!(
+            "'{s}' is not a valid datetime-formatted string"
+        ))),
+    }
+}
+
 pub fn prev_date(days: i64) -> Option<NaiveDate> {
     curr_date().checked_sub_signed(Duration::days(days))
 }
@@ -39,8 +58,10 @@ pub fn prev_date_str(days: i64) -> String {
 }
 
 pub fn validate_date(date: &str) -> bool {
-    let regex = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
-    regex.is_match(date)
+    // compiled once, imports call this for every score
+    static DATE: OnceLock<Regex> = OnceLock::new();
+    DATE.get_or_init(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap())
+        .is_match(date)
 }
 
 pub fn execute_sqls(sqls: Vec<String>, dao: &dyn Dao) -> Result<()> {
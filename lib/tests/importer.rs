@@ -0,0 +1,75 @@
+use scorg_lib::{
+    database::{Dao, SqliteDao},
+    importer::Importer,
+    services::{SafmedScoreService, StudentService},
+};
+use std::sync::Arc;
+
+fn setup() -> (Importer, Arc<StudentService>, Arc<SafmedScoreService>) {
+    let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").expect("failed to open db"));
+    dao.init().expect("failed to set up db");
+    let students = Arc::new(StudentService::new(Arc::clone(&dao)));
+    let scores = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
+    let importer = Importer::new(dao, Arc::clone(&students), Arc::clone(&scores));
+    (importer, students, scores)
+}
+
+#[test]
+fn test_import() {
+    let csv_data = "\
+first_names,last_name,date_of_birth,2021-02-01,2021-02-02,2021-02-03
+Ben,Jones,1990-01-23,89/23,78/21,90/12
+Gemma Victoria,Mercer-Forbes,1988-08-30,98/12,78/12,89/3";
+    let (importer, students, scores) = setup();
+    let imported = importer.import(csv_data).expect("failed to import");
+    assert_eq!((2, 6), imported);
+    let all = students.all().expect("failed to get all students");
+    assert_eq!(2, all.len());
+    assert_eq!("Ben", all[0].first_names);
+    assert_eq!("Gemma Victoria", all[1].first_names);
+    let bens = scores
+        .get_safmed_scores(&all[0].id)
+        .expect("failed to get Ben's scores");
+    assert_eq!(
+        vec![(89, 23), (78, 21), (90, 12)],
+        bens.iter()
+            .map(|s| (s.correct, s.incorrect))
+            .collect::<Vec<(i32, i32)>>()
+    );
+}
+
+#[test]
+fn test_import_bad_csv() {
+    let bad_csvs = vec![
+        // missing incorrect score
+        "\
+first_names,last_name,date_of_birth,2021-02-01,2021-02-02,2021-02-03
+Ben,Jones,1990-01-23,89/23,78/21,90/12
+Gemma Victoria,Mercer-Forbes,1990-01-23,98/,78/12,89/3",
+        // missing date
+        "\
+first_names,last_name,date_of_birth,2021-02-01,2021-02-02,
+Ben,Jones,1990-01-23,89/23,78/21,90/12
+Gemma Victoria,Mercer-Forbes,1990-01-23,98/12,78/12,89/3",
+        // blank last name
+        "\
+first_names,last_name,date_of_birth,2021-02-01,2021-02-02,2021-02-03
+Ben,Jones,1990-01-23,89/23,78/21,90/12
+Gemma Victoria,,,98/12,78/12,89/3",
+        // missing a score
+        "\
+first_names,last_name,date_of_birth,2021-02-01,2021-02-02,2021-02-03
+Ben,Jones,1990-01-23,89/23,78/21
+Gemma Victoria,Mercer-Forbes,1990-01-23,98/12,78/12,89/3",
+        // had no headers
+        "\
+Ben,Jones,1990-01-23,89/23,78/21,78/21
+Gemma Victoria,Mercer-Forbes,1990-01-23,98/12,78/12,89/3",
+    ];
+    for csv_data in bad_csvs {
+        let (importer, students, _) = setup();
+        assert!(importer.import(csv_data).is_err(), "{}", csv_data);
+        // nothing from a bad file goes in
+        assert_eq!(Ok(vec![]), students.all(), "{}", csv_data);
+    }
+}
@@ -0,0 +1,103 @@
+use scorg_lib::{
+    database::{Dao, SqliteDao},
+    models::{SafmedScore, Student},
+    services::{SafmedScoreService, StudentService},
+    useful::*,
+};
+use std::sync::Arc;
+
+fn setup(students: &[Student]) -> (StudentService, SafmedScoreService) {
+    let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new(":memory:").expect("failed to open db"));
+    dao.init().expect("failed to set up db");
+    let service = StudentService::new(Arc::clone(&dao));
+    service
+        .add_students(&students.to_vec())
+        .expect("failed to add students");
+    (service, SafmedScoreService::new(dao))
+}
+
+fn ben() -> Student {
+    Student {
+        id: "st1".into(),
+        ..Student::new("Ben", "Jones", "1990-01-23").unwrap()
+    }
+}
+
+fn gemma() -> Student {
+    Student {
+        id: "st2".into(),
+        ..Student::new("Gemma", "Forbes", "1990-01-23").unwrap()
+    }
+}
+
+#[test]
+fn can_add_a_student() {
+    let (service, _) = setup(&[]);
+    service
+        .add_student(&Student::new("first", "last", "1970-01-01").unwrap())
+        .expect("failed to add student");
+    let all = service.all().expect("failed to get all students");
+    let s = all.first().expect("didn't find a student");
+    assert_eq!("first", s.first_names);
+    assert_eq!("last", s.last_name);
+    // the id is random, so just make sure there is one
+    assert!(!s.id.is_empty());
+}
+
+#[test]
+fn adding_a_score() {
+    let (_, scores) = setup(&[ben()]);
+    let score = SafmedScore::new("st1", 33, 11, "2022-01-01").unwrap();
+    scores.add_score(&score).expect("failed to add score");
+    let added = scores
+        .get_safmed_scores("st1")
+        .expect("failed to get scores");
+    assert_eq!(1, added.len());
+    assert_eq!((33, 11), (added[0].correct, added[0].incorrect));
+}
+
+#[test]
+fn adding_multiple_scores() {
+    let (_, scores) = setup(&[ben(), gemma()]);
+    scores
+        .add_scores(&[
+            SafmedScore::new("st1", 33, 11, "2022-01-01").unwrap(),
+            SafmedScore::new("st2", 40, 2, "2022-01-01").unwrap(),
+        ])
+        .expect("failed to add scores");
+    assert_eq!(1, scores.get_safmed_scores("st1").unwrap().len());
+    assert_eq!(40, scores.get_safmed_scores("st2").unwrap()[0].correct);
+}
+
+#[test]
+fn delete_a_student() {
+    let (service, _) = setup(&[ben(), gemma()]);
+    service
+        .archive("st2")
+        .expect("failed to archive student st2");
+    service
+        .purge_student("st2")
+        .expect("failed to delete student st2");
+    let students = service
+        .all()
+        .expect("failed to get all students after deleting");
+    assert_eq!(vec![ben()], students);
+}
+
+#[test]
+fn update_a_student() {
+    let (service, _) = setup(&[ben(), gemma()]);
+    let update = Student {
+        first_names: "NEWNAME".into(),
+        last_name: "NEWNAME".into(),
+        date_of_birth: date_from_str("1990-01-24").unwrap(),
+        ..ben()
+    };
+    service
+        .update_student(&update)
+        .expect("failed to update student st1");
+    let students = service
+        .all()
+        .expect("failed to get all students after updating");
+    assert_eq!(vec![gemma(), update], students);
+}
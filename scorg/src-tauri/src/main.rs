@@ -5,15 +5,21 @@
 
 use log::*;
 use scorg_lib::{
-    constant::DB_FILE,
+    config::{self, open_db},
     errors::Error,
-    database::{Dao, SqliteDao},
+    database::{Dao, MemoryDao, Order, QueryOptions, SqliteDao},
     importer::Importer,
-    models::{SafmedScore, Student},
+    models::{
+        Card, Deck, Group, MemberScore, Membership, MissedCard, Role, SafmedScore, Student, User,
+    },
     plotter::{Plotter, SafmedPlotter},
-    services::{SafmedScoreService, StudentService},
+    services::{
+        DeckService, GroupService, SafmedScoreService, Session, StudentService, UserService,
+    },
+    useful::{curr_date, date_from_str, prev_date},
 };
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tauri::State;
 
@@ -27,49 +33,262 @@ fn main() {
     .expect("failed to init logging");
     info!("setting up backend services...");
     debug!("creating and initialising DAO");
-    let dao: Arc<dyn Dao> = Arc::new(SqliteDao::new());
+    // --demo runs against an empty in-memory database that is never saved
+    let sqlite: Option<Arc<SqliteDao>> = match std::env::args().any(|arg| arg == "--demo") {
+        true => {
+            info!("running in demo mode, nothing will be saved");
+            None
+        }
+        false => match open_db(None) {
+            Ok(dao) => Some(Arc::new(dao)),
+            Err(e) => {
+                error!("failed to open database: {e}");
+                std::process::exit(1);
+            }
+        },
+    };
+    let dao: Arc<dyn Dao> = match &sqlite {
+        Some(sqlite) => Arc::clone(sqlite) as Arc<dyn Dao>,
+        None => Arc::new(MemoryDao::new()),
+    };
     dao.init().expect("failed to init sqlite database");
-    debug!("creating and initialising STUDENT SERVICE");
-    let students = Arc::new(StudentService::new(Arc::clone(&dao)));
-    students.init().expect("failed to init student service");
-    debug!("creating and initialising SCORE SERVICE");
-    let scores = Arc::new(SafmedScoreService::new(Arc::clone(&dao)));
-    scores.init().expect("failed to init score service");
+    let policy = match config::Config::load() {
+        Ok(config) => config.day_policy.unwrap_or_default(),
+        Err(e) => {
+            error!("failed to read config: {e}");
+            std::process::exit(1);
+        }
+    };
+    // shared by the services so they all see who is logged in
+    let session = Session::new();
+    debug!("creating USER SERVICE");
+    let users = Arc::new(UserService::new(Arc::clone(&dao)).with_session(Arc::clone(&session)));
+    debug!("creating STUDENT SERVICE");
+    let students =
+        Arc::new(StudentService::new(Arc::clone(&dao)).with_session(Arc::clone(&session)));
+    debug!("creating SCORE SERVICE");
+    let scores = Arc::new(
+        SafmedScoreService::new(Arc::clone(&dao))
+            .with_session(Arc::clone(&session))
+            .with_policy(policy),
+    );
+    debug!("creating DECK SERVICE");
+    let decks = Arc::new(DeckService::new(Arc::clone(&dao)).with_session(Arc::clone(&session)));
+    debug!("creating GROUP SERVICE");
+    let groups = Arc::new(
+        GroupService::new(Arc::clone(&dao))
+            .with_session(Arc::clone(&session))
+            .with_policy(policy),
+    );
     debug!("creating and initialising IMPORTER");
-    let importer = Importer::new(Arc::clone(&students), Arc::clone(&scores));
+    let importer = Importer::new(Arc::clone(&dao), Arc::clone(&students), Arc::clone(&scores));
+    let handler = tauri::generate_handler![
+        login,
+        logout,
+        current_user,
+        has_users,
+        add_user,
+        all_users,
+        delete_user,
+        set_password,
+        set_role,
+        assign_student,
+        unassign_student,
+        all_students,
+        search_students,
+        add_student,
+        delete_student,
+        unarchive_student,
+        archived_students,
+        purge_student,
+        edit_student,
+        add_safmeds_score,
+        get_safmeds_scores,
+        list_timings,
+        edit_timing,
+        delete_timing,
+        all_decks,
+        add_deck,
+        edit_deck,
+        delete_deck,
+        deck_cards,
+        add_card,
+        edit_card,
+        delete_card,
+        missed_cards,
+        import_csv,
+        preview_import,
+        backup_now,
+        list_backups,
+        restore_backup,
+        all_groups,
+        add_group,
+        edit_group,
+        delete_group,
+        add_group_member,
+        remove_group_member,
+        group_members,
+        group_history,
+        group_latest_scores
+    ];
+    let gate = Arc::clone(&session);
     info!("Starting...");
     tauri::Builder::default()
+        .manage(dao)
+        .manage(sqlite)
+        .manage(session)
+        .manage(users)
         .manage(students)
         .manage(scores)
+        .manage(decks)
+        .manage(groups)
         .manage(importer)
-        .invoke_handler(tauri::generate_handler![
-            all_students,
-            add_student,
-            delete_student,
-            edit_student,
-            add_safmeds_score,
-            get_safmeds_scores,
-            import_csv
-        ])
+        // nothing is served until someone logs in
+        .invoke_handler(move |invoke| {
+            let command = invoke.message.command();
+            if !gate.is_logged_in() && !OPEN_COMMANDS.contains(&command) {
+                warn!("refused {command} before login");
+                invoke
+                    .resolver
+                    .reject(Error::PermissionDenied("log in first".into()));
+                return;
+            }
+            handler(invoke)
+        })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
+// the commands needed to log in, or to add the first user when there's nobody
+// to log in as
+const OPEN_COMMANDS: &[&str] = &["login", "has_users", "current_user", "add_user"];
+
+// USER COMMANDS
+#[tauri::command]
+fn login(
+    username: String,
+    password: String,
+    service: State<Arc<UserService>>,
+) -> Result<User, Error> {
+    debug!("logging in {username}");
+    service.login(&username, &password)
+}
+
+#[tauri::command]
+fn logout(service: State<Arc<UserService>>) {
+    service.logout()
+}
+
+#[tauri::command]
+fn current_user(session: State<Arc<Session>>) -> Option<User> {
+    session.user()
+}
+
+#[tauri::command]
+fn has_users(service: State<Arc<UserService>>) -> Result<bool, Error> {
+    service.has_users()
+}
+
+#[tauri::command]
+fn add_user(
+    username: String,
+    password: String,
+    role: Role,
+    service: State<Arc<UserService>>,
+) -> Result<User, Error> {
+    debug!("received user to add: {username}");
+    service.add_user(&username, &password, role)
+}
+
+#[tauri::command]
+fn all_users(service: State<Arc<UserService>>) -> Result<Vec<User>, Error> {
+    service.all()
+}
+
+#[tauri::command]
+fn delete_user(id: String, service: State<Arc<UserService>>) -> Result<usize, Error> {
+    debug!("deleting user {id}");
+    service.delete_user(&id)
+}
+
+#[tauri::command]
+fn set_password(
+    id: String,
+    password: String,
+    service: State<Arc<UserService>>,
+) -> Result<usize, Error> {
+    service.set_password(&id, &password)
+}
+
+#[tauri::command]
+fn set_role(id: String, role: Role, service: State<Arc<UserService>>) -> Result<usize, Error> {
+    service.set_role(&id, role)
+}
+
+#[tauri::command]
+fn assign_student(
+    student_id: String,
+    user_id: String,
+    service: State<Arc<UserService>>,
+) -> Result<usize, Error> {
+    service.assign(&student_id, &user_id)
+}
+
+#[tauri::command]
+fn unassign_student(
+    student_id: String,
+    user_id: String,
+    service: State<Arc<UserService>>,
+) -> Result<usize, Error> {
+    service.unassign(&student_id, &user_id)
+}
+
 // STUDENT COMMANDS
 #[tauri::command]
-fn all_students(service: State<Arc<StudentService>>) -> Result<Vec<Student>, Error> {
+fn all_students(
+    sort: Option<String>,
+    descending: Option<bool>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    service: State<Arc<StudentService>>,
+) -> Result<Vec<Student>, Error> {
     debug!("received request for all students");
-    match service.all() {
+    let order = match descending {
+        Some(true) => Order::Desc,
+        _ => Order::Asc,
+    };
+    let mut options = QueryOptions::new();
+    if let Some(field) = sort {
+        options = options.order_by(&field, order);
+    }
+    options.limit = limit;
+    options.offset = offset;
+    match service.list(&options) {
         Ok(students) => Ok(students),
         Err(error) => Err(error),
     }
 }
 
+#[tauri::command]
+fn search_students(
+    text: String,
+    service: State<Arc<StudentService>>,
+) -> Result<Vec<Student>, Error> {
+    debug!("searching students for {text:?}");
+    service.search(&text)
+}
+
 #[tauri::command]
 fn add_student(
     first_names: String,
     last_name: String,
     date_of_birth: String,
+    upn: Option<String>,
+    class: Option<String>,
+    notes: Option<String>,
+    start_date: Option<String>,
+    leave_date: Option<String>,
+    active: Option<bool>,
     service: State<Arc<StudentService>>,
 ) -> Result<String, Error> {
     debug!(
@@ -80,29 +299,62 @@ fn add_student(
         Ok(student) => student,
         Err(error) => return Err(error),
     };
+    // the app sends empty strings for details that weren't filled in
+    let given = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+    let new_student = Student {
+        upn: given(upn),
+        class: given(class),
+        notes: notes.unwrap_or_default(),
+        start_date: given(start_date)
+            .as_deref()
+            .map(date_from_str)
+            .transpose()?,
+        leave_date: given(leave_date)
+            .as_deref()
+            .map(date_from_str)
+            .transpose()?,
+        active: active.unwrap_or(true),
+        ..new_student
+    };
     service.add_student(&new_student).and_then(|_| Ok(new_student.id.to_owned()))
 }
 
+// deleting from the student list only archives, so a mis-click can be undone
+#[tauri::command]
+fn delete_student(id: String, service: State<Arc<StudentService>>) -> Result<usize, Error> {
+    debug!("archiving student {id}");
+    service.archive(&id)
+}
+
+#[tauri::command]
+fn unarchive_student(id: String, service: State<Arc<StudentService>>) -> Result<usize, Error> {
+    debug!("unarchiving student {id}");
+    service.unarchive(&id)
+}
+
 #[tauri::command]
-fn delete_student(
+fn archived_students(service: State<Arc<StudentService>>) -> Result<Vec<Student>, Error> {
+    debug!("received request for archived students");
+    service.archived()
+}
+
+#[tauri::command]
+fn purge_student(
     id: String,
+    dao: State<Arc<dyn Dao>>,
     students: State<Arc<StudentService>>,
     scores: State<Arc<SafmedScoreService>>,
 ) -> Result<(), Error> {
-    match scores.delete_scores(&id) {
-        Ok(num) => debug!("deleted {num} scores"),
-        Err(error) => {
-            error!("{}", error);
-            return Err(error);
-        }
-    };
-    match students.delete_student(&id) {
-        Ok(_) => Ok(()),
-        Err(error) => {
-            error!("{}", error);
-            Err(error)
-        }
+    let result = dao.transaction(&mut |_| {
+        let num = scores.delete_scores(&id)?;
+        debug!("deleted {num} scores");
+        students.purge_student(&id)?;
+        Ok(())
+    });
+    if let Err(error) = &result {
+        error!("{}", error);
     }
+    result
 }
 
 #[tauri::command]
@@ -110,20 +362,186 @@ fn edit_student(update: Student, service: State<Arc<StudentService>>) -> Result<
     service.update_student(&update)
 }
 
+// GROUP COMMANDS
+#[tauri::command]
+fn all_groups(service: State<Arc<GroupService>>) -> Result<Vec<Group>, Error> {
+    debug!("received request for all groups");
+    service.all()
+}
+
+#[tauri::command]
+fn add_group(
+    name: String,
+    description: Option<String>,
+    service: State<Arc<GroupService>>,
+) -> Result<String, Error> {
+    debug!("received group to add: {name}");
+    let group = Group::new(&name, &description.unwrap_or_default());
+    service.add_group(&group)?;
+    Ok(group.id)
+}
+
+#[tauri::command]
+fn edit_group(update: Group, service: State<Arc<GroupService>>) -> Result<usize, Error> {
+    service.update_group(&update)
+}
+
+#[tauri::command]
+fn delete_group(id: String, service: State<Arc<GroupService>>) -> Result<usize, Error> {
+    debug!("deleting group {id}");
+    service.delete_group(&id)
+}
+
+// dates default to today, as that's usually when the change is made
+#[tauri::command]
+fn add_group_member(
+    group_id: String,
+    student_id: String,
+    date: Option<String>,
+    service: State<Arc<GroupService>>,
+) -> Result<usize, Error> {
+    let joined_on = match date {
+        Some(date) => date_from_str(&date)?,
+        None => curr_date(),
+    };
+    service.add_member(&group_id, &student_id, joined_on)
+}
+
+#[tauri::command]
+fn remove_group_member(
+    group_id: String,
+    student_id: String,
+    date: Option<String>,
+    service: State<Arc<GroupService>>,
+) -> Result<usize, Error> {
+    let left_on = match date {
+        Some(date) => date_from_str(&date)?,
+        None => curr_date(),
+    };
+    service.remove_member(&group_id, &student_id, left_on)
+}
+
+#[tauri::command]
+fn group_members(id: String, service: State<Arc<GroupService>>) -> Result<Vec<Student>, Error> {
+    service.members(&id)
+}
+
+#[tauri::command]
+fn group_history(
+    id: String,
+    service: State<Arc<GroupService>>,
+) -> Result<Vec<Membership>, Error> {
+    service.history(&id)
+}
+
+#[tauri::command]
+fn group_latest_scores(
+    id: String,
+    service: State<Arc<GroupService>>,
+) -> Result<Vec<MemberScore>, Error> {
+    service.latest_scores(&id)
+}
+
+// DECK COMMANDS
+#[tauri::command]
+fn all_decks(service: State<Arc<DeckService>>) -> Result<Vec<Deck>, Error> {
+    debug!("received request for all decks");
+    service.all()
+}
+
+#[tauri::command]
+fn add_deck(
+    name: String,
+    subject: Option<String>,
+    card_count: Option<i32>,
+    description: Option<String>,
+    service: State<Arc<DeckService>>,
+) -> Result<String, Error> {
+    debug!("received deck to add: {name}");
+    let deck = Deck::new(
+        &name,
+        &subject.unwrap_or_default(),
+        card_count.unwrap_or_default(),
+        &description.unwrap_or_default(),
+    )?;
+    service.add_deck(&deck)?;
+    Ok(deck.id)
+}
+
+#[tauri::command]
+fn edit_deck(update: Deck, service: State<Arc<DeckService>>) -> Result<usize, Error> {
+    service.update_deck(&update)
+}
+
+#[tauri::command]
+fn delete_deck(id: String, service: State<Arc<DeckService>>) -> Result<usize, Error> {
+    debug!("deleting deck {id}");
+    service.delete_deck(&id)
+}
+
+#[tauri::command]
+fn deck_cards(deck_id: String, service: State<Arc<DeckService>>) -> Result<Vec<Card>, Error> {
+    service.cards(&deck_id)
+}
+
+#[tauri::command]
+fn add_card(
+    deck_id: String,
+    front: String,
+    back: Option<String>,
+    tags: Option<Vec<String>>,
+    service: State<Arc<DeckService>>,
+) -> Result<String, Error> {
+    debug!("received card to add to {deck_id}: {front}");
+    let tags = tags.unwrap_or_default();
+    let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+    let card = Card::new(&deck_id, &front, &back.unwrap_or_default(), &tags)?;
+    service.add_cards(std::slice::from_ref(&card))?;
+    Ok(card.id)
+}
+
+#[tauri::command]
+fn edit_card(update: Card, service: State<Arc<DeckService>>) -> Result<usize, Error> {
+    service.update_card(&update)
+}
+
+#[tauri::command]
+fn delete_card(id: String, service: State<Arc<DeckService>>) -> Result<usize, Error> {
+    debug!("deleting card {id}");
+    service.delete_card(&id)
+}
+
 // SCORE COMMANDS
+// scores are one minute timings on the general deck unless the app says
+// otherwise
 #[tauri::command]
 fn add_safmeds_score(
     id: String,
     date: String,
     correct: i32,
     incorrect: i32,
+    deck_id: Option<String>,
+    duration: Option<i32>,
+    cards: Option<HashMap<String, bool>>,
     service: State<Arc<SafmedScoreService>>,
 ) -> Result<(), String> {
     let new_score = match SafmedScore::new(&id, correct, incorrect, &date) {
-        Ok(score) => score,
+        Ok(score) => SafmedScore {
+            deck_id: deck_id.unwrap_or(score.deck_id.clone()),
+            duration: duration.unwrap_or(score.duration),
+            ..score
+        },
         Err(error) => return Err(error.to_string()),
     };
-    match service.add_score(&new_score) {
+    // cards maps the id of each card asked to whether the student got it
+    let added = match cards {
+        Some(cards) => {
+            let cards: Vec<(String, bool)> = cards.into_iter().collect();
+            service.add_score_with_cards(&new_score, &cards)
+        }
+        None => service.add_score(&new_score),
+    };
+    match added {
         Ok(_) => Ok(()),
         Err(error) => Err(error.to_string())
     }
@@ -132,9 +550,65 @@ fn add_safmeds_score(
 #[tauri::command]
 fn get_safmeds_scores(
     student_id: &str,
+    deck_id: Option<String>,
     service: State<Arc<SafmedScoreService>>,
 ) -> Result<Vec<SafmedScore>, String> {
-    service.get_safmed_scores(&student_id).or_else(|e| Err(e.to_string()))
+    let scores = match deck_id {
+        Some(deck_id) => service.get_deck_scores(student_id, &deck_id),
+        None => service.get_safmed_scores(student_id),
+    };
+    scores.or_else(|e| Err(e.to_string()))
+}
+
+// every timing, where get_safmeds_scores gives one a day
+#[tauri::command]
+fn list_timings(
+    student_id: &str,
+    service: State<Arc<SafmedScoreService>>,
+) -> Result<Vec<SafmedScore>, Error> {
+    service.timings(student_id)
+}
+
+// update is one of the timings from list_timings, changed
+#[tauri::command]
+fn edit_timing(
+    update: SafmedScore,
+    service: State<Arc<SafmedScoreService>>,
+) -> Result<usize, Error> {
+    service.update_score(&update)
+}
+
+#[tauri::command]
+fn delete_timing(
+    timing: SafmedScore,
+    service: State<Arc<SafmedScoreService>>,
+) -> Result<usize, Error> {
+    debug!(
+        "deleting timing {} on {} of {}",
+        timing.seq, timing.date, timing.id
+    );
+    service.delete_timing(&timing)
+}
+
+// the window runs from 30 days ago up to today unless the app says otherwise
+#[tauri::command]
+fn missed_cards(
+    student_id: String,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<usize>,
+    service: State<Arc<SafmedScoreService>>,
+) -> Result<Vec<MissedCard>, Error> {
+    debug!("received request for cards missed by {student_id}");
+    let from = match from {
+        Some(from) => date_from_str(&from)?,
+        None => prev_date(30).ok_or(Error::ValueError("date out of range".into()))?,
+    };
+    let to = match to {
+        Some(to) => date_from_str(&to)?,
+        None => curr_date(),
+    };
+    service.missed_cards(&student_id, from, to, limit.unwrap_or(10))
 }
 
 #[tauri::command]
@@ -148,3 +622,66 @@ fn import_csv(file: &str, importer: State<Importer>) -> Result<(), String> {
         }
     }
 }
+
+#[tauri::command]
+fn preview_import(file: &str, importer: State<Importer>) -> Result<(usize, usize), String> {
+    debug!("previewing import of {file}");
+    match importer.preview(file) {
+        Ok(counts) => Ok(counts),
+        Err(error) => {
+            error!("{error}");
+            Err(error.to_string())
+        }
+    }
+}
+
+// BACKUP COMMANDS
+#[tauri::command]
+fn backup_now(dao: State<Arc<dyn Dao>>) -> Result<String, String> {
+    debug!("received request for a snapshot");
+    match dao.backup("manual") {
+        Ok(Some(path)) => Ok(path.display().to_string()),
+        Ok(None) => Err("snapshots aren't kept in demo mode".into()),
+        Err(error) => {
+            error!("{error}");
+            Err(error.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+fn list_backups(sqlite: State<Option<Arc<SqliteDao>>>) -> Result<Vec<String>, String> {
+    let sqlite = sqlite.inner().as_ref().ok_or("snapshots aren't kept in demo mode")?;
+    let snapshots = sqlite.snapshots().or_else(|e| Err(e.to_string()))?;
+    Ok(snapshots
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .collect())
+}
+
+#[tauri::command]
+fn restore_backup(
+    name: &str,
+    sqlite: State<Option<Arc<SqliteDao>>>,
+    session: State<Arc<Session>>,
+    users: State<Arc<UserService>>,
+) -> Result<(), String> {
+    debug!("restoring snapshot {name}");
+    // it replaces everyone's students, not just the user's
+    if session.user().map(|user| user.role) != Some(Role::Admin) {
+        return Err(Error::PermissionDenied("only admins can do that".into()).to_string());
+    }
+    let sqlite = sqlite.inner().as_ref().ok_or("snapshots aren't kept in demo mode")?;
+    match sqlite.restore(name) {
+        // the user may not be in the restored database, or have another role
+        Ok(_) => {
+            users.logout();
+            Ok(())
+        }
+        Err(error) => {
+            error!("{error}");
+            Err(error.to_string())
+        }
+    }
+}
@@ -2,12 +2,15 @@ import { useContext, useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
 import "./App.css";
 import StudentList from "./StudentList/StudentList";
-import { Student } from "./StudentList/Student";
+import { Student, StudentDetails } from "./StudentList/Student";
 import SnackbarContext from "./snackbar-context";
 import SnackBar from "./Snackbar";
 import ScoreTabs from "./ScoreTabs/ScoreTabs";
+import { describeError } from "./errors";
+import Login, { User } from "./Login";
 
 function App() {
+  const [user, setUser] = useState(null as User | null)
   const [selected, setSelected] = useState("")
   const [students, setStudents] = useState([] as Student[])
   const [studentCache, setStudentCache] = useState([] as Student[])
@@ -19,17 +22,17 @@ function App() {
       let students: Student[] = await invoke("all_students")
       setStudentCache(students)
     } catch (error) {
-      snackbarCtx.error(`failed to get students: ${error!.toString()}`)
+      snackbarCtx.error(`failed to get students: ${describeError(error)}`)
     }
   }
 
-  const addStudentToTauri = async (firstNames: string, lastName: string, dateOfBirth: string) => {
+  const addStudentToTauri = async (firstNames: string, lastName: string, dateOfBirth: string, details: Partial<StudentDetails> = {}) => {
     try {
-      await invoke("add_student", { firstNames, lastName, dateOfBirth })
+      await invoke("add_student", { firstNames, lastName, dateOfBirth, ...details })
       snackbarCtx.success(`added ${firstNames} ${lastName}`)
       getStudentsFromTauri()
     } catch (error) {
-      snackbarCtx.error(`failed to add student: ${error!.toString()}`)
+      snackbarCtx.error(`failed to add student: ${describeError(error)}`)
     }
   }
 
@@ -37,36 +40,69 @@ function App() {
     try {
       await invoke("delete_student", { id })
       let deleted = students.find(st => st.id === id)!
-      snackbarCtx.success(`deleted ${deleted.first_names} ${deleted.last_name}`)
+      snackbarCtx.success(`archived ${deleted.first_names} ${deleted.last_name}`)
       getStudentsFromTauri()
     } catch (error) {
-      snackbarCtx.error(`failed to delete student: ${error!.toString()}`)
+      snackbarCtx.error(`failed to delete student: ${describeError(error)}`)
     }
   }
 
-  const editStudentInTauri = async (id: string, editName: string, editDob: string) => {
+  const editStudentInTauri = async (id: string, editName: string, editDob: string, details: StudentDetails) => {
     let splitName = editName.split(" ")
     let last_name = splitName.pop()
     let first_names = splitName.join(" ")
+    let student = studentCache.find(st => st.id === id)!
     try {
-      await invoke("edit_student", { update: { id: id, first_names: first_names, last_name: last_name, date_of_birth: editDob } })
+      await invoke("edit_student", { update: { ...student, ...details, first_names: first_names, last_name: last_name, date_of_birth: editDob } })
       snackbarCtx.success("edited student")
       getStudentsFromTauri()
     } catch (error) {
-      snackbarCtx.error(`failed to edit student: ${error!.toString()}`)
+      snackbarCtx.error(`failed to edit student: ${describeError(error)}`)
     }
   }
 
-  const applyFilter = (filter: string) => {
+  const applyFilter = async (filter: string) => {
     console.log(`filtering on ${filter}`)
-    setStudents(studentCache.filter(st => `${st.first_names} ${st.last_name}`.toLowerCase().includes(filter.toLowerCase())))
     setCurrFilter(filter)
+    if (filter.trim() === "") {
+      setStudents(studentCache)
+      return
+    }
+    try {
+      let found: Student[] = await invoke("search_students", { text: filter })
+      setStudents(found)
+    } catch (error) {
+      snackbarCtx.error(`failed to search students: ${describeError(error)}`)
+    }
+  }
+
+  const logout = async () => {
+    await invoke("logout")
+    setUser(null)
+    setSelected("")
+    setStudentCache([])
   }
 
-  useEffect(() => applyFilter(currFilter), [studentCache])
+  useEffect(() => { applyFilter(currFilter) }, [studentCache])
+
+  // the app may have been reloaded while someone was logged in
+  useEffect(() => { invoke("current_user").then(current => setUser(current as User | null)) }, [])
+
+  if (user === null) {
+    return (
+      <div className="container">
+        <Login loggedIn={setUser} />
+        {snackbarCtx.isDisplayed && <SnackBar />}
+      </div>
+    )
+  }
 
   return (
     <div className="container">
+      <div id="user-bar">
+        <span>{user.username} ({user.role})</span>
+        <button className="button" onClick={logout}>Log out</button>
+      </div>
       <div id="student-list-area">
         <StudentList students={students} selected={selected} select={setSelected} getStudents={getStudentsFromTauri} addStudent={addStudentToTauri} deleteStudent={deleteStudentFromTauri} editStudent={editStudentInTauri} applyFilter={applyFilter} />
       </div>
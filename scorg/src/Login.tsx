@@ -0,0 +1,64 @@
+import { useRef, useContext, useEffect, useState } from "react"
+import { invoke } from "@tauri-apps/api/tauri"
+import SnackbarContext from "./snackbar-context"
+import { describeError } from "./errors"
+
+export interface User {
+    id: string,
+    username: string,
+    role: "admin" | "teacher" | "viewer",
+}
+
+// Shown until someone logs in. When nobody can log in yet it sets up the
+// first admin instead.
+export default function Login({ loggedIn }: Props) {
+    const [hasUsers, setHasUsers] = useState(true)
+    const usernameInput = useRef<HTMLInputElement>(null)
+    const passwordInput = useRef<HTMLInputElement>(null)
+    const snackCtx = useContext(SnackbarContext)
+
+    useEffect(() => {
+        invoke("has_users")
+            .then(has => setHasUsers(has as boolean))
+            .catch(error => snackCtx.error(`failed to check for users: ${describeError(error)}`))
+        document.getElementById("username-input")?.focus()
+    }, [])
+
+    async function handleLogin() {
+        let username = usernameInput.current!.value.trim()
+        let password = passwordInput.current!.value
+        try {
+            if (!hasUsers) {
+                await invoke("add_user", { username, password, role: "admin" })
+            }
+            let user: User = await invoke("login", { username, password })
+            passwordInput.current!.value = ""
+            loggedIn(user)
+        } catch (error) {
+            snackCtx.error(`failed to log in: ${describeError(error)}`)
+        }
+    }
+
+    return (
+        <div id="login-dialog" className="dialog" style={{ display: "flex" }}>
+            <div className="topbar">
+                <span><em>{hasUsers ? "Log in" : "Create the first admin"}</em></span>
+            </div>
+            <div className="row">
+                <label>Username</label>
+                <input id="username-input" type="text" ref={usernameInput} />
+            </div>
+            <div className="row">
+                <label>Password</label>
+                <input type="password" ref={passwordInput} onKeyDown={e => e.key === "Enter" && handleLogin()} />
+            </div>
+            <div className="row">
+                <button className="button wide" onClick={handleLogin}>{hasUsers ? "Log in" : "Create and log in"}</button>
+            </div>
+        </div>
+    )
+}
+
+interface Props {
+    loggedIn: (user: User) => void,
+}
@@ -65,16 +65,21 @@ interface Props {
 }
 
 
+// plotted per minute, so timings of different lengths line up
+function perMinute(count: number, score: Score) {
+  return count * 60 / score.duration
+}
+
 function scoreToDataPoints(score: Score) {
   const date = getDate(score.date)
   return {
     correct: {
       x: date,
-      y: score.correct
+      y: perMinute(score.correct, score)
     },
     incorrect: {
       x: date,
-      y: score.incorrect
+      y: perMinute(score.incorrect, score)
     }
   }
 }
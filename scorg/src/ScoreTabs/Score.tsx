@@ -2,5 +2,17 @@ export type Score = {
     id: string,
     correct: number,
     incorrect: number,
-    date: Date
+    date: Date,
+    deck_id: string,
+    duration: number,
+    seq: number,
+    timed_at: Date
+  }
+
+export type Deck = {
+    id: string,
+    name: string,
+    subject: string,
+    card_count: number,
+    description: string
   }
\ No newline at end of file
@@ -3,11 +3,14 @@ import { useContext, useEffect, useRef, useState } from "react"
 import SnackbarContext from "../snackbar-context"
 import SafmedContent from "./SafmedContent"
 import moment from "moment"
-import { Score } from "./Score"
+import { Deck, Score } from "./Score"
 
 export default function ScoreTabs({ selected }: Props) {
   const [currentTab, setCurrentTab] = useState("safmeds")
   const [scores, setScores] = useState([] as Score[])
+  const [decks, setDecks] = useState([] as Deck[])
+  const [deckId, setDeckId] = useState("general")
+  const [duration, setDuration] = useState(60)
   const correctInput = useRef<HTMLInputElement>(null)
   const incorrectInput = useRef<HTMLInputElement>(null)
   const [currentDate, setCurrentDate] = useState(moment().format("YYYY-MM-DD"))
@@ -18,7 +21,7 @@ export default function ScoreTabs({ selected }: Props) {
   }
 
   function getScores() {
-    invoke(`get_${currentTab}_scores`, { studentId: selected }).then((sc) => {
+    invoke(`get_${currentTab}_scores`, { studentId: selected, deckId }).then((sc) => {
       // snack.success(`got plot`)
       setScores(sc as Score[])
     }).catch(e => snack.error(e.toString()))
@@ -27,7 +30,7 @@ export default function ScoreTabs({ selected }: Props) {
   function addSafmedsScore() {
     let correct = Number.parseInt(correctInput.current!.value)
     let incorrect = Number.parseInt(incorrectInput.current!.value)
-    invoke("add_safmeds_score", {id: selected, date: currentDate, correct, incorrect}).then(() => {
+    invoke("add_safmeds_score", {id: selected, date: currentDate, correct, incorrect, deckId, duration}).then(() => {
       snack.success("added safmeds score"); 
       getScores();
       correctInput.current!.value = ""
@@ -35,9 +38,13 @@ export default function ScoreTabs({ selected }: Props) {
     }).catch(e => snack.error(e.toString()))
   }
 
+  useEffect(() => {
+    invoke("all_decks").then(d => setDecks(d as Deck[])).catch(e => snack.error(e.toString()))
+  }, [])
+
   useEffect(() => {
     getScores()
-  }, [selected, currentTab])
+  }, [selected, currentTab, deckId])
 
 
   return (
@@ -52,6 +59,14 @@ export default function ScoreTabs({ selected }: Props) {
         {/* {currentTab === "writing" && <span>WritingContent</span>}
         {currentTab === "reading" && <span>ReadingContent</span>} */}
         <div id="controls" className="row center">
+          <select id="deck" value={deckId} onChange={e => setDeckId(e.target.value)}>
+            {decks.map(deck => <option key={deck.id} value={deck.id}>{deck.name}</option>)}
+          </select>
+          <select id="duration" value={duration} onChange={e => setDuration(Number.parseInt(e.target.value))}>
+            <option value={30}>30s</option>
+            <option value={60}>1 min</option>
+            <option value={120}>2 min</option>
+          </select>
           <input ref={correctInput} id="correct" type="number"></input>
           <input ref={incorrectInput} id="incorrect" type="number"></input>
           <input value={currentDate} id="date" type="date" onChange={e => setCurrentDate(e.target.value)}></input>
@@ -1 +1,15 @@
-export type Student = {id: string, first_names: string, last_name: string, date_of_birth: string}
\ No newline at end of file
+export type Student = {
+  id: string,
+  first_names: string,
+  last_name: string,
+  date_of_birth: string,
+  upn: string | null,
+  class: string | null,
+  notes: string,
+  start_date: string | null,
+  leave_date: string | null,
+  active: boolean,
+}
+
+// the details that can be edited apart from the name and date of birth
+export type StudentDetails = Pick<Student, "upn" | "class" | "notes" | "start_date" | "leave_date" | "active">
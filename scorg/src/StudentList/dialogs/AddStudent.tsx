@@ -4,11 +4,15 @@ import SnackbarContext from "../../snackbar-context"
 export default function AddStudentDialog({ showDialog, addStudent, closeModals }: Props) {
     const nameInput = useRef<HTMLInputElement>(null)
     const dobInput = useRef<HTMLInputElement>(null)
+    const upnInput = useRef<HTMLInputElement>(null)
+    const classInput = useRef<HTMLInputElement>(null)
     const snackCtx = useContext(SnackbarContext)
 
     function clearInputs() {
         nameInput.current!.value = ""
         dobInput.current!.value = ""
+        upnInput.current!.value = ""
+        classInput.current!.value = ""
     }
 
     useEffect(() => {
@@ -26,7 +30,7 @@ export default function AddStudentDialog({ showDialog, addStudent, closeModals }
             let splitName = addName.split(" ")
             let last_name = splitName.pop()
             let first_names = splitName.join(" ")
-            addStudent(first_names, last_name, addDob)
+            addStudent(first_names, last_name, addDob, { upn: upnInput.current!.value, class: classInput.current!.value })
             clearInputs()
             closeModals()
         }
@@ -49,6 +53,14 @@ export default function AddStudentDialog({ showDialog, addStudent, closeModals }
                 <label>Date of birth</label>
                 <input type="date" ref={dobInput} />
             </div>
+            <div className="row">
+                <label>UPN</label>
+                <input type="text" ref={upnInput} />
+            </div>
+            <div className="row">
+                <label>Class</label>
+                <input type="text" ref={classInput} />
+            </div>
             <div className="row">
                 <button className="button wide" onClick={handleAdd}>Add</button>
             </div>
@@ -1,18 +1,40 @@
 import { useEffect, useRef, useState } from "react"
-import { Student } from "../Student"
+import { Student, StudentDetails } from "../Student"
 
 export default function EditStudentDialog({showDialog, editStudent, closeModals, student}: Props) {
 
     const nameInput = useRef<HTMLInputElement>(null)
     const dobInput = useRef<HTMLInputElement>(null)
+    const upnInput = useRef<HTMLInputElement>(null)
+    const classInput = useRef<HTMLInputElement>(null)
+    const notesInput = useRef<HTMLTextAreaElement>(null)
+    const startInput = useRef<HTMLInputElement>(null)
+    const leaveInput = useRef<HTMLInputElement>(null)
+    const activeInput = useRef<HTMLInputElement>(null)
 
     function clearInputs() {
         nameInput.current!.value = ""
         dobInput.current!.value = ""
+        upnInput.current!.value = ""
+        classInput.current!.value = ""
+        notesInput.current!.value = ""
+        startInput.current!.value = ""
+        leaveInput.current!.value = ""
     }
 
+    // blank inputs clear the detail
+    const orNull = (value: string) => value.trim() === "" ? null : value.trim()
+
     function handleEdit() {
-        editStudent(student!.id, nameInput.current!.value, dobInput.current!.value)
+        let details: StudentDetails = {
+            upn: orNull(upnInput.current!.value),
+            class: orNull(classInput.current!.value),
+            notes: notesInput.current!.value,
+            start_date: orNull(startInput.current!.value),
+            leave_date: orNull(leaveInput.current!.value),
+            active: activeInput.current!.checked,
+        }
+        editStudent(student!.id, nameInput.current!.value, dobInput.current!.value, details)
         clearInputs()
         closeModals()
     }
@@ -20,6 +42,12 @@ export default function EditStudentDialog({showDialog, editStudent, closeModals,
     useEffect(() => {
         nameInput.current!.value = `${student!.first_names} ${student!.last_name}`
         dobInput.current!.value = student!.date_of_birth
+        upnInput.current!.value = student!.upn ?? ""
+        classInput.current!.value = student!.class ?? ""
+        notesInput.current!.value = student!.notes
+        startInput.current!.value = student!.start_date ?? ""
+        leaveInput.current!.value = student!.leave_date ?? ""
+        activeInput.current!.checked = student!.active
     }, [student])
 
     return (
@@ -39,6 +67,30 @@ export default function EditStudentDialog({showDialog, editStudent, closeModals,
                 <label>Date of birth</label>
                 <input type="date" ref={dobInput} />
             </div>
+            <div className="row">
+                <label>UPN</label>
+                <input type="text" ref={upnInput} />
+            </div>
+            <div className="row">
+                <label>Class</label>
+                <input type="text" ref={classInput} />
+            </div>
+            <div className="row">
+                <label>Started</label>
+                <input type="date" ref={startInput} />
+            </div>
+            <div className="row">
+                <label>Left</label>
+                <input type="date" ref={leaveInput} />
+            </div>
+            <div className="row">
+                <label>Active</label>
+                <input type="checkbox" ref={activeInput} />
+            </div>
+            <div className="row">
+                <label>Notes</label>
+                <textarea ref={notesInput} />
+            </div>
             <div className="row">
                 <button className="button wide" onClick={handleEdit}>Save</button>
             </div>
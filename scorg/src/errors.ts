@@ -0,0 +1,27 @@
+// Commands that return the library's Error send it as { kind, detail }, the
+// rest send a plain string.
+interface LibError {
+  kind: string
+  detail?: any
+}
+
+export function describeError(error: unknown): string {
+  if (typeof error === "string") {
+    return error
+  }
+  const { kind, detail } = error as LibError
+  switch (kind) {
+    case "Duplicate":
+      return detail.table === "student" ? "there's already a student with that name" : `that ${detail.table} already exists`
+    case "NotFound":
+      return `couldn't find ${detail}`
+    case "LoginFailed":
+      return "wrong username or password"
+    case "PermissionDenied":
+      return detail
+    case "Csv":
+      return `line ${detail.line ?? "?"}: ${describeError(detail.source)}`
+    default:
+      return detail === undefined ? kind : `${kind}: ${typeof detail === "string" ? detail : JSON.stringify(detail)}`
+  }
+}